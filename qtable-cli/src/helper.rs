@@ -0,0 +1,140 @@
+use std::sync::{Arc, Mutex};
+
+use qtable_client::Table;
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+
+const KEYWORDS: [&str; 37] = [
+    "SELECT", "INSERT", "INTO", "UPDATE", "DELETE", "FROM", "WHERE", "AND", "OR", "LIKE", "STARTS",
+    "ENDS", "WITH", "GROUP", "ORDER", "BY", "ASC", "DESC", "LIMIT", "OFFSET", "COUNT", "SUM",
+    "AVG", "MIN", "MAX", "CREATE", "DEFINE", "ALTER", "DROP", "DESCRIBE", "FORMAT", "USE", "BEGIN",
+    "COMMIT", "ROLLBACK", "COMPACT", "REINDEX",
+];
+
+/// ShellHelper completes keywords and the table and column names of the current database
+#[derive(Clone, Default)]
+pub struct ShellHelper {
+    names: Arc<Mutex<Vec<String>>>,
+}
+
+impl ShellHelper {
+    /// Replace the names offered for completion with the tables and columns of the definitions
+    pub fn set_tables(&self, tables: &[Table]) {
+        let mut names = vec![];
+        for table in tables {
+            names.push(table.table.clone());
+            names.extend(table.columns.keys().cloned());
+        }
+        names.sort();
+        names.dedup();
+        *self.names.lock().unwrap() = names;
+    }
+
+    fn candidates(&self, word: &str) -> Vec<String> {
+        let upper = word.to_uppercase();
+        let mut candidates = KEYWORDS
+            .iter()
+            .filter(|keyword| keyword.starts_with(&upper))
+            .map(|keyword| {
+                // Keep the case the user started typing with
+                if word.chars().all(|c| c.is_lowercase()) {
+                    keyword.to_lowercase()
+                } else {
+                    keyword.to_string()
+                }
+            })
+            .collect::<Vec<_>>();
+        candidates.extend(
+            self.names
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|name| name.starts_with(word))
+                .cloned(),
+        );
+        candidates
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &line[start..pos];
+        if word.is_empty() {
+            return Ok((start, vec![]));
+        }
+        let pairs = self
+            .candidates(word)
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::BTreeMap;
+
+    use qtable_client::Column;
+    use rustyline::history::DefaultHistory;
+
+    use super::*;
+
+    #[test]
+    fn test_complete_names() {
+        let helper = ShellHelper::default();
+        helper.set_tables(&[Table {
+            table: "user".to_string(),
+            columns: BTreeMap::from([
+                ("name".to_string(), Column::new("String")),
+                ("nick".to_string(), Column::new("String")),
+            ]),
+        }]);
+
+        let history = DefaultHistory::new();
+        let context = Context::new(&history);
+        let line = "SELECT user WHERE n";
+        let (start, pairs) = helper.complete(line, line.len(), &context).unwrap();
+        assert_eq!(start, line.len() - 1);
+        let replacements = pairs
+            .into_iter()
+            .map(|pair| pair.replacement)
+            .collect::<Vec<_>>();
+        assert_eq!(replacements, vec!["name", "nick"]);
+
+        let (_, pairs) = helper.complete("sel", 3, &context).unwrap();
+        assert_eq!(pairs[0].replacement, "select");
+        let (_, pairs) = helper.complete("SELECT us", 9, &context).unwrap();
+        assert!(pairs.iter().any(|pair| pair.replacement == "user"));
+    }
+}
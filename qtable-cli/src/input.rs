@@ -0,0 +1,136 @@
+use crate::render::OutputMode;
+
+/// Input is what the shell should do after a line was entered
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    /// The statement is not terminated with `;` yet
+    Incomplete,
+    /// A complete statement without its terminating `;`
+    Statement(String),
+    /// `USE <db>` selects the database statements are sent to
+    Use(String),
+    /// `\table` or `\json` switches the output mode
+    Mode(OutputMode),
+    Help,
+    Quit,
+    Empty,
+}
+
+/// InputBuffer collects lines until a statement is terminated with `;`.
+/// Shell commands (`USE`, `\json`, `\table`, `\q`, `help`) are only recognised on the first line.
+#[derive(Debug, Default)]
+pub struct InputBuffer {
+    statement: String,
+}
+
+impl InputBuffer {
+    /// True when a statement spans more than the lines entered so far
+    pub fn is_pending(&self) -> bool {
+        !self.statement.is_empty()
+    }
+
+    pub fn push(&mut self, line: &str) -> Input {
+        let trimmed = line.trim();
+        if !self.is_pending() {
+            if let Some(input) = shell_command(trimmed) {
+                return input;
+            }
+            if trimmed.is_empty() {
+                return Input::Empty;
+            }
+        }
+        if !self.statement.is_empty() {
+            self.statement.push('\n');
+        }
+        self.statement.push_str(line);
+
+        if terminated(&self.statement) {
+            let statement = std::mem::take(&mut self.statement);
+            let statement = statement.trim().trim_end_matches(';').trim_end();
+            return Input::Statement(statement.to_string());
+        }
+        Input::Incomplete
+    }
+}
+
+fn shell_command(line: &str) -> Option<Input> {
+    let line = line.trim_end_matches(';').trim();
+    let lowercase = line.to_lowercase();
+    match lowercase.as_str() {
+        "\\q" | "quit" | "exit" => return Some(Input::Quit),
+        "help" | "\\?" | "\\h" => return Some(Input::Help),
+        "\\json" => return Some(Input::Mode(OutputMode::Json)),
+        "\\table" => return Some(Input::Mode(OutputMode::Table)),
+        _ => {}
+    }
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some(keyword), Some(database), None) if keyword.eq_ignore_ascii_case("USE") => {
+            Some(Input::Use(database.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// A statement is terminated by a `;` at the end of the input that is not inside a string or a
+/// JSON document
+fn terminated(statement: &str) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut last = None;
+    for c in statement.chars() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => {}
+        }
+        if !c.is_whitespace() {
+            last = Some(c);
+        }
+    }
+    quote.is_none() && last == Some(';')
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_multi_line_statement() {
+        let mut buffer = InputBuffer::default();
+        assert_eq!(buffer.push("INSERT INTO user {"), Input::Incomplete);
+        assert_eq!(buffer.push(r#"  "name": "a;b""#), Input::Incomplete);
+        assert!(buffer.is_pending());
+        assert_eq!(
+            buffer.push("};"),
+            Input::Statement("INSERT INTO user {\n  \"name\": \"a;b\"\n}".to_string())
+        );
+        assert!(!buffer.is_pending());
+        assert_eq!(
+            buffer.push("SELECT user WHERE name = 'John';  "),
+            Input::Statement("SELECT user WHERE name = 'John'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_shell_commands() {
+        let mut buffer = InputBuffer::default();
+        assert_eq!(buffer.push("use xdr;"), Input::Use("xdr".to_string()));
+        assert_eq!(buffer.push("\\json"), Input::Mode(OutputMode::Json));
+        assert_eq!(buffer.push("   "), Input::Empty);
+        assert_eq!(buffer.push("exit"), Input::Quit);
+
+        // Inside a statement the words are part of the statement
+        assert_eq!(buffer.push("SELECT user WHERE"), Input::Incomplete);
+        assert_eq!(buffer.push("exit"), Input::Incomplete);
+    }
+}
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use clap::Parser;
+use qtable_client::{Client, ClientError, Describe, Statement, Table};
+use rustyline::{error::ReadlineError, history::FileHistory, Editor};
+use tokio::runtime::Runtime;
+
+use helper::ShellHelper;
+use input::{Input, InputBuffer};
+use render::{render, OutputMode};
+
+mod helper;
+mod input;
+mod render;
+
+const HISTORY_FILE: &str = ".qtable_history";
+const HELP: &str = "\
+Statements end with `;` and may span several lines.
+
+  USE <db>      send the following statements to <db>
+  \\table        print documents as an aligned table (default)
+  \\json         print documents as JSON
+  help          show this help
+  \\q, exit      leave the shell";
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Interactive shell for qtable", long_about = None)]
+struct Cmd {
+    /// Address of the qtable server
+    #[arg(short, long, default_value_t = String::from("127.0.0.1:8080"))]
+    address: String,
+    /// Database used until a `USE` statement selects another one
+    #[arg(short, long)]
+    database: Option<String>,
+    /// Seconds to wait for a response
+    #[arg(short, long, default_value_t = 30)]
+    timeout: u64,
+}
+
+struct Shell {
+    client: Client,
+    runtime: Runtime,
+    helper: ShellHelper,
+    database: Option<String>,
+    mode: OutputMode,
+}
+
+fn main() {
+    let args = Cmd::parse();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let client = Client::builder(&args.address)
+        .pool_size(1)
+        .request_timeout(Duration::from_secs(args.timeout))
+        .build();
+
+    let mut shell = Shell {
+        client,
+        runtime,
+        helper: ShellHelper::default(),
+        database: None,
+        mode: OutputMode::Table,
+    };
+    if let Some(database) = args.database {
+        shell.use_database(database);
+    }
+    if let Err(error) = shell.run() {
+        eprintln!("Error: {}", error);
+        std::process::exit(1);
+    }
+}
+
+impl Shell {
+    fn run(&mut self) -> rustyline::Result<()> {
+        let mut editor: Editor<ShellHelper, FileHistory> = Editor::new()?;
+        editor.set_helper(Some(self.helper.clone()));
+        let history = history_path();
+        if let Some(history) = &history {
+            // There is no history yet on the first run
+            let _ = editor.load_history(history);
+        }
+
+        let mut buffer = InputBuffer::default();
+        loop {
+            let prompt = match (&self.database, buffer.is_pending()) {
+                (_, true) => "     -> ".to_string(),
+                (Some(database), false) => format!("{}> ", database),
+                (None, false) => "qtable> ".to_string(),
+            };
+            let line = match editor.readline(&prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    buffer = InputBuffer::default();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(error) => return Err(error),
+            };
+
+            match buffer.push(&line) {
+                Input::Incomplete | Input::Empty => continue,
+                Input::Statement(statement) => {
+                    editor.add_history_entry(format!("{};", statement))?;
+                    self.execute(&statement);
+                }
+                Input::Use(database) => {
+                    editor.add_history_entry(line.trim())?;
+                    self.use_database(database);
+                }
+                Input::Mode(mode) => self.mode = mode,
+                Input::Help => println!("{}", HELP),
+                Input::Quit => break,
+            }
+        }
+
+        if let Some(history) = &history {
+            editor.save_history(history)?;
+        }
+        Ok(())
+    }
+
+    fn use_database(&mut self, database: String) {
+        self.database = Some(database);
+        self.refresh_completion();
+    }
+
+    fn execute(&mut self, statement: &str) {
+        let database = self.database.clone().unwrap_or_default();
+        match self
+            .runtime
+            .block_on(self.client.send(&database, statement))
+        {
+            Ok(documents) => println!("{}", render(&documents, self.mode)),
+            Err(ClientError::Server(error)) => {
+                eprintln!("Error [{}]: {}", error.kind_name(), error.message)
+            }
+            Err(error) => eprintln!("{}", error),
+        }
+
+        let keyword = statement
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_uppercase();
+        if matches!(keyword.as_str(), "DEFINE" | "ALTER" | "DROP") {
+            self.refresh_completion();
+        }
+    }
+
+    /// Load the table and column names of the current database for tab completion
+    fn refresh_completion(&mut self) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        let describe = Describe::all().command();
+        let tables = self
+            .runtime
+            .block_on(self.client.send(database, &describe))
+            .map(|documents| {
+                documents
+                    .into_iter()
+                    .filter_map(|document| serde_json::from_value::<Table>(document).ok())
+                    .collect::<Vec<_>>()
+            });
+        match tables {
+            Ok(tables) => self.helper.set_tables(&tables),
+            Err(ClientError::Server(error)) => {
+                eprintln!("Error [{}]: {}", error.kind_name(), error.message)
+            }
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+}
+
+fn history_path() -> Option<std::path::PathBuf> {
+    std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(HISTORY_FILE))
+}
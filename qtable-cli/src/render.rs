@@ -0,0 +1,137 @@
+use serde_json::Value;
+
+/// How the documents of a response are printed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    Table,
+    Json,
+}
+
+/// Render the documents in the output mode
+pub fn render(documents: &[Value], mode: OutputMode) -> String {
+    match mode {
+        OutputMode::Table => render_table(documents),
+        OutputMode::Json => {
+            serde_json::to_string_pretty(&Value::Array(documents.to_vec())).unwrap_or_default()
+        }
+    }
+}
+
+/// Render the documents as an aligned table, every attribute found in a document is a column
+pub fn render_table(documents: &[Value]) -> String {
+    let columns = columns(documents);
+    if columns.is_empty() {
+        return format!("({} rows)", documents.len());
+    }
+    let rows = documents
+        .iter()
+        .map(|document| {
+            columns
+                .iter()
+                .map(|column| cell(&document[column.as_str()]))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(column.chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut output = String::new();
+    output.push_str(&line(&columns, &widths));
+    output.push('\n');
+    output.push_str(
+        &widths
+            .iter()
+            .map(|width| "-".repeat(width + 2))
+            .collect::<Vec<_>>()
+            .join("+"),
+    );
+    output.push('\n');
+    for row in &rows {
+        output.push_str(&line(row, &widths));
+        output.push('\n');
+    }
+    let plural = if rows.len() == 1 { "row" } else { "rows" };
+    output.push_str(&format!("({} {})", rows.len(), plural));
+    output
+}
+
+/// Columns in the order they are first seen, `object_id` always comes first
+fn columns(documents: &[Value]) -> Vec<String> {
+    let mut columns: Vec<String> = vec![];
+    for document in documents {
+        if let Value::Object(document) = document {
+            for key in document.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+    }
+    if let Some(position) = columns.iter().position(|column| column == "object_id") {
+        let object_id = columns.remove(position);
+        columns.insert(0, object_id);
+    }
+    columns
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn line(cells: &[String], widths: &[usize]) -> String {
+    cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!(" {:<width$} ", cell, width = width))
+        .collect::<Vec<_>>()
+        .join("|")
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let documents = vec![
+            json!({ "object_id": "1", "name": "John", "age": 30 }),
+            json!({ "object_id": "20", "name": "Jo", "tags": ["a"] }),
+        ];
+        let expected = [
+            " object_id | age | name | tags",
+            "-----------+-----+------+-------",
+            " 1         | 30  | John |",
+            " 20        |     | Jo   | [\"a\"]",
+            "(2 rows)",
+        ]
+        .join("\n");
+        assert_eq!(render_table(&documents), expected);
+        assert_eq!(render_table(&[]), "(0 rows)");
+    }
+
+    #[test]
+    fn test_render_json() {
+        let documents = vec![json!({ "object_id": "1" })];
+        let rendered = render(&documents, OutputMode::Json);
+        let value: Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(value, json!([{ "object_id": "1" }]));
+    }
+}
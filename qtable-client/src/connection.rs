@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use log::debug;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::error::{ClientError, ServerError};
+
+const PROTOCOL_VERSION: u8 = 1;
+const HEADER_LENGTH: usize = 1 + 8; // version + request id
+const MAX_FRAME_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Connection is a single TCP connection to the server speaking the framed protocol.
+/// Responses are negotiated as JSON when the connection is opened.
+pub struct Connection {
+    stream: TcpStream,
+    next_request_id: u64,
+    request_timeout: Duration,
+}
+
+impl Connection {
+    /// Open a connection and switch it to JSON responses
+    pub async fn connect(
+        address: &str,
+        connect_timeout: Duration,
+        request_timeout: Duration,
+    ) -> Result<Connection, ClientError> {
+        let stream = timeout(connect_timeout, TcpStream::connect(address))
+            .await
+            .map_err(|_| ClientError::Timeout(format!("Connecting to {}", address)))??;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            stream,
+            next_request_id: 0,
+            request_timeout,
+        };
+        connection.send("", "FORMAT JSON").await?;
+        debug!("Connected to {}", address);
+        Ok(connection)
+    }
+
+    /// Send the command for the database and wait for its documents
+    pub async fn send(&mut self, database: &str, command: &str) -> Result<Vec<Value>, ClientError> {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let request_timeout = self.request_timeout;
+        let response = timeout(
+            request_timeout,
+            self.round_trip(request_id, database, command),
+        )
+        .await
+        .map_err(|_| ClientError::Timeout(format!("Waiting for the response to {}", command)))??;
+        decode_response(&response)
+    }
+
+    async fn round_trip(
+        &mut self,
+        request_id: u64,
+        database: &str,
+        command: &str,
+    ) -> Result<Vec<u8>, ClientError> {
+        let database = database.as_bytes();
+        let database_length = u16::try_from(database.len()).map_err(|_| {
+            ClientError::Protocol(format!(
+                "Database name of {} bytes exceeds the maximum of {} bytes",
+                database.len(),
+                u16::MAX
+            ))
+        })?;
+        let mut payload = Vec::with_capacity(2 + database.len() + command.len());
+        payload.extend_from_slice(&database_length.to_be_bytes());
+        payload.extend_from_slice(database);
+        payload.extend_from_slice(command.as_bytes());
+
+        let mut frame = Vec::with_capacity(4 + HEADER_LENGTH + payload.len());
+        frame.extend_from_slice(&((HEADER_LENGTH + payload.len()) as u32).to_be_bytes());
+        frame.push(PROTOCOL_VERSION);
+        frame.extend_from_slice(&request_id.to_be_bytes());
+        frame.extend_from_slice(&payload);
+        self.stream.write_all(&frame).await?;
+
+        let mut length = [0; 4];
+        self.stream.read_exact(&mut length).await?;
+        let length = u32::from_be_bytes(length) as usize;
+        if !(HEADER_LENGTH..=MAX_FRAME_LENGTH).contains(&length) {
+            return Err(ClientError::Protocol(format!(
+                "Invalid frame length {}",
+                length
+            )));
+        }
+        let mut body = vec![0; length];
+        self.stream.read_exact(&mut body).await?;
+        if body[0] != PROTOCOL_VERSION {
+            return Err(ClientError::Protocol(format!(
+                "Unsupported protocol version {}",
+                body[0]
+            )));
+        }
+        let mut response_id = [0; 8];
+        response_id.copy_from_slice(&body[1..HEADER_LENGTH]);
+        let response_id = u64::from_be_bytes(response_id);
+        if response_id != request_id {
+            return Err(ClientError::Protocol(format!(
+                "Expected the response to request {} but got {}",
+                request_id, response_id
+            )));
+        }
+        Ok(body.split_off(HEADER_LENGTH))
+    }
+}
+
+fn decode_response(payload: &[u8]) -> Result<Vec<Value>, ClientError> {
+    let mut response: Value = serde_json::from_slice(payload)
+        .map_err(|e| ClientError::Protocol(format!("Response is not JSON: {}", e)))?;
+    match response["status"].as_str() {
+        Some("ok") => match response["data"].take() {
+            Value::Array(documents) => Ok(documents),
+            _ => Err(ClientError::Protocol(
+                "Response is missing the data".to_string(),
+            )),
+        },
+        Some("error") => {
+            let error = serde_json::from_value::<ServerError>(response["error"].take())
+                .map_err(|e| ClientError::Protocol(format!("Invalid error response: {}", e)))?;
+            Err(ClientError::Server(error))
+        }
+        _ => Err(ClientError::Protocol(
+            "Response is missing the status".to_string(),
+        )),
+    }
+}
@@ -0,0 +1,58 @@
+use std::fmt::{Display, Formatter};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Error type for client operations
+#[derive(Debug)]
+pub enum ClientError {
+    /// Error reading or writing the connection
+    Io(std::io::Error),
+    /// The connection or the request did not complete in time
+    Timeout(String),
+    /// The server sent a frame the client could not understand
+    Protocol(String),
+    /// The server rejected the request
+    Server(ServerError),
+    /// The documents in the response could not be decoded into the requested type
+    Decode(serde_json::Error),
+}
+
+/// ServerError is the error document sent by the server when a request fails
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ServerError {
+    /// Kind of the error, e.g. `"TableNotFound"` or `{"Syntax": "InvalidValue"}`
+    pub kind: Value,
+    pub message: String,
+}
+
+impl ServerError {
+    /// Name of the error kind without its details, e.g. `"Syntax"` for `{"Syntax": "InvalidValue"}`
+    pub fn kind_name(&self) -> &str {
+        match &self.kind {
+            Value::String(kind) => kind,
+            Value::Object(kind) => kind.keys().next().map(|k| k.as_str()).unwrap_or_default(),
+            _ => "",
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "IO Error: {}", e),
+            ClientError::Timeout(e) => write!(f, "Timeout: {}", e),
+            ClientError::Protocol(e) => write!(f, "Protocol Error: {}", e),
+            ClientError::Server(e) => write!(f, "Server Error: {} {}", e.kind, e.message),
+            ClientError::Decode(e) => write!(f, "Decode Error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<std::io::Error> for ClientError {
+    fn from(error: std::io::Error) -> Self {
+        ClientError::Io(error)
+    }
+}
@@ -0,0 +1,281 @@
+//! Async client for the qtable server.
+//!
+//! ```ignore
+//! let client = Client::builder("127.0.0.1:8080").pool_size(4).build();
+//! client.create_database("xdr").await?;
+//! let db = client.database("xdr");
+//! db.execute(&Define::table("user").column("name", Column::new("String").indexed())).await?;
+//! db.execute(&Insert::into("user", json!({"id": "1", "name": "John"}))).await?;
+//! let users: Vec<User> = db.query(&Select::from("user").filter(Filter::eq("name", "John"))).await?;
+//! ```
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+mod connection;
+mod error;
+mod pool;
+mod statement;
+
+pub use error::{ClientError, ServerError};
+pub use statement::{
+    Column, Define, Delete, Describe, DropTable, Filter, Insert, Select, Statement, Table, Update,
+};
+
+use pool::Pool;
+
+const DEFAULT_POOL_SIZE: usize = 8;
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Client sends commands to a qtable server over a pool of connections
+pub struct Client {
+    pool: Pool,
+}
+
+/// ClientBuilder configures the pool and the timeouts of a `Client`
+pub struct ClientBuilder {
+    address: String,
+    pool_size: usize,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+}
+
+impl ClientBuilder {
+    /// Maximum number of connections open at once
+    pub fn pool_size(mut self, pool_size: usize) -> ClientBuilder {
+        self.pool_size = pool_size.max(1);
+        self
+    }
+
+    /// Time allowed to open a connection or to wait for a free one
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> ClientBuilder {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Time allowed for a request to be answered
+    pub fn request_timeout(mut self, request_timeout: Duration) -> ClientBuilder {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Build the client, connections are opened lazily on the first request
+    pub fn build(self) -> Client {
+        Client {
+            pool: Pool::new(
+                self.address,
+                self.pool_size,
+                self.connect_timeout,
+                self.request_timeout,
+            ),
+        }
+    }
+}
+
+impl Client {
+    pub fn builder(address: &str) -> ClientBuilder {
+        ClientBuilder {
+            address: address.to_string(),
+            pool_size: DEFAULT_POOL_SIZE,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Build a client with the default settings and check the server is reachable
+    pub async fn connect(address: &str) -> Result<Client, ClientError> {
+        let client = Client::builder(address).build();
+        drop(client.pool.get().await?);
+        Ok(client)
+    }
+
+    /// `CREATE <database>`
+    pub async fn create_database(&self, database: &str) -> Result<(), ClientError> {
+        self.send("", &format!("CREATE {}", database)).await?;
+        Ok(())
+    }
+
+    /// `DROP DATABASE <database>`, removes the database with all its tables
+    pub async fn drop_database(&self, database: &str) -> Result<(), ClientError> {
+        self.send("", &format!("DROP DATABASE {}", database))
+            .await?;
+        Ok(())
+    }
+
+    /// Handle to run statements against the database
+    pub fn database<'a>(&'a self, database: &str) -> Database<'a> {
+        Database {
+            client: self,
+            name: database.to_string(),
+        }
+    }
+
+    /// Number of open connections waiting in the pool
+    pub fn idle_connections(&self) -> usize {
+        self.pool.idle()
+    }
+
+    /// Send a raw command for the database and return the documents of the response
+    pub async fn send(&self, database: &str, command: &str) -> Result<Vec<Value>, ClientError> {
+        let mut connection = self.pool.get().await?;
+        connection.send(database, command).await
+    }
+}
+
+/// Database runs statements against one database of the server
+pub struct Database<'a> {
+    client: &'a Client,
+    name: String,
+}
+
+impl Database<'_> {
+    /// Run the statement and return the documents of the response
+    pub async fn execute(&self, statement: &impl Statement) -> Result<Vec<Value>, ClientError> {
+        self.client.send(&self.name, &statement.command()).await
+    }
+
+    /// Run the statement and decode the documents of the response into `T`
+    pub async fn query<T: DeserializeOwned>(
+        &self,
+        statement: &impl Statement,
+    ) -> Result<Vec<T>, ClientError> {
+        self.execute(statement)
+            .await?
+            .into_iter()
+            .map(|document| serde_json::from_value(document).map_err(ClientError::Decode))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{collections::HashMap, sync::Arc};
+
+    use qtable::network::server::Server;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use tempfile::{Builder, TempDir};
+    use tokio::{net::TcpListener, sync::RwLock};
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct User {
+        id: String,
+        name: String,
+        age: u32,
+    }
+
+    async fn start_server() -> (String, TempDir) {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let data_path = dir.path().to_str().unwrap().to_string();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let databases = Arc::new(RwLock::new(HashMap::new()));
+        tokio::spawn(Server::serve(listener, data_path, databases));
+        (address, dir)
+    }
+
+    #[tokio::test]
+    async fn test_client_statements() {
+        let (address, _dir) = start_server().await;
+        let client = Client::connect(&address).await.unwrap();
+        client.create_database("xdr").await.unwrap();
+
+        let db = client.database("xdr");
+        let define = Define::table("user")
+            .column("name", Column::new("String").indexed())
+            .column("age", Column::new("Number").indexed().optional());
+        db.execute(&define).await.unwrap();
+        let tables: Vec<Table> = db.query(&Describe::all()).await.unwrap();
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].table, "user");
+        assert_eq!(
+            tables[0].columns["age"],
+            Column::new("Number").indexed().optional()
+        );
+
+        let john = User {
+            id: "1".to_string(),
+            name: "John".to_string(),
+            age: 30,
+        };
+        db.execute(&Insert::value("user", &john).unwrap())
+            .await
+            .unwrap();
+        db.execute(&Insert::into(
+            "user",
+            json!({ "id": "2", "name": "Jane", "age": 25 }),
+        ))
+        .await
+        .unwrap();
+
+        let select = Select::from("user").filter(Filter::eq("name", "John"));
+        let users: Vec<User> = db.query(&select).await.unwrap();
+        assert_eq!(users, vec![john]);
+
+        let update = Update::table("user", json!({ "name": "John", "age": 31 }))
+            .filter(Filter::eq("name", "John"));
+        db.execute(&update).await.unwrap();
+        let users: Vec<User> = db.query(&select).await.unwrap();
+        assert_eq!(users[0].age, 31);
+
+        db.execute(&Delete::from("user").filter(Filter::eq("name", "John")))
+            .await
+            .unwrap();
+        assert!(db.execute(&select).await.unwrap().is_empty());
+
+        db.execute(&DropTable::table("user")).await.unwrap();
+        assert!(db
+            .query::<Table>(&Describe::all())
+            .await
+            .unwrap()
+            .is_empty());
+        client.drop_database("xdr").await.unwrap();
+
+        match client.database("abc").execute(&select).await {
+            Err(ClientError::Server(error)) => assert_eq!(error.kind_name(), "DatabaseNotFound"),
+            other => panic!("Expected a server error but got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_pool() {
+        let (address, _dir) = start_server().await;
+        let client = Arc::new(Client::builder(&address).pool_size(2).build());
+        client.create_database("xdr").await.unwrap();
+
+        let requests = (0..8).map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move { client.send("", "CREATE xdr").await })
+        });
+        for request in requests {
+            let result = request.await.unwrap();
+            assert!(matches!(result, Err(ClientError::Server(_))));
+        }
+        // Server errors keep the connection usable so it goes back to the pool
+        assert!(client.idle_connections() <= 2);
+        assert!(client.idle_connections() >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        // A listener that never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let client = Client::builder(&address)
+            .request_timeout(Duration::from_millis(100))
+            .build();
+        match client.send("", "CREATE xdr").await {
+            Err(ClientError::Timeout(_)) => {}
+            other => panic!("Expected a timeout but got {:?}", other),
+        }
+        assert_eq!(client.idle_connections(), 0);
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::debug;
+use serde_json::Value;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::timeout,
+};
+
+use crate::{connection::Connection, error::ClientError};
+
+/// Pool keeps idle connections around and caps the number of connections open at once
+pub struct Pool {
+    address: String,
+    idle: Mutex<Vec<Connection>>,
+    permits: Arc<Semaphore>,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+}
+
+/// PooledConnection is a connection checked out of the pool, it goes back to the pool when the
+/// request completes and is dropped when the connection broke
+pub struct PooledConnection<'a> {
+    pool: &'a Pool,
+    connection: Option<Connection>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Pool {
+    pub fn new(
+        address: String,
+        size: usize,
+        connect_timeout: Duration,
+        request_timeout: Duration,
+    ) -> Pool {
+        Pool {
+            address,
+            idle: Mutex::new(vec![]),
+            permits: Arc::new(Semaphore::new(size)),
+            connect_timeout,
+            request_timeout,
+        }
+    }
+
+    /// Check out an idle connection or open a new one, waits at most the connect timeout for a
+    /// free slot when all connections are busy
+    pub async fn get(&self) -> Result<PooledConnection<'_>, ClientError> {
+        let permit = timeout(self.connect_timeout, self.permits.clone().acquire_owned())
+            .await
+            .map_err(|_| ClientError::Timeout("Waiting for a free connection".to_string()))?
+            .map_err(|_| ClientError::Protocol("Connection pool is closed".to_string()))?;
+
+        let idle = self.idle.lock().unwrap().pop();
+        let connection = match idle {
+            Some(connection) => connection,
+            None => {
+                debug!("Opening a new connection to {}", self.address);
+                Connection::connect(&self.address, self.connect_timeout, self.request_timeout)
+                    .await?
+            }
+        };
+        Ok(PooledConnection {
+            pool: self,
+            connection: Some(connection),
+            _permit: permit,
+        })
+    }
+
+    /// Number of idle connections kept by the pool
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+impl PooledConnection<'_> {
+    pub async fn send(&mut self, database: &str, command: &str) -> Result<Vec<Value>, ClientError> {
+        let connection = match self.connection.as_mut() {
+            Some(connection) => connection,
+            None => {
+                return Err(ClientError::Protocol(
+                    "Connection was closed after an earlier error".to_string(),
+                ))
+            }
+        };
+        let result = connection.send(database, command).await;
+        match &result {
+            Ok(_) | Err(ClientError::Server(_)) | Err(ClientError::Decode(_)) => {}
+            // The stream may be left in the middle of a frame, never reuse it
+            Err(_) => self.connection = None,
+        }
+        result
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.idle.lock().unwrap().push(connection);
+        }
+    }
+}
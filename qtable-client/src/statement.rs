@@ -0,0 +1,484 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::error::ClientError;
+
+/// Statement is a command that can be sent to a database
+pub trait Statement {
+    /// The command text sent to the server
+    fn command(&self) -> String;
+}
+
+/// Column definition used by `Define` and returned by `Describe`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Column {
+    #[serde(rename = "type")]
+    pub data_type: String,
+    pub indexed: bool,
+    pub optional: bool,
+    #[serde(default)]
+    pub unique: bool,
+}
+
+impl Column {
+    /// A mandatory, not indexed column of the given type
+    pub fn new(data_type: &str) -> Column {
+        Column {
+            data_type: data_type.to_string(),
+            indexed: false,
+            optional: false,
+            unique: false,
+        }
+    }
+
+    pub fn indexed(mut self) -> Column {
+        self.indexed = true;
+        self
+    }
+
+    pub fn optional(mut self) -> Column {
+        self.optional = true;
+        self
+    }
+
+    /// An indexed column that no two records hold the same value in
+    pub fn unique(mut self) -> Column {
+        self.indexed = true;
+        self.unique = true;
+        self
+    }
+}
+
+/// `DEFINE <table> {...}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Define {
+    table: String,
+    columns: Vec<(String, Column)>,
+}
+
+impl Define {
+    pub fn table(table: &str) -> Define {
+        Define {
+            table: table.to_string(),
+            columns: vec![],
+        }
+    }
+
+    pub fn column(mut self, name: &str, column: Column) -> Define {
+        self.columns.push((name.to_string(), column));
+        self
+    }
+}
+
+impl Statement for Define {
+    fn command(&self) -> String {
+        let columns = self
+            .columns
+            .iter()
+            .map(|(name, column)| {
+                let mut definition = json!({
+                    "type": column.data_type,
+                    "indexed": column.indexed,
+                    "optional": column.optional,
+                });
+                if column.unique {
+                    definition["unique"] = Value::Bool(true);
+                }
+                (name.clone(), definition)
+            })
+            .collect::<Map<_, _>>();
+        format!("DEFINE {} {}", self.table, Value::Object(columns))
+    }
+}
+
+/// `DROP TABLE <table>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropTable {
+    table: String,
+}
+
+impl DropTable {
+    pub fn table(table: &str) -> DropTable {
+        DropTable {
+            table: table.to_string(),
+        }
+    }
+}
+
+impl Statement for DropTable {
+    fn command(&self) -> String {
+        format!("DROP TABLE {}", self.table)
+    }
+}
+
+/// `DESCRIBE [<table>]`, decode the documents of the response with `Table`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Describe {
+    table: Option<String>,
+}
+
+/// Definition of a table returned by `Describe`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Table {
+    pub table: String,
+    pub columns: BTreeMap<String, Column>,
+}
+
+impl Describe {
+    /// Describe every table of the database
+    pub fn all() -> Describe {
+        Describe { table: None }
+    }
+
+    pub fn table(table: &str) -> Describe {
+        Describe {
+            table: Some(table.to_string()),
+        }
+    }
+}
+
+impl Statement for Describe {
+    fn command(&self) -> String {
+        match &self.table {
+            Some(table) => format!("DESCRIBE {}", table),
+            None => "DESCRIBE".to_string(),
+        }
+    }
+}
+
+/// `INSERT INTO <table> {...}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    table: String,
+    document: Value,
+}
+
+impl Insert {
+    pub fn into(table: &str, document: Value) -> Insert {
+        Insert {
+            table: table.to_string(),
+            document,
+        }
+    }
+
+    /// Insert any serializable value, it must serialize into a JSON object with an `id`
+    pub fn value<T: Serialize>(table: &str, value: &T) -> Result<Insert, ClientError> {
+        let document = serde_json::to_value(value).map_err(ClientError::Decode)?;
+        Ok(Insert::into(table, document))
+    }
+}
+
+impl Statement for Insert {
+    fn command(&self) -> String {
+        format!("INSERT INTO {} {}", self.table, self.document)
+    }
+}
+
+/// `SELECT [<fields> FROM] <table> [WHERE ...] [GROUP BY <field>] [ORDER BY <field> ASC|DESC]
+/// [LIMIT n] [OFFSET m]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    table: String,
+    columns: Vec<String>,
+    filter: Option<Filter>,
+    group_by: Option<String>,
+    order_by: Option<(String, bool)>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+impl Select {
+    pub fn from(table: &str) -> Select {
+        Select {
+            table: table.to_string(),
+            columns: vec![],
+            filter: None,
+            group_by: None,
+            order_by: None,
+            limit: None,
+            offset: None,
+        }
+    }
+
+    /// Sort the documents by the field in ascending order
+    pub fn order_by(mut self, field: &str) -> Select {
+        self.order_by = Some((field.to_string(), false));
+        self
+    }
+
+    /// Sort the documents by the field in descending order
+    pub fn order_by_desc(mut self, field: &str) -> Select {
+        self.order_by = Some((field.to_string(), true));
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Select {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Select {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Return only these fields, nested fields are separated with dots. Aggregates such as
+    /// `COUNT(*)` or `SUM(age)` are listed here too
+    pub fn columns(mut self, columns: &[&str]) -> Select {
+        self.columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Compute the aggregates once per value of the field
+    pub fn group_by(mut self, field: &str) -> Select {
+        self.group_by = Some(field.to_string());
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Select {
+        self.filter = Some(filter);
+        self
+    }
+}
+
+impl Statement for Select {
+    fn command(&self) -> String {
+        let columns = if self.columns.is_empty() {
+            String::new()
+        } else {
+            format!("{} FROM ", self.columns.join(", "))
+        };
+        let mut command = format!(
+            "SELECT {}{}{}",
+            columns,
+            self.table,
+            where_clause(&self.filter)
+        );
+        if let Some(field) = &self.group_by {
+            command.push_str(&format!(" GROUP BY {}", field));
+        }
+        if let Some((field, descending)) = &self.order_by {
+            let direction = if *descending { "DESC" } else { "ASC" };
+            command.push_str(&format!(" ORDER BY {} {}", field, direction));
+        }
+        if let Some(limit) = self.limit {
+            command.push_str(&format!(" LIMIT {}", limit));
+        }
+        if let Some(offset) = self.offset {
+            command.push_str(&format!(" OFFSET {}", offset));
+        }
+        command
+    }
+}
+
+/// `UPDATE <table> {...} WHERE ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    table: String,
+    document: Value,
+    filter: Option<Filter>,
+}
+
+impl Update {
+    pub fn table(table: &str, document: Value) -> Update {
+        Update {
+            table: table.to_string(),
+            document,
+            filter: None,
+        }
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Update {
+        self.filter = Some(filter);
+        self
+    }
+}
+
+impl Statement for Update {
+    fn command(&self) -> String {
+        format!(
+            "UPDATE {} {}{}",
+            self.table,
+            self.document,
+            where_clause(&self.filter)
+        )
+    }
+}
+
+/// `DELETE FROM <table> WHERE ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    table: String,
+    filter: Option<Filter>,
+}
+
+impl Delete {
+    pub fn from(table: &str) -> Delete {
+        Delete {
+            table: table.to_string(),
+            filter: None,
+        }
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Delete {
+        self.filter = Some(filter);
+        self
+    }
+}
+
+impl Statement for Delete {
+    fn command(&self) -> String {
+        format!("DELETE FROM {}{}", self.table, where_clause(&self.filter))
+    }
+}
+
+fn where_clause(filter: &Option<Filter>) -> String {
+    match filter {
+        Some(filter) => format!(" WHERE {}", filter),
+        None => String::new(),
+    }
+}
+
+/// Filter mirrors the conditions of the `WHERE` clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Equal(String, String),
+    GreaterThan(String, String),
+    GreaterThanOrEqual(String, String),
+    LessThan(String, String),
+    LessThanOrEqual(String, String),
+    StartsWith(String, String),
+    EndsWith(String, String),
+    Contains(String, String),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    pub fn eq(field: &str, value: impl Display) -> Filter {
+        Filter::Equal(field.to_string(), value.to_string())
+    }
+
+    pub fn gt(field: &str, value: impl Display) -> Filter {
+        Filter::GreaterThan(field.to_string(), value.to_string())
+    }
+
+    pub fn ge(field: &str, value: impl Display) -> Filter {
+        Filter::GreaterThanOrEqual(field.to_string(), value.to_string())
+    }
+
+    pub fn lt(field: &str, value: impl Display) -> Filter {
+        Filter::LessThan(field.to_string(), value.to_string())
+    }
+
+    pub fn le(field: &str, value: impl Display) -> Filter {
+        Filter::LessThanOrEqual(field.to_string(), value.to_string())
+    }
+
+    pub fn starts_with(field: &str, value: impl Display) -> Filter {
+        Filter::StartsWith(field.to_string(), value.to_string())
+    }
+
+    pub fn ends_with(field: &str, value: impl Display) -> Filter {
+        Filter::EndsWith(field.to_string(), value.to_string())
+    }
+
+    pub fn contains(field: &str, value: impl Display) -> Filter {
+        Filter::Contains(field.to_string(), value.to_string())
+    }
+
+    pub fn and(self, other: Filter) -> Filter {
+        Filter::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Filter) -> Filter {
+        Filter::Or(Box::new(self), Box::new(other))
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Filter::Equal(field, value) => write!(f, "{} = '{}'", field, value),
+            Filter::GreaterThan(field, value) => write!(f, "{} > '{}'", field, value),
+            Filter::GreaterThanOrEqual(field, value) => write!(f, "{} >= '{}'", field, value),
+            Filter::LessThan(field, value) => write!(f, "{} < '{}'", field, value),
+            Filter::LessThanOrEqual(field, value) => write!(f, "{} <= '{}'", field, value),
+            Filter::StartsWith(field, value) => write!(f, "{} STARTS WITH '{}'", field, value),
+            Filter::EndsWith(field, value) => write!(f, "{} ENDS WITH '{}'", field, value),
+            Filter::Contains(field, value) => write!(f, "{} LIKE '{}'", field, value),
+            Filter::And(left, right) => write!(f, "({} AND {})", left, right),
+            Filter::Or(left, right) => write!(f, "({} OR {})", left, right),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_statement_commands() {
+        let define = Define::table("user").column("name", Column::new("String").indexed());
+        assert_eq!(
+            define.command(),
+            r#"DEFINE user {"name":{"indexed":true,"optional":false,"type":"String"}}"#
+        );
+        let define = Define::table("user").column("email", Column::new("String").unique());
+        assert_eq!(
+            define.command(),
+            r#"DEFINE user {"email":{"indexed":true,"optional":false,"type":"String","unique":true}}"#
+        );
+
+        let insert = Insert::into("user", json!({ "id": "1" }));
+        assert_eq!(insert.command(), r#"INSERT INTO user {"id":"1"}"#);
+
+        let filter = Filter::eq("name", "John").and(Filter::ge("age", 30).or(Filter::lt("age", 5)));
+        let select = Select::from("user").filter(filter.clone());
+        assert_eq!(
+            select.command(),
+            "SELECT user WHERE (name = 'John' AND (age >= '30' OR age < '5'))"
+        );
+        let select = Select::from("user")
+            .columns(&["name", "address.city"])
+            .filter(Filter::eq("name", "John"));
+        assert_eq!(
+            select.command(),
+            "SELECT name, address.city FROM user WHERE name = 'John'"
+        );
+        let select = Select::from("user")
+            .filter(Filter::eq("name", "John"))
+            .order_by_desc("age")
+            .limit(10)
+            .offset(20);
+        assert_eq!(
+            select.command(),
+            "SELECT user WHERE name = 'John' ORDER BY age DESC LIMIT 10 OFFSET 20"
+        );
+        let select = Select::from("user")
+            .columns(&["city", "COUNT(*)"])
+            .group_by("city")
+            .order_by_desc("count");
+        assert_eq!(
+            select.command(),
+            "SELECT city, COUNT(*) FROM user GROUP BY city ORDER BY count DESC"
+        );
+
+        let update = Update::table("user", json!({ "age": 31 })).filter(filter.clone());
+        assert!(update
+            .command()
+            .starts_with(r#"UPDATE user {"age":31} WHERE ("#));
+
+        let delete = Delete::from("user").filter(Filter::starts_with("name", "Jo"));
+        assert_eq!(
+            delete.command(),
+            "DELETE FROM user WHERE name STARTS WITH 'Jo'"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use crate::parser::{Aggregate, AggregateFunction, Data, DataObject, InsertData, Number};
+
+/// Compute the aggregates over the records, one row per group. Rows are objects holding the
+/// group field, when there is one, and the aggregates under their `Aggregate::name`.
+/// Records without the group field are grouped under `null`.
+pub fn aggregate(
+    records: &[InsertData],
+    aggregates: &[Aggregate],
+    group_by: Option<&str>,
+) -> Vec<DataObject> {
+    let mut groups: Vec<(DataObject, Vec<&InsertData>)> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for record in records {
+        let key = match group_by {
+            Some(field) => record
+                .data
+                .get_path(field)
+                .cloned()
+                .unwrap_or(DataObject::Null),
+            None => DataObject::Null,
+        };
+        // Values of different types may print the same, e.g. the string "1" and the number 1
+        let id = format!("{:?}", key);
+        match positions.get(&id) {
+            Some(position) => groups[*position].1.push(record),
+            None => {
+                positions.insert(id, groups.len());
+                groups.push((key, vec![record]));
+            }
+        }
+    }
+    // Without GROUP BY there is a single row even when no record matched
+    if group_by.is_none() && groups.is_empty() {
+        groups.push((DataObject::Null, vec![]));
+    }
+    groups.sort_by(|(a, _), (b, _)| a.compare(b));
+
+    groups
+        .into_iter()
+        .map(|(key, records)| {
+            let mut row = vec![];
+            if let Some(field) = group_by {
+                row.push(Data {
+                    key: field.to_string(),
+                    value: key,
+                });
+            }
+            for aggregate in aggregates {
+                row.push(Data {
+                    key: aggregate.name(),
+                    value: compute(aggregate, &records),
+                });
+            }
+            DataObject::Object(row)
+        })
+        .collect()
+}
+
+/// COUNT(*) counts the records, the other functions skip records without the field or with a
+/// null value. SUM and AVG only consider numbers and are null when there are none.
+fn compute(aggregate: &Aggregate, records: &[&InsertData]) -> DataObject {
+    let field = match &aggregate.field {
+        Some(field) => field,
+        None => return count(records.len()),
+    };
+    let values = records
+        .iter()
+        .filter_map(|record| record.data.get_path(field))
+        .filter(|value| **value != DataObject::Null)
+        .collect::<Vec<_>>();
+    match aggregate.function {
+        AggregateFunction::Count => count(values.len()),
+        AggregateFunction::Sum => sum(&values).unwrap_or(DataObject::Null),
+        AggregateFunction::Avg => {
+            let numbers = numbers(&values);
+            if numbers.is_empty() {
+                return DataObject::Null;
+            }
+            let total = numbers.iter().map(|number| number.as_f64()).sum::<f64>();
+            DataObject::Number(Number::Float(total / numbers.len() as f64))
+        }
+        AggregateFunction::Min => values
+            .into_iter()
+            .min_by(|a, b| a.compare(b))
+            .cloned()
+            .unwrap_or(DataObject::Null),
+        AggregateFunction::Max => values
+            .into_iter()
+            .max_by(|a, b| a.compare(b))
+            .cloned()
+            .unwrap_or(DataObject::Null),
+    }
+}
+
+fn count(count: usize) -> DataObject {
+    DataObject::Number(Number::Int(count as i64))
+}
+
+fn numbers<'a>(values: &[&'a DataObject]) -> Vec<&'a Number> {
+    values
+        .iter()
+        .filter_map(|value| match value {
+            DataObject::Number(number) => Some(number),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Sum of the numbers, stays an integer while every number is an integer and the sum fits
+fn sum(values: &[&DataObject]) -> Option<DataObject> {
+    let numbers = numbers(values);
+    if numbers.is_empty() {
+        return None;
+    }
+    let integers = numbers
+        .iter()
+        .map(|number| match number {
+            Number::Int(value) => Some(*value),
+            Number::Float(_) => None,
+        })
+        .try_fold(0i64, |total, value| total.checked_add(value?));
+    match integers {
+        Some(total) => Some(DataObject::Number(Number::Int(total))),
+        None => Some(DataObject::Number(Number::Float(
+            numbers.iter().map(|number| number.as_f64()).sum(),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::parser::parse_json_value;
+
+    fn record(object_id: &str, document: serde_json::Value) -> InsertData {
+        let data = parse_json_value(document).unwrap();
+        InsertData {
+            object_id: object_id.to_string(),
+            table: "user".to_string(),
+            data,
+            active: true,
+        }
+    }
+
+    fn function(function: AggregateFunction, field: Option<&str>) -> Aggregate {
+        Aggregate {
+            function,
+            field: field.map(|field| field.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let records = vec![
+            record("1", json!({ "city": "Colombo", "age": 30 })),
+            record("2", json!({ "city": "Kandy", "age": 20 })),
+            record("3", json!({ "city": "Colombo", "age": 40.5 })),
+            record("4", json!({ "city": "Colombo" })),
+            record("5", json!({ "age": 10 })),
+        ];
+        let aggregates = vec![
+            function(AggregateFunction::Count, None),
+            function(AggregateFunction::Count, Some("age")),
+            function(AggregateFunction::Sum, Some("age")),
+            function(AggregateFunction::Avg, Some("age")),
+            function(AggregateFunction::Min, Some("age")),
+            function(AggregateFunction::Max, Some("age")),
+        ];
+
+        let rows = aggregate(&records, &aggregates, None)
+            .iter()
+            .map(|row| row.to_json())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![json!({
+                "count": 5,
+                "count(age)": 4,
+                "sum(age)": 100.5,
+                "avg(age)": 25.125,
+                "min(age)": 10,
+                "max(age)": 40.5,
+            })]
+        );
+
+        let rows = aggregate(&records, &aggregates[..3], Some("city"))
+            .iter()
+            .map(|row| row.to_json())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                json!({ "city": null, "count": 1, "count(age)": 1, "sum(age)": 10 }),
+                json!({ "city": "Colombo", "count": 3, "count(age)": 2, "sum(age)": 70.5 }),
+                json!({ "city": "Kandy", "count": 1, "count(age)": 1, "sum(age)": 20 }),
+            ]
+        );
+
+        // A single row even without records
+        let rows = aggregate(&[], &aggregates[2..4], None);
+        assert_eq!(
+            rows[0].to_json(),
+            json!({ "sum(age)": null, "avg(age)": null })
+        );
+        assert!(aggregate(&[], &aggregates, Some("city")).is_empty());
+    }
+}
@@ -0,0 +1,44 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use log::debug;
+use tokio::sync::RwLock;
+
+use crate::database::NoSqlDatabase;
+
+/// Share of dead records a data file needs before the compactor rewrites it
+pub const DEFAULT_MIN_GARBAGE: f64 = 0.5;
+
+/// Compact, every `interval`, the tables whose data file holds at least `min_garbage` of
+/// records left behind by updates and deletes. The write lock shared by every database is
+/// taken for one table at a time: every request waits while a table is compacted, and requests
+/// run between two tables.
+pub async fn run(
+    databases: Arc<RwLock<HashMap<String, NoSqlDatabase>>>,
+    interval: Duration,
+    min_garbage: f64,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes right away, the first round waits for a whole interval
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        debug!("Compacting tables with at least {} garbage", min_garbage);
+        let tables = databases
+            .read()
+            .await
+            .iter()
+            .flat_map(|(name, database)| {
+                database
+                    .tables()
+                    .into_iter()
+                    .map(move |table| (name.clone(), table))
+            })
+            .collect::<Vec<_>>();
+        for (name, table) in tables {
+            // The database may have been dropped since the tables were listed
+            if let Some(database) = databases.write().await.get_mut(&name) {
+                database.compact_table(&table, min_garbage).await;
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use config::{Config, ConfigError};
 
 static DEFAULT_CONFIG_PATH: &str = "./config/qtable/config.toml";
@@ -8,12 +8,30 @@ static DEFAULT_CONFIG_PATH: &str = "./config/qtable/config.toml";
 pub struct Cmd {
     #[arg(short, long, default_value_t=String::from(DEFAULT_CONFIG_PATH))]
     pub config_path: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Commands run instead of the server
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check that the index files of every table agree with its data file, the server must not
+    /// be running
+    Fsck {
+        data_path: String,
+        /// Rebuild the indexes of inconsistent tables from their data file
+        #[arg(long)]
+        rebuild: bool,
+    },
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct ServerConfig {
     pub data_path: String,
     pub port: Option<u16>,
+    pub http_port: Option<u16>, // The HTTP API is only started when a port is configured
+    pub compact_interval: Option<u64>, // Seconds between compactions, no background compaction when unset
+    pub compact_min_garbage: Option<f64>, // Share of dead records that makes a table worth compacting
 }
 
 impl ServerConfig {
@@ -1,31 +1,62 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{Display, Formatter},
+    future::Future,
     io::SeekFrom,
+    path::Path,
+    pin::Pin,
     vec,
 };
 
 use log::{debug, error};
+use serde::{Deserialize, Serialize};
 use tokio::{
     fs::{self, File},
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader},
 };
 
 use crate::{
-    index::{new_or_load, Index, IndexId},
-    parser::{Condition, Data, DataObject, Definition, InsertData, Query, WildCardOperations},
+    aggregate::aggregate,
+    index::{
+        index_key, literal_key, new_or_load, range_key, read_index, remove_index, Index,
+        IndexError, IndexId,
+    },
+    parser::{
+        Aggregate, AggregateFunction, AlterOperation, Condition, Data, DataObject, DataType,
+        Definition, IdGenerator, IndexKind, InsertData, Number, OrderBy, Query, SortDirection,
+        WildCardOperations, ID,
+    },
+    wal::{self, Intent, Wal},
 };
 
 const OBJECT_ID: &str = "object_id";
 const DEF_FILE: &str = ".def";
 const INDEX_FOLDER: &str = "idx";
-const DATA_FOLDER: &str = "dat";
+// File in the index folder holding the version of the format of the index keys, index folders
+// without it were written with the text of numbers as their keys
+const KEY_FORMAT_FILE: &str = "key_format";
+// Version 1 encodes the keys of number columns so that they sort numerically
+const KEY_FORMAT_VERSION: u32 = 1;
+// Data file being written by a compaction, it replaces the data file once complete
+const COMPACT_FILE: &str = ".dat.compact";
+// Present while the indexes of a compacted data file are rebuilt
+const COMPACTING_MARKER: &str = ".compacting";
+// Every record in the data file starts with a header: magic, format version, length of the
+// serialized record (u32 BE) and CRC32 of the serialized record (u32 BE)
+const RECORD_MAGIC: [u8; 4] = *b"QTRC";
+const RECORD_VERSION: u8 = 1;
+const RECORD_HEADER_LENGTH: u64 = 13;
+// Records written before the header had a version were only prefixed with their length
+const LEGACY_HEADER_LENGTH: usize = std::mem::size_of::<usize>();
 
 pub struct NoSqlDataObject {
     data_object: String,
     index: HashMap<String, Box<dyn Index>>, // Attribute, Index
     definition: HashMap<String, Definition>,
     root_path: String,
+    wal: Option<Wal>,
+    /// Largest incremented object id assigned, read from the object id index on first use
+    last_id: Option<i64>,
 }
 
 pub enum RangeOp {
@@ -42,6 +73,57 @@ pub enum DataObjectError {
     Insert(String),
     Delete(String),
     Create(String),
+    Alter(String),
+    /// A value does not match the type declared for its column
+    Type(String),
+    /// The write-ahead log could not be written or replayed
+    Wal(String),
+    /// The data file could not be compacted
+    Compact(String),
+    /// A record of the data file does not match its header or its index
+    Corrupt(String),
+    /// An index could not be read or written
+    Index(String),
+    /// A value is already held by another record in a unique column
+    DuplicateKey(String),
+}
+
+/// DataObjectErrorKind identifies a `DataObjectError` without its message
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum DataObjectErrorKind {
+    Serialize,
+    Deserialize,
+    Update,
+    Insert,
+    Delete,
+    Create,
+    Alter,
+    Type,
+    Wal,
+    Compact,
+    Corrupt,
+    Index,
+    DuplicateKey,
+}
+
+impl DataObjectError {
+    pub fn kind(&self) -> DataObjectErrorKind {
+        match self {
+            DataObjectError::Serialize(_) => DataObjectErrorKind::Serialize,
+            DataObjectError::Deserialize(_) => DataObjectErrorKind::Deserialize,
+            DataObjectError::Update(_) => DataObjectErrorKind::Update,
+            DataObjectError::Insert(_) => DataObjectErrorKind::Insert,
+            DataObjectError::Delete(_) => DataObjectErrorKind::Delete,
+            DataObjectError::Create(_) => DataObjectErrorKind::Create,
+            DataObjectError::Alter(_) => DataObjectErrorKind::Alter,
+            DataObjectError::Type(_) => DataObjectErrorKind::Type,
+            DataObjectError::Wal(_) => DataObjectErrorKind::Wal,
+            DataObjectError::Compact(_) => DataObjectErrorKind::Compact,
+            DataObjectError::Corrupt(_) => DataObjectErrorKind::Corrupt,
+            DataObjectError::Index(_) => DataObjectErrorKind::Index,
+            DataObjectError::DuplicateKey(_) => DataObjectErrorKind::DuplicateKey,
+        }
+    }
 }
 
 impl Display for DataObjectError {
@@ -53,6 +135,13 @@ impl Display for DataObjectError {
             DataObjectError::Insert(e) => write!(f, "Insert Error: {}", e),
             DataObjectError::Delete(e) => write!(f, "Delete Error: {}", e),
             DataObjectError::Create(e) => write!(f, "Create Error: {}", e),
+            DataObjectError::Alter(e) => write!(f, "Alter Error: {}", e),
+            DataObjectError::Type(e) => write!(f, "Type Error: {}", e),
+            DataObjectError::Wal(e) => write!(f, "WAL Error: {}", e),
+            DataObjectError::Compact(e) => write!(f, "Compact Error: {}", e),
+            DataObjectError::Corrupt(e) => write!(f, "Corrupt Data: {}", e),
+            DataObjectError::Index(e) => write!(f, "Index Error: {}", e),
+            DataObjectError::DuplicateKey(e) => write!(f, "Duplicate Key: {}", e),
         }
     }
 }
@@ -77,20 +166,23 @@ impl NoSqlDataObject {
         create_def(&root_path, data_object, &definition).await?;
         create_data_file(&root_path, data_object).await?;
         create_object_id_idx(&index_path).await?;
+        write_key_format(&index_path).await?;
 
         let mut indices = HashMap::new();
         for (attribute, def) in &definition {
             if def.indexed {
-                let index = new_or_load(attribute, &index_path)
+                let index = new_or_load(attribute, &index_path, def.index)
                     .await
                     .map_err(|e| DataObjectError::Create(format!("Error creating index: {}", e)))?;
                 indices.insert(attribute.clone(), index);
             }
         }
 
-        let object_id_idx = new_or_load(OBJECT_ID, &index_path).await.map_err(|e| {
-            DataObjectError::Create(format!("Error creating object id index: {}", e))
-        })?;
+        let object_id_idx = new_or_load(OBJECT_ID, &index_path, IndexKind::Memory)
+            .await
+            .map_err(|e| {
+                DataObjectError::Create(format!("Error creating object id index: {}", e))
+            })?;
         indices.insert(OBJECT_ID.to_string(), object_id_idx);
 
         Ok(NoSqlDataObject {
@@ -98,40 +190,329 @@ impl NoSqlDataObject {
             index: indices,
             definition,
             root_path: format!("{}/{}", root, data_object),
+            wal: None,
+            last_id: None,
         })
     }
 
     pub async fn load(data_object: &str, root: &str) -> Result<Self, DataObjectError> {
         let root_path = format!("{}/{}", root, data_object);
         let index_path = format!("{}/{}/{}", root, data_object, INDEX_FOLDER);
-        let def_file = format!("{}/{}{}", root_path, data_object, DEF_FILE);
-        let def = fs::read(def_file).await.map_err(|e| {
-            DataObjectError::Create(format!("Error reading definition file: {}", e))
-        })?;
-        let definition: HashMap<String, Definition> = bincode::deserialize(&def).map_err(|e| {
-            DataObjectError::Deserialize(format!("Error deserializing definition: {}", e))
-        })?;
+        let definition = read_definition(&root_path, data_object).await?;
         let mut indices = HashMap::new();
+        let mut stale = vec![];
         for (attribute, def) in &definition {
             if def.indexed {
-                let index = new_or_load(attribute, &index_path)
-                    .await
-                    .map_err(|e| DataObjectError::Create(format!("Error loading index: {}", e)))?;
+                let (index, readable) = load_index(attribute, &index_path, def.index).await?;
+                if !readable {
+                    stale.push(attribute.clone());
+                }
                 indices.insert(attribute.clone(), index);
             }
         }
-        let object_id_idx = new_or_load(OBJECT_ID, &index_path).await.map_err(|e| {
-            DataObjectError::Create(format!("Error loading object id index: {}", e))
-        })?;
+        let (object_id_idx, readable) =
+            load_index(OBJECT_ID, &index_path, IndexKind::Memory).await?;
+        if !readable {
+            stale.push(OBJECT_ID.to_string());
+        }
         indices.insert(OBJECT_ID.to_string(), object_id_idx);
+        // Indexes of number columns written before the keys were encoded hold text keys
+        let key_format = read_key_format(&index_path).await;
+        if key_format < KEY_FORMAT_VERSION {
+            for (attribute, def) in &definition {
+                let numeric = matches!(
+                    def.column_type(),
+                    DataType::Number | DataType::Int | DataType::Float
+                );
+                if def.indexed && numeric && !stale.contains(attribute) {
+                    stale.push(attribute.clone());
+                }
+            }
+        }
 
-        Ok(NoSqlDataObject {
+        let mut data_object = NoSqlDataObject {
             data_object: data_object.to_string(),
             index: indices,
             definition,
             root_path,
+            wal: None,
+            last_id: None,
+        };
+        data_object.upgrade_data_file().await?;
+        data_object.finish_compaction().await?;
+        for column in stale {
+            data_object.reindex(Some(&column)).await?;
+        }
+        if key_format < KEY_FORMAT_VERSION {
+            write_key_format(&index_path).await?;
+        }
+        Ok(data_object)
+    }
+
+    /// Log the mutations of the table in the write-ahead log of its database
+    pub fn with_wal(mut self, wal: Wal) -> Self {
+        self.wal = Some(wal);
+        self
+    }
+
+    /// Load the table after undoing the intents a crash left in the write-ahead log. The indexes
+    /// may be as torn as the data file so they are rebuilt from the restored data file.
+    pub async fn recover(
+        data_object: &str,
+        root: &str,
+        intents: &[&Intent],
+    ) -> Result<Self, DataObjectError> {
+        let root_path = format!("{}/{}", root, data_object);
+        undo_data_file(&root_path, data_object, intents).await?;
+        NoSqlDataObject::rebuild(data_object, root).await
+    }
+
+    /// Load the table with every index file rebuilt from the data file, the index files are
+    /// not read so they may be missing or unreadable
+    pub async fn rebuild(data_object: &str, root: &str) -> Result<Self, DataObjectError> {
+        let index_path = format!("{}/{}/{}", root, data_object, INDEX_FOLDER);
+        let _ = fs::remove_dir_all(&index_path).await;
+        fs::create_dir_all(&index_path)
+            .await
+            .map_err(|e| DataObjectError::Create(format!("Error creating index path: {}", e)))?;
+        write_key_format(&index_path).await?;
+
+        let mut data_object = NoSqlDataObject::load(data_object, root).await?;
+        data_object.rebuild_indexes().await?;
+        Ok(data_object)
+    }
+
+    /// Cross-check the index files of the table against its data file without changing any
+    /// file. Every entry of an index must point at an active record holding its key, and every
+    /// active record must have an entry in the index of each indexed column it holds.
+    pub async fn check(
+        data_object: &str,
+        root: &str,
+    ) -> Result<Vec<Inconsistency>, DataObjectError> {
+        let root_path = format!("{}/{}", root, data_object);
+        let index_path = format!("{}/{}", root_path, INDEX_FOLDER);
+        let definition = read_definition(&root_path, data_object).await?;
+        let records =
+            read_data_file(Path::new(&format!("{}/{}.dat", root_path, data_object))).await?;
+        let by_position = records
+            .iter()
+            .map(|(index_id, record)| (index_id.position, (index_id, record)))
+            .collect::<HashMap<_, _>>();
+
+        let mut columns = definition
+            .iter()
+            .filter(|(_, definition)| definition.indexed)
+            .map(|(column, definition)| {
+                (
+                    column.as_str(),
+                    Some(definition.column_type()),
+                    definition.index,
+                )
+            })
+            .collect::<Vec<_>>();
+        columns.sort_by_key(|(column, _, _)| *column);
+        columns.push((OBJECT_ID, None, IndexKind::Memory));
+
+        let mut inconsistencies = vec![];
+        for (column, data_type, kind) in columns {
+            // The key of the record in the index of the column, object ids are kept as they are
+            let key_of = |record: &InsertData| match data_type {
+                Some(data_type) => {
+                    attribute(&record.data, column).map(|value| index_key(data_type, value))
+                }
+                None => Some(record.object_id.clone()),
+            };
+            let index_file = format!("{}/{}.idx", index_path, column);
+            if !fs::try_exists(&index_file).await.unwrap_or(false) {
+                inconsistencies.push(Inconsistency::UnreadableIndex {
+                    column: column.to_string(),
+                    error: "the index file is missing".to_string(),
+                });
+                continue;
+            }
+            let entries = match read_index(column, &index_path, kind).await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    inconsistencies.push(Inconsistency::UnreadableIndex {
+                        column: column.to_string(),
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let mut found = std::collections::HashSet::new();
+            for (key, index_id) in &entries {
+                let reason = match by_position.get(&index_id.position) {
+                    None => Some("no record starts at its position"),
+                    Some((record_id, _)) if record_id.length != index_id.length => {
+                        Some("its length differs from the record")
+                    }
+                    Some((_, record)) if !record.active => Some("the record is inactive"),
+                    Some((_, record)) if key_of(record).as_ref() != Some(key) => {
+                        Some("the record holds another value")
+                    }
+                    Some(_) => None,
+                };
+                match reason {
+                    Some(reason) => inconsistencies.push(Inconsistency::Dangling {
+                        column: column.to_string(),
+                        key: key.to_string(),
+                        position: index_id.position,
+                        reason: reason.to_string(),
+                    }),
+                    None => {
+                        found.insert((key.to_string(), index_id.position));
+                    }
+                }
+            }
+            for (index_id, record) in records.iter().filter(|(_, record)| record.active) {
+                if let Some(key) = key_of(record) {
+                    if !found.contains(&(key.clone(), index_id.position)) {
+                        inconsistencies.push(Inconsistency::Missing {
+                            column: column.to_string(),
+                            key,
+                            position: index_id.position,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(inconsistencies)
+    }
+}
+
+/// Inconsistency between the index files and the data file of a table, found by `check`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inconsistency {
+    /// The index file of the column is missing or can not be read
+    UnreadableIndex { column: String, error: String },
+    /// An index entry does not point at an active record holding its key
+    Dangling {
+        column: String,
+        key: String,
+        position: u64,
+        reason: String,
+    },
+    /// An active record has no entry in the index of the column
+    Missing {
+        column: String,
+        key: String,
+        position: u64,
+    },
+}
+
+impl Display for Inconsistency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Inconsistency::UnreadableIndex { column, error } => {
+                write!(f, "index {} is unreadable: {}", column, error)
+            }
+            Inconsistency::Dangling {
+                column,
+                key,
+                position,
+                reason,
+            } => write!(
+                f,
+                "index {} has a dangling entry {} at {}: {}",
+                column, key, position, reason
+            ),
+            Inconsistency::Missing {
+                column,
+                key,
+                position,
+            } => write!(
+                f,
+                "index {} has no entry {} for the record at {}",
+                column, key, position
+            ),
+        }
+    }
+}
+
+/// Read the definition file of the table
+async fn read_definition(
+    root_path: &str,
+    data_object: &str,
+) -> Result<HashMap<String, Definition>, DataObjectError> {
+    let def_file = format!("{}/{}{}", root_path, data_object, DEF_FILE);
+    let def = fs::read(def_file)
+        .await
+        .map_err(|e| DataObjectError::Create(format!("Error reading definition file: {}", e)))?;
+    if let Ok(definition) = serde_json::from_slice(&def) {
+        return Ok(definition);
+    }
+    // Definition files were written with bincode before columns could choose their index
+    let definition: HashMap<String, LegacyDefinition> =
+        bincode::deserialize(&def).map_err(|e| {
+            DataObjectError::Deserialize(format!("Error deserializing definition: {}", e))
+        })?;
+    Ok(definition
+        .into_iter()
+        .map(|(column, legacy)| {
+            let definition = Definition {
+                data_type: legacy.data_type,
+                indexed: legacy.indexed,
+                optional: legacy.optional,
+                index: IndexKind::Memory,
+                unique: false,
+                generated: None,
+            };
+            (column, definition)
         })
+        .collect())
+}
+
+/// Column definition as written by versions that serialized definitions with bincode
+#[derive(Deserialize)]
+struct LegacyDefinition {
+    data_type: String,
+    indexed: bool,
+    optional: bool,
+}
+
+/// Load the index of the attribute. A missing or unreadable index file is replaced with an
+/// empty index, `false` tells the index must be rebuilt from the data file.
+async fn load_index(
+    attribute: &str,
+    index_path: &str,
+    kind: IndexKind,
+) -> Result<(Box<dyn Index>, bool), DataObjectError> {
+    let index_file = format!("{}/{}.idx", index_path, attribute);
+    let exists = fs::try_exists(&index_file).await.unwrap_or(false);
+    match new_or_load(attribute, index_path, kind).await {
+        Ok(index) => Ok((index, exists)),
+        Err(e) => {
+            error!("Rebuilding the unreadable index {}: {}", index_file, e);
+            remove_index(attribute, index_path)
+                .await
+                .map_err(|e| DataObjectError::Create(format!("Error removing index: {}", e)))?;
+            let index = new_or_load(attribute, index_path, kind)
+                .await
+                .map_err(|e| DataObjectError::Create(format!("Error creating index: {}", e)))?;
+            Ok((index, false))
+        }
+    }
+}
+
+fn index_error(e: IndexError) -> DataObjectError {
+    DataObjectError::Index(e.to_string())
+}
+
+/// Undo the intents on the data file of the table, the last intent is undone first
+async fn undo_data_file(
+    root_path: &str,
+    data_object: &str,
+    intents: &[&Intent],
+) -> Result<(), DataObjectError> {
+    let data_file = format!("{}/{}.dat", root_path, data_object);
+    for intent in intents.iter().rev() {
+        wal::undo(Path::new(&data_file), intent)
+            .await
+            .map_err(|e| DataObjectError::Wal(format!("Error undoing intent: {}", e)))?;
     }
+    Ok(())
 }
 
 async fn create_object_id_idx(index_path: &str) -> Result<(), DataObjectError> {
@@ -142,6 +523,62 @@ async fn create_object_id_idx(index_path: &str) -> Result<(), DataObjectError> {
     Ok(())
 }
 
+/// Version of the format of the keys of the indexes in the index folder, 0 when the folder
+/// predates the version file
+async fn read_key_format(index_path: &str) -> u32 {
+    fs::read_to_string(format!("{}/{}", index_path, KEY_FORMAT_FILE))
+        .await
+        .ok()
+        .and_then(|version| version.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+async fn write_key_format(index_path: &str) -> Result<(), DataObjectError> {
+    fs::write(
+        format!("{}/{}", index_path, KEY_FORMAT_FILE),
+        KEY_FORMAT_VERSION.to_string(),
+    )
+    .await
+    .map_err(|e| DataObjectError::Create(format!("Error writing key format file: {}", e)))
+}
+
+/// Append the record to the data file and return its position, I/O errors are reported with
+/// the error of the operation writing the record
+async fn write_to_end(
+    file: &mut File,
+    data: Vec<u8>,
+    error: fn(String) -> DataObjectError,
+) -> Result<u64, DataObjectError> {
+    let io_error = |e: std::io::Error| error(format!("Error writing data file: {}", e));
+    let position = file.seek(SeekFrom::End(0)).await.map_err(io_error)?;
+    debug!("Writing data to file: {:?}", position);
+    file.write_all(&frame_record(data)?)
+        .await
+        .map_err(io_error)?;
+    file.flush().await.map_err(io_error)?;
+    Ok(position)
+}
+
+/// Overwrite the record at the position of the data file
+async fn seek_and_write(
+    file: &mut File,
+    position: u64,
+    data: Vec<u8>,
+    error: fn(String) -> DataObjectError,
+) -> Result<(), DataObjectError> {
+    let io_error = |e: std::io::Error| error(format!("Error writing data file: {}", e));
+    let position = file
+        .seek(SeekFrom::Start(position))
+        .await
+        .map_err(io_error)?;
+    debug!("Writing data to file: {:?}", position);
+    file.write_all(&frame_record(data)?)
+        .await
+        .map_err(io_error)?;
+    file.flush().await.map_err(io_error)?;
+    Ok(())
+}
+
 async fn create_data_file(root_path: &str, data_object: &str) -> Result<(), DataObjectError> {
     let data_file = format!("{}/{}.dat", root_path, data_object);
     let _ = File::create(data_file)
@@ -159,7 +596,7 @@ async fn create_def(
     let mut def_file = File::create(def_file)
         .await
         .map_err(|e| DataObjectError::Create(format!("Error creating definition file: {}", e)))?;
-    let def = bincode::serialize(definition)
+    let def = serde_json::to_vec(definition)
         .map_err(|e| DataObjectError::Serialize(format!("Error serializing definition: {}", e)))?;
     def_file
         .write_all(&def)
@@ -169,24 +606,40 @@ async fn create_def(
 }
 
 impl NoSqlDataObject {
-    pub async fn add_to_index(&mut self, index_data: Vec<Data>, index_id: &IndexId) {
+    /// Definition of the attributes of the table
+    pub fn definition(&self) -> &HashMap<String, Definition> {
+        &self.definition
+    }
+
+    /// Add the record to the indexes of its attributes, each index touched is saved once all
+    /// the attributes are added
+    pub async fn add_to_index(
+        &mut self,
+        index_data: Vec<Data>,
+        index_id: &IndexId,
+    ) -> Result<(), DataObjectError> {
+        let mut touched = HashSet::new();
         for data in index_data {
+            let key = index_key(column_type(&self.definition, &data.key), &data.value);
             if let Some(index) = self.index.get_mut(data.key.as_str()) {
-                index.add_to_index(data.value.to_string().as_str(), index_id);
-                match index.save().await {
-                    Ok(_) => debug!("Index saved"),
-                    Err(e) => error!("Error saving index: {:?}", e), //#FIXME: Should handle
-                                                                     //the error
-                }
+                index
+                    .add_to_index(&key, index_id)
+                    .await
+                    .map_err(index_error)?;
+                touched.insert(data.key);
             }
         }
+        self.save_indexes(touched).await
     }
 
+    /// Move the records from their old to their new positions in the indexes, each index
+    /// touched is saved once all the records are moved
     pub async fn update_index(
         &mut self,
         new_index_data: Vec<(IndexId, InsertData)>,
         old_index_data: Vec<(IndexId, InsertData)>,
     ) -> Result<(), DataObjectError> {
+        let mut touched = HashSet::new();
         for (index_id, data) in old_index_data {
             let data_object = data.data;
             let attributes = self
@@ -197,21 +650,21 @@ impl NoSqlDataObject {
                 .collect::<Vec<_>>();
 
             for attra in attributes {
+                let key = index_key(column_type(&self.definition, &attra.key), &attra.value);
                 if let Some(index) = self.index.get_mut(attra.key.as_str()) {
-                    index.remove_from_index(attra.value.to_string().as_str(), &index_id);
-                    match index.save().await {
-                        Ok(_) => debug!("Index saved"),
-                        Err(e) => error!("Error saving index: {:?}", e), //#FIXME: Should handle
-                                                                         //the error
-                    }
+                    index
+                        .remove_from_index(&key, &index_id)
+                        .await
+                        .map_err(index_error)?;
+                    touched.insert(attra.key.clone());
                 }
             }
             if let Some(index) = self.index.get_mut(OBJECT_ID) {
-                index.remove_from_index(data.object_id.as_str(), &index_id);
-                match index.save().await {
-                    Ok(_) => debug!("Object ID index remvoed"),
-                    Err(e) => error!("Error saving index: {:?}", e),
-                }
+                index
+                    .remove_from_index(data.object_id.as_str(), &index_id)
+                    .await
+                    .map_err(index_error)?;
+                touched.insert(OBJECT_ID.to_string());
             }
         }
 
@@ -225,125 +678,384 @@ impl NoSqlDataObject {
                 .collect::<Vec<_>>();
 
             for attra in attributes {
+                let key = index_key(column_type(&self.definition, &attra.key), &attra.value);
                 if let Some(index) = self.index.get_mut(attra.key.as_str()) {
-                    index.add_to_index(attra.value.to_string().as_str(), &index_id);
-                    match index.save().await {
-                        Ok(_) => debug!("Index saved"),
-                        Err(e) => error!("Error saving index: {:?}", e), //#FIXME: Should handle
-                                                                         //the error
-                    }
+                    index
+                        .add_to_index(&key, &index_id)
+                        .await
+                        .map_err(index_error)?;
+                    touched.insert(attra.key.clone());
                 }
             }
             if let Some(index) = self.index.get_mut(OBJECT_ID) {
-                index.add_to_index(data.object_id.as_str(), &index_id);
-                match index.save().await {
-                    Ok(_) => debug!("Object ID index remvoed"),
-                    Err(e) => error!("Error saving index: {:?}", e),
-                }
+                index
+                    .add_to_index(data.object_id.as_str(), &index_id)
+                    .await
+                    .map_err(index_error)?;
+                touched.insert(OBJECT_ID.to_string());
             }
         }
-        Ok(())
+        self.save_indexes(touched).await
     }
 
-    pub fn remove_from_index(&mut self, attribute: &str, value: &str, object_id: &IndexId) {
-        if let Some(index) = self.index.get_mut(attribute) {
-            index.remove_from_index(value, object_id);
+    /// Save the indexes of the columns, the changes reach the disk before the write-ahead log
+    /// forgets the write
+    async fn save_indexes(&mut self, columns: HashSet<String>) -> Result<(), DataObjectError> {
+        for column in columns {
+            if let Some(index) = self.index.get_mut(&column) {
+                index.save().await.map_err(index_error)?;
+            }
         }
+        debug!("Index saved");
+        Ok(())
     }
 
-    pub async fn handle_query(
-        &self,
-        condition: &Condition,
-    ) -> Result<Vec<InsertData>, DataObjectError> {
-        let object_ids = self.query(condition);
-        self.get_record(object_ids).await
+    pub async fn remove_from_index(
+        &mut self,
+        attribute: &str,
+        value: &str,
+        object_id: &IndexId,
+    ) -> Result<(), DataObjectError> {
+        if let Some(index) = self.index.get_mut(attribute) {
+            index
+                .remove_from_index(value, object_id)
+                .await
+                .map_err(index_error)?;
+        }
+        Ok(())
     }
 
-    fn query(&self, condition: &Condition) -> Vec<&IndexId> {
-        match condition {
-            Condition::WildCard(op) => self.query_wildcard(op),
-            Condition::Equal(attr, value) => self.query_equal(attr, value),
-            Condition::GreaterThan(attr, value) => {
-                self.query_range(attr, value, RangeOp::GreaterThan)
+    pub async fn handle_query(&self, query: &Query) -> Result<Vec<InsertData>, DataObjectError> {
+        let mut object_ids = self.find(query.filter.as_ref()).await?;
+        let mut records = match &query.order_by {
+            Some(order_by) if self.index_ordered(&order_by.field) => {
+                self.order_by_index(&mut object_ids, order_by).await?;
+                let object_ids = paginate(object_ids, query.offset, query.limit);
+                self.get_record(object_ids.iter().collect()).await?
             }
-            Condition::GreaterThanOrEqual(attr, value) => {
-                self.query_range(attr, value, RangeOp::GreaterThanOrEqual)
+            Some(order_by) => {
+                let mut records = self.get_record(object_ids.iter().collect()).await?;
+                records.sort_by(|a, b| compare_records(a, b, order_by));
+                paginate(records, query.offset, query.limit)
             }
-            Condition::LessThan(attr, value) => self.query_range(attr, value, RangeOp::LessThan),
-            Condition::LessThanOrEqual(attr, value) => {
-                self.query_range(attr, value, RangeOp::LessThanOrEqual)
+            None => {
+                let object_ids = paginate(object_ids, query.offset, query.limit);
+                self.get_record(object_ids.iter().collect()).await?
             }
-            Condition::And(cond1, cond2) => {
-                let mut results1 = self.query(cond1);
-                let results2 = self.query(cond2);
-                results1.retain(|item| results2.contains(item));
-                results1
+        };
+        if let Some(projection) = &query.projection {
+            for record in records.iter_mut() {
+                record.data = record.data.project(projection);
             }
-            Condition::Or(cond1, cond2) => {
-                let mut results1 = self.query(cond1);
-                let results2 = self.query(cond2);
-                results1.extend(results2);
-                results1.dedup();
-                results1
+        }
+        Ok(records)
+    }
+
+    /// Answer a select with aggregates, one row per group sorted by the group value unless the
+    /// query has an `ORDER BY` on the group field or an aggregate name.
+    /// A lone `COUNT(*)` on conditions the indexes answer is counted without reading records.
+    pub async fn handle_aggregate(
+        &self,
+        query: &Query,
+    ) -> Result<Vec<DataObject>, DataObjectError> {
+        let rows = match self.count_from_index(query).await? {
+            Some(count) => vec![DataObject::Object(vec![Data {
+                key: query.aggregates[0].name(),
+                value: DataObject::Number(Number::Int(count as i64)),
+            }])],
+            None => {
+                let object_ids = self.find(query.filter.as_ref()).await?;
+                let records = self.get_record(object_ids.iter().collect()).await?;
+                aggregate(&records, &query.aggregates, query.group_by.as_deref())
             }
+        };
+        let mut rows = rows;
+        if let Some(order_by) = &query.order_by {
+            rows.sort_by(|a, b| {
+                compare_values(
+                    a.get_path(&order_by.field),
+                    b.get_path(&order_by.field),
+                    order_by,
+                )
+            });
         }
+        Ok(paginate(rows, query.offset, query.limit))
     }
 
-    fn query_wildcard(&self, op: &WildCardOperations) -> Vec<&IndexId> {
-        match op {
-            WildCardOperations::StartsWith(attr, prefix) => self.query_prefix(attr, prefix),
-            WildCardOperations::EndsWith(attr, suffix) => self.query_suffix(attr, suffix),
-            WildCardOperations::Contains(attr, substring) => self.query_contains(attr, substring),
+    /// Number of records matching the filter of a `COUNT(*)` without `GROUP BY`, `None` when the
+    /// indexes can not answer it
+    async fn count_from_index(&self, query: &Query) -> Result<Option<usize>, DataObjectError> {
+        let count_all = Aggregate {
+            function: AggregateFunction::Count,
+            field: None,
+        };
+        if query.group_by.is_some() || query.aggregates != [count_all] {
+            return Ok(None);
         }
+        let object_ids = match (&query.filter, self.index.get(OBJECT_ID)) {
+            (Some(condition), _) if self.is_indexed(condition) => self.query(condition).await?,
+            (None, Some(index)) => index.query_ordered().await.map_err(index_error)?,
+            _ => return Ok(None),
+        };
+        // OR conditions may return a record once per matching side
+        let positions = object_ids
+            .into_iter()
+            .map(|index_id| index_id.position)
+            .collect::<std::collections::HashSet<_>>();
+        Ok(Some(positions.len()))
     }
 
-    fn query_equal(&self, attr: &str, value: &str) -> Vec<&IndexId> {
-        if let Some(index) = self.index.get(attr) {
-            return index.query_equal(value);
+    /// True when the index of the field returns its records in the order `ORDER BY` expects,
+    /// arrays and objects are indexed by their text
+    fn index_ordered(&self, field: &str) -> bool {
+        self.index.contains_key(field)
+            && index_kind(&self.definition, field).is_ordered()
+            && !matches!(
+                column_type(&self.definition, field),
+                DataType::Array | DataType::Object
+            )
+    }
+
+    /// Sort the object ids by the position of their records in the index of the sort field,
+    /// records without the field come last
+    async fn order_by_index(
+        &self,
+        object_ids: &mut [IndexId],
+        order_by: &OrderBy,
+    ) -> Result<(), DataObjectError> {
+        let Some(index) = self.index.get(&order_by.field) else {
+            return Ok(());
+        };
+        let ranks = index
+            .query_ordered()
+            .await
+            .map_err(index_error)?
+            .into_iter()
+            .enumerate()
+            .map(|(rank, index_id)| (index_id.position, rank))
+            .collect::<HashMap<_, _>>();
+        object_ids.sort_by_key(|index_id| match ranks.get(&index_id.position) {
+            Some(rank) => match order_by.direction {
+                SortDirection::Asc => (false, *rank),
+                SortDirection::Desc => (false, usize::MAX - rank),
+            },
+            None => (true, 0),
+        });
+        Ok(())
+    }
+
+    /// Object ids of the active records matching the condition, `None` matches every record.
+    /// Conditions on attributes without an index are evaluated against every record of the
+    /// data file.
+    async fn find(&self, condition: Option<&Condition>) -> Result<Vec<IndexId>, DataObjectError> {
+        match condition {
+            Some(condition) if self.is_indexed(condition) => self.query(condition).await,
+            Some(condition) => Ok(self
+                .scan_records()
+                .await?
+                .into_iter()
+                .filter(|(_, record)| matches_condition(&record.data, condition, &self.definition))
+                .map(|(index_id, _)| index_id)
+                .collect()),
+            None => match self.index.get(OBJECT_ID) {
+                Some(index) => index.query_ordered().await.map_err(index_error),
+                None => Ok(self
+                    .scan_records()
+                    .await?
+                    .into_iter()
+                    .map(|(index_id, _)| index_id)
+                    .collect()),
+            },
         }
-        vec![]
     }
 
-    fn query_range(&self, attr: &str, value: &str, op: RangeOp) -> Vec<&IndexId> {
-        if let Some(index) = self.index.get(attr) {
-            return index.query_range(value, op);
+    /// True when every attribute of the condition has an index that can answer it. Wildcards
+    /// match the text of the value so only indexes of strings answer them.
+    fn is_indexed(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::And(cond1, cond2) | Condition::Or(cond1, cond2) => {
+                self.is_indexed(cond1) && self.is_indexed(cond2)
+            }
+            Condition::WildCard(_) => {
+                let attr = condition_attribute(condition);
+                self.index.contains_key(attr)
+                    && index_kind(&self.definition, attr).is_ordered()
+                    && column_type(&self.definition, attr) == DataType::String
+            }
+            Condition::Equal(attr, _) => self.index.contains_key(attr.as_str()),
+            condition => {
+                let attr = condition_attribute(condition);
+                self.index.contains_key(attr) && index_kind(&self.definition, attr).is_ordered()
+            }
         }
-        vec![]
     }
 
-    fn query_prefix(&self, attr: &str, prefix: &str) -> Vec<&IndexId> {
-        if let Some(index) = self.index.get(attr) {
-            return index.query_prefix(prefix);
+    fn query<'a>(
+        &'a self,
+        condition: &'a Condition,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<IndexId>, DataObjectError>> + Send + 'a>> {
+        Box::pin(async move {
+            match condition {
+                Condition::WildCard(op) => self.query_wildcard(op).await,
+                Condition::Equal(attr, value) => self.query_equal(attr, value).await,
+                Condition::GreaterThan(attr, value) => {
+                    self.query_range(attr, value, RangeOp::GreaterThan).await
+                }
+                Condition::GreaterThanOrEqual(attr, value) => {
+                    self.query_range(attr, value, RangeOp::GreaterThanOrEqual)
+                        .await
+                }
+                Condition::LessThan(attr, value) => {
+                    self.query_range(attr, value, RangeOp::LessThan).await
+                }
+                Condition::LessThanOrEqual(attr, value) => {
+                    self.query_range(attr, value, RangeOp::LessThanOrEqual)
+                        .await
+                }
+                Condition::And(cond1, cond2) => {
+                    let mut results1 = self.query(cond1).await?;
+                    let results2 = self.query(cond2).await?;
+                    results1.retain(|item| results2.contains(item));
+                    Ok(results1)
+                }
+                Condition::Or(cond1, cond2) => {
+                    let mut results1 = self.query(cond1).await?;
+                    let results2 = self.query(cond2).await?;
+                    results1.extend(results2);
+                    results1.dedup();
+                    Ok(results1)
+                }
+            }
+        })
+    }
+
+    async fn query_wildcard(
+        &self,
+        op: &WildCardOperations,
+    ) -> Result<Vec<IndexId>, DataObjectError> {
+        let (WildCardOperations::StartsWith(attr, _)
+        | WildCardOperations::EndsWith(attr, _)
+        | WildCardOperations::Contains(attr, _)) = op;
+        let Some(index) = self.index.get(attr) else {
+            return Ok(vec![]);
+        };
+        match op {
+            WildCardOperations::StartsWith(_, prefix) => index.query_prefix(prefix).await,
+            WildCardOperations::EndsWith(_, suffix) => index.query_suffix(suffix).await,
+            WildCardOperations::Contains(_, substring) => index.query_contains(substring).await,
         }
-        vec![]
+        .map_err(index_error)
     }
 
-    fn query_suffix(&self, attr: &str, suffix: &str) -> Vec<&IndexId> {
+    async fn query_equal(&self, attr: &str, value: &str) -> Result<Vec<IndexId>, DataObjectError> {
         if let Some(index) = self.index.get(attr) {
-            return index.query_suffix(suffix);
+            if let Some(key) = literal_key(column_type(&self.definition, attr), value) {
+                return index.query_equal(&key).await.map_err(index_error);
+            }
         }
-        vec![]
+        Ok(vec![])
     }
 
-    fn query_contains(&self, attr: &str, substring: &str) -> Vec<&IndexId> {
+    async fn query_range(
+        &self,
+        attr: &str,
+        value: &str,
+        op: RangeOp,
+    ) -> Result<Vec<IndexId>, DataObjectError> {
         if let Some(index) = self.index.get(attr) {
-            return index.query_contains(substring);
+            if let Some((key, op)) = range_key(column_type(&self.definition, attr), value, op) {
+                return index.query_range(&key, op).await.map_err(index_error);
+            }
+        }
+        Ok(vec![])
+    }
+
+    /// Assign an object id to an insert without an `id`, the way the `generated` key of the `id`
+    /// column selects, and add it to the document as its `id`
+    pub async fn assign_object_id(
+        &mut self,
+        insert_data: &mut InsertData,
+    ) -> Result<(), DataObjectError> {
+        let generator = self
+            .definition
+            .get(ID)
+            .and_then(|definition| definition.generated)
+            .unwrap_or_default();
+        if !insert_data.object_id.is_empty() {
+            // Ids chosen by the client are never handed out again
+            if let (Some(last_id), Ok(id)) = (self.last_id, insert_data.object_id.parse::<i64>()) {
+                self.last_id = Some(last_id.max(id));
+            }
+            return Ok(());
         }
-        vec![]
+        let (object_id, value) = match generator {
+            IdGenerator::Uuid => {
+                let object_id = uuid::Uuid::new_v4().to_string();
+                (object_id.clone(), DataObject::String(object_id))
+            }
+            IdGenerator::Ulid => {
+                let object_id = ulid::Ulid::new().to_string();
+                (object_id.clone(), DataObject::String(object_id))
+            }
+            IdGenerator::Increment => {
+                let last_id = match self.last_id {
+                    Some(last_id) => last_id,
+                    None => self.largest_id().await?,
+                };
+                self.last_id = Some(last_id + 1);
+                (
+                    (last_id + 1).to_string(),
+                    DataObject::Number(Number::Int(last_id + 1)),
+                )
+            }
+        };
+        if let DataObject::Object(attributes) = &mut insert_data.data {
+            attributes.push(Data {
+                key: ID.to_string(),
+                value,
+            });
+        }
+        insert_data.object_id = object_id;
+        Ok(())
+    }
+
+    /// Largest integer object id of the table, 0 when it has none
+    async fn largest_id(&self) -> Result<i64, DataObjectError> {
+        let Some(index) = self.index.get(OBJECT_ID) else {
+            return Ok(0);
+        };
+        let entries = index.entries().await.map_err(index_error)?;
+        Ok(entries
+            .iter()
+            .filter_map(|(key, _)| key.parse::<i64>().ok())
+            .max()
+            .unwrap_or(0))
     }
 
     pub async fn handle_insert(&mut self, insert_data: &InsertData) -> Result<(), DataObjectError> {
+        if insert_data.object_id.is_empty() {
+            return Err(DataObjectError::Insert("Object id is missing".to_string()));
+        }
         let attributes = self.get_attributes(&insert_data.data);
+        self.validate_types(&attributes)?;
         let mut indexed_attr = self.validate_insert_index_data(&attributes)?;
-        let index_id = self.insert_record(insert_data).await?;
+        self.validate_unique(&attributes, Some(&insert_data.object_id), &[])
+            .await?;
+        self.log_intent(&[]).await?;
+        let index_id = match self.insert_record(insert_data).await {
+            Ok(index_id) => index_id,
+            Err(e) => return self.rollback(Err(e)).await,
+        };
 
         let index_data = Data {
             key: OBJECT_ID.to_string(),
             value: DataObject::String(insert_data.object_id.clone()),
         };
         indexed_attr.push(index_data);
-        self.add_to_index(indexed_attr, &index_id).await; //# FIXME: should add other attributes to the index considering the definition
-        Ok(())
+        match self.add_to_index(indexed_attr, &index_id).await {
+            Ok(_) => self.commit().await,
+            Err(e) => self.rollback(Err(e)).await,
+        }
     }
 
     fn validate_insert_index_data(
@@ -400,6 +1112,26 @@ impl NoSqlDataObject {
         Ok(indexed_attra)
     }
 
+    /// Check every defined attribute holds a value of its declared type. Columns with a type this
+    /// version does not know are not checked.
+    fn validate_types(&self, attributes: &[&Data]) -> Result<(), DataObjectError> {
+        for attribute in attributes {
+            let Some(definition) = self.definition.get(&attribute.key) else {
+                continue;
+            };
+            let Ok(data_type) = definition.data_type.parse::<DataType>() else {
+                continue;
+            };
+            if !data_type.accepts(&attribute.value) {
+                return Err(DataObjectError::Type(format!(
+                    "Expected {} for {} but found {}",
+                    definition.data_type, attribute.key, attribute.value
+                )));
+            }
+        }
+        Ok(())
+    }
+
     fn validate_update_data(&self, attributes: &[&Data]) -> Result<Vec<Data>, DataObjectError> {
         let defined_index_atta = self.defined_indexed_attra();
         self.validate_null_index(attributes, &defined_index_atta)?;
@@ -450,6 +1182,58 @@ impl NoSqlDataObject {
         attributes
     }
 
+    /// Reject values of unique columns, and the object id, that the index already holds for a
+    /// record other than the `updated` records. Setting a unique column of more than one record
+    /// to the same value is rejected as well.
+    async fn validate_unique(
+        &self,
+        attributes: &[&Data],
+        object_id: Option<&str>,
+        updated: &[IndexId],
+    ) -> Result<(), DataObjectError> {
+        let mut keys = attributes
+            .iter()
+            .filter(|data| !matches!(data.value, DataObject::Null))
+            .filter(|data| {
+                self.definition
+                    .get(&data.key)
+                    .is_some_and(|definition| definition.indexed && definition.unique)
+            })
+            .map(|data| {
+                let key = index_key(column_type(&self.definition, &data.key), &data.value);
+                (data.key.as_str(), key)
+            })
+            .collect::<Vec<_>>();
+        if let Some(object_id) = object_id {
+            keys.push((OBJECT_ID, object_id.to_string()));
+        }
+        for (column, key) in keys {
+            if updated.len() > 1 {
+                return Err(DataObjectError::DuplicateKey(format!(
+                    "{} records would hold {} in the unique column {}",
+                    updated.len(),
+                    key,
+                    column
+                )));
+            }
+            let Some(index) = self.index.get(column) else {
+                continue;
+            };
+            let object_ids = index.get(&key).await.map_err(index_error)?;
+            if object_ids
+                .unwrap_or_default()
+                .iter()
+                .any(|index_id| !updated.contains(index_id))
+            {
+                return Err(DataObjectError::DuplicateKey(format!(
+                    "{} is already held by another record in the unique column {}",
+                    key, column
+                )));
+            }
+        }
+        Ok(())
+    }
+
     fn validate_null_index(
         &self,
         attributes: &[&Data],
@@ -480,45 +1264,645 @@ impl NoSqlDataObject {
         update_data: &InsertData,
         query: Query,
     ) -> Result<(), DataObjectError> {
-        let old_index_id = self.query(&query.filter);
+        let old_index_id = self.find(query.filter.as_ref()).await?;
         let updated_attributes = self.get_attributes(&update_data.data);
+        self.validate_types(&updated_attributes)?;
         self.validate_update_data(&updated_attributes)?;
         if old_index_id.is_empty() {
             return Err(DataObjectError::Update("Data not found".to_string()));
         }
-        let (new_index_data, old_index_data) = self
-            .update_record(old_index_id, update_data.clone())
+        self.validate_unique(&updated_attributes, None, &old_index_id)
             .await?;
-
-        self.update_index(new_index_data, old_index_data).await?;
-        Ok(())
+        self.log_intent(&old_index_id).await?;
+        let result = match self
+            .update_record(old_index_id.iter().collect(), update_data.clone())
+            .await
+        {
+            Ok((new_index_data, old_index_data)) => {
+                self.update_index(new_index_data, old_index_data).await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_) => self.commit().await,
+            Err(e) => self.rollback(Err(e)).await,
+        }
     }
 
     pub async fn handle_delete(&mut self, query: &Query) -> Result<(), DataObjectError> {
-        let index_ids = self.query(&query.filter);
+        let index_ids = self.find(query.filter.as_ref()).await?;
         if index_ids.is_empty() {
             return Err(DataObjectError::Delete("Data not found".to_string()));
         }
-        let deleted_data = self.delete_records(index_ids).await?;
-        for (deleted_data, index_id) in deleted_data {
-            self.remove_from_index(OBJECT_ID, &deleted_data.object_id, &index_id);
+        self.log_intent(&index_ids).await?;
+        let deleted_data = match self.delete_records(index_ids.iter().collect()).await {
+            Ok(deleted_data) => deleted_data,
+            Err(e) => return self.rollback(Err(e)).await,
+        };
+        let deleted_data = deleted_data
+            .into_iter()
+            .map(|(data, index_id)| (index_id, data))
+            .collect::<Vec<_>>();
+        match self.update_index(vec![], deleted_data).await {
+            Ok(_) => self.commit().await,
+            Err(e) => self.rollback(Err(e)).await,
+        }
+    }
+
+    /// Begin a transaction in the write-ahead log and log how to undo the change about to be
+    /// made to the data file, `overwrites` are the records the change rewrites in place
+    async fn log_intent(&self, overwrites: &[IndexId]) -> Result<(), DataObjectError> {
+        let Some(wal) = &self.wal else {
+            return Ok(());
+        };
+        let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
+        let data_length = fs::metadata(&data_file_name)
+            .await
+            .map_err(|e| DataObjectError::Wal(format!("Error reading data file: {}", e)))?
+            .len();
+        let mut records = vec![];
+        for index_id in overwrites {
+            records.push((index_id.position, self.read_raw(index_id).await?));
+        }
+        wal.begin()
+            .await
+            .map_err(|e| DataObjectError::Wal(e.to_string()))?;
+        let intent = Intent {
+            table: self.data_object.clone(),
+            data_length,
+            overwrites: records,
+        };
+        if let Err(e) = wal.log(intent).await {
+            let _ = wal.rollback().await;
+            return Err(DataObjectError::Wal(e.to_string()));
         }
         Ok(())
     }
-}
 
-impl NoSqlDataObject {
-    async fn insert_record(&self, insert_data: &InsertData) -> Result<IndexId, DataObjectError> {
-        let serialized = bincode::serialize(&insert_data);
-        match serialized {
-            Ok(data) => {
-                let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
-                let file = File::options().append(true).open(data_file_name).await; // Data file
-                                                                                    // should be available at this point
-                match file {
-                    Ok(file) => {
-                        let data_len = data.len();
-                        let (position, _file) = self.write_to_end(file, data).await?;
+    /// Commit the transaction of the change
+    async fn commit(&self) -> Result<(), DataObjectError> {
+        match &self.wal {
+            Some(wal) => wal
+                .commit()
+                .await
+                .map_err(|e| DataObjectError::Wal(e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// End the transaction of a failed change. When no outer transaction is open the change is
+    /// undone right away and the indexes are rebuilt from the restored data file, the error of
+    /// the change is returned either way.
+    async fn rollback(
+        &mut self,
+        result: Result<(), DataObjectError>,
+    ) -> Result<(), DataObjectError> {
+        let Some(wal) = self.wal.clone() else {
+            return result;
+        };
+        let Some(intents) = wal.rollback().await else {
+            return result;
+        };
+        match self.undo(&intents.iter().collect::<Vec<_>>()).await {
+            Ok(_) => {
+                if let Err(e) = wal.clear().await {
+                    error!("Error clearing WAL: {}", e);
+                }
+            }
+            // The log keeps the intents so the change is undone when the database is loaded
+            Err(e) => error!("Error undoing change: {}", e),
+        }
+        result
+    }
+
+    /// Undo the intents of a transaction that did not commit and rebuild the indexes from the
+    /// restored data file
+    pub async fn undo(&mut self, intents: &[&Intent]) -> Result<(), DataObjectError> {
+        undo_data_file(&self.root_path, &self.data_object, intents).await?;
+        self.rebuild_indexes().await
+    }
+
+    /// Rewrite the active records into a new data file and swap it with the data file, the
+    /// records left behind by updates and deletes are dropped. Every index is rebuilt as the
+    /// records move. Returns the number of bytes reclaimed.
+    pub async fn compact(&mut self) -> Result<u64, DataObjectError> {
+        let records = self.scan_records().await?;
+        self.rewrite(records.into_iter().map(|(_, record)| record))
+            .await
+    }
+
+    /// Write the records into a new data file, swap it with the data file and rebuild the
+    /// indexes. Returns the number of bytes the data file shrank by.
+    async fn rewrite(
+        &mut self,
+        records: impl IntoIterator<Item = InsertData>,
+    ) -> Result<u64, DataObjectError> {
+        let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
+        let compact_file_name = format!("{}/{}{}", self.root_path, self.data_object, COMPACT_FILE);
+        let compact_error = |e: std::io::Error| DataObjectError::Compact(e.to_string());
+        let before = fs::metadata(&data_file_name)
+            .await
+            .map_err(compact_error)?
+            .len();
+
+        let mut file = File::create(&compact_file_name)
+            .await
+            .map_err(compact_error)?;
+        for record in records {
+            let data = bincode::serialize(&record).map_err(|e| {
+                DataObjectError::Serialize(format!("Error serializing data: {}", e))
+            })?;
+            file.write_all(&frame_record(data)?)
+                .await
+                .map_err(compact_error)?;
+        }
+        file.sync_all().await.map_err(compact_error)?;
+        let after = file.metadata().await.map_err(compact_error)?.len();
+        drop(file);
+
+        // The indexes point into the old data file until they are rebuilt, the marker tells
+        // `load` to rebuild them when the server stops in between
+        let marker = format!(
+            "{}/{}{}",
+            self.root_path, self.data_object, COMPACTING_MARKER
+        );
+        File::create(&marker).await.map_err(compact_error)?;
+        fs::rename(&compact_file_name, &data_file_name)
+            .await
+            .map_err(compact_error)?;
+        self.rebuild_indexes().await?;
+        fs::remove_file(&marker).await.map_err(compact_error)?;
+        debug!(
+            "Rewrote {} from {} to {} bytes",
+            self.data_object, before, after
+        );
+        Ok(before.saturating_sub(after))
+    }
+
+    /// Rewrite a data file written before records had a versioned header, only its active
+    /// records are kept
+    async fn upgrade_data_file(&mut self) -> Result<(), DataObjectError> {
+        let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
+        let data = fs::read(&data_file_name)
+            .await
+            .map_err(|e| DataObjectError::Create(format!("Error reading data file: {}", e)))?;
+        if data.is_empty() || data.starts_with(&RECORD_MAGIC) {
+            return Ok(());
+        }
+        debug!("Upgrading the data file of {}", self.data_object);
+        let mut records = vec![];
+        let mut position = 0;
+        while position < data.len() {
+            let start = position + LEGACY_HEADER_LENGTH;
+            let header = data.get(position..start).ok_or_else(|| {
+                DataObjectError::Corrupt(format!("Truncated record at {}", position))
+            })?;
+            let length = usize::from_be_bytes(header.try_into().unwrap());
+            let payload = start
+                .checked_add(length)
+                .and_then(|end| data.get(start..end))
+                .ok_or_else(|| {
+                    DataObjectError::Corrupt(format!("Truncated record at {}", position))
+                })?;
+            let record = bincode::deserialize::<InsertData>(payload).map_err(|e| {
+                DataObjectError::Corrupt(format!("Invalid record at {}: {}", position, e))
+            })?;
+            if record.active {
+                records.push(record);
+            }
+            position = start + length;
+        }
+        self.rewrite(records).await?;
+        Ok(())
+    }
+
+    /// Share of the data file held by records that are no longer active, between 0 and 1
+    pub async fn garbage_ratio(&self) -> Result<f64, DataObjectError> {
+        let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
+        let total = fs::metadata(&data_file_name)
+            .await
+            .map_err(|e| DataObjectError::Compact(e.to_string()))?
+            .len();
+        if total == 0 {
+            return Ok(0.0);
+        }
+        let live = match self.index.get(OBJECT_ID) {
+            Some(index) => index
+                .query_ordered()
+                .await
+                .map_err(|e| DataObjectError::Compact(e.to_string()))?
+                .iter()
+                .map(|index_id| RECORD_HEADER_LENGTH + index_id.length as u64)
+                .sum::<u64>(),
+            None => total,
+        };
+        Ok(total.saturating_sub(live) as f64 / total as f64)
+    }
+
+    /// Complete a compaction the server stopped in. Before the swap the data file is intact
+    /// and the new data file is dropped, after it the indexes are rebuilt.
+    async fn finish_compaction(&mut self) -> Result<(), DataObjectError> {
+        let compact_file_name = format!("{}/{}{}", self.root_path, self.data_object, COMPACT_FILE);
+        let marker = format!(
+            "{}/{}{}",
+            self.root_path, self.data_object, COMPACTING_MARKER
+        );
+        let compact_error = |e: std::io::Error| DataObjectError::Compact(e.to_string());
+        if fs::try_exists(&compact_file_name)
+            .await
+            .map_err(compact_error)?
+        {
+            fs::remove_file(&compact_file_name)
+                .await
+                .map_err(compact_error)?;
+        }
+        if fs::try_exists(&marker).await.map_err(compact_error)? {
+            debug!(
+                "Rebuilding the indexes of the compacted {}",
+                self.data_object
+            );
+            self.rebuild_indexes().await?;
+            fs::remove_file(&marker).await.map_err(compact_error)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild every index of the table from the records of the data file
+    pub async fn rebuild_indexes(&mut self) -> Result<(), DataObjectError> {
+        self.reindex(None).await
+    }
+
+    /// Rebuild the index of the column, or every index of the table, from the records of the
+    /// data file. The column must be indexed or be the object id.
+    pub async fn reindex(&mut self, column: Option<&str>) -> Result<(), DataObjectError> {
+        let columns = match column {
+            Some(OBJECT_ID) => vec![OBJECT_ID.to_string()],
+            Some(column)
+                if self
+                    .definition
+                    .get(column)
+                    .is_some_and(|definition| definition.indexed) =>
+            {
+                vec![column.to_string()]
+            }
+            Some(column) => {
+                return Err(DataObjectError::Alter(format!(
+                    "Column {} is not indexed",
+                    column
+                )))
+            }
+            None => {
+                let mut columns = self
+                    .definition
+                    .iter()
+                    .filter(|(_, definition)| definition.indexed)
+                    .map(|(column, _)| column.clone())
+                    .collect::<Vec<_>>();
+                columns.push(OBJECT_ID.to_string());
+                columns
+            }
+        };
+        let records = self.scan_records().await?;
+        for column in columns {
+            self.drop_index(&column).await?;
+            match self.definition.get(&column).cloned() {
+                Some(definition) => self.build_index(&column, &definition, &records).await?,
+                None => self.build_object_id_index(&records).await?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn build_object_id_index(
+        &mut self,
+        records: &[(IndexId, InsertData)],
+    ) -> Result<(), DataObjectError> {
+        let index_path = format!("{}/{}", self.root_path, INDEX_FOLDER);
+        let mut index = new_or_load(OBJECT_ID, &index_path, IndexKind::Memory)
+            .await
+            .map_err(|e| {
+                DataObjectError::Create(format!("Error creating object id index: {}", e))
+            })?;
+        for (index_id, record) in records {
+            index
+                .add_to_index(&record.object_id, index_id)
+                .await
+                .map_err(index_error)?;
+        }
+        index
+            .save()
+            .await
+            .map_err(|e| DataObjectError::Create(format!("Error saving index: {}", e)))?;
+        self.index.insert(OBJECT_ID.to_string(), index);
+        Ok(())
+    }
+
+    /// Change the definition of a column. When a column becomes indexed its index is built from
+    /// the stored records, when it stops being indexed its index file is removed.
+    pub async fn handle_alter(
+        &mut self,
+        operation: &AlterOperation,
+    ) -> Result<(), DataObjectError> {
+        match operation {
+            AlterOperation::Add(column, definition) => {
+                if column == OBJECT_ID || self.definition.contains_key(column) {
+                    return Err(DataObjectError::Alter(format!(
+                        "Column {} already exists",
+                        column
+                    )));
+                }
+                let records = self.scan_records().await?;
+                validate_column(&records, column, definition)?;
+                if definition.indexed {
+                    self.build_index(column, definition, &records).await?;
+                }
+                self.definition.insert(column.clone(), definition.clone());
+            }
+            AlterOperation::Drop(column) => match self.definition.remove(column) {
+                Some(definition) => {
+                    if definition.indexed {
+                        self.drop_index(column).await?;
+                    }
+                }
+                None => {
+                    return Err(DataObjectError::Alter(format!(
+                        "Column {} does not exist",
+                        column
+                    )))
+                }
+            },
+            AlterOperation::Modify(column, definition) => {
+                let (indexed, data_type, kind) = match self.definition.get(column) {
+                    Some(old_definition) => (
+                        old_definition.indexed,
+                        old_definition.column_type(),
+                        old_definition.index,
+                    ),
+                    None => {
+                        return Err(DataObjectError::Alter(format!(
+                            "Column {} does not exist",
+                            column
+                        )))
+                    }
+                };
+                let records = self.scan_records().await?;
+                validate_column(&records, column, definition)?;
+                if definition.indexed
+                    && indexed
+                    && (definition.column_type() != data_type || definition.index != kind)
+                {
+                    // The keys of the index depend on the type of the column
+                    self.drop_index(column).await?;
+                    self.build_index(column, definition, &records).await?;
+                } else if definition.indexed && !indexed {
+                    self.build_index(column, definition, &records).await?;
+                } else if !definition.indexed && indexed {
+                    self.drop_index(column).await?;
+                }
+                self.definition.insert(column.clone(), definition.clone());
+            }
+        }
+        create_def(&self.root_path, &self.data_object, &self.definition).await
+    }
+
+    /// Build the index of the column from the records and register it
+    async fn build_index(
+        &mut self,
+        column: &str,
+        definition: &Definition,
+        records: &[(IndexId, InsertData)],
+    ) -> Result<(), DataObjectError> {
+        let index_path = format!("{}/{}", self.root_path, INDEX_FOLDER);
+        let mut index = new_or_load(column, &index_path, definition.index)
+            .await
+            .map_err(|e| DataObjectError::Alter(format!("Error creating index: {}", e)))?;
+        for (index_id, record) in records {
+            if let Some(value) = attribute(&record.data, column) {
+                index
+                    .add_to_index(&index_key(definition.column_type(), value), index_id)
+                    .await
+                    .map_err(|e| DataObjectError::Alter(format!("Error building index: {}", e)))?;
+            }
+        }
+        index
+            .save()
+            .await
+            .map_err(|e| DataObjectError::Alter(format!("Error saving index: {}", e)))?;
+        self.index.insert(column.to_string(), index);
+        Ok(())
+    }
+
+    /// Forget the index of the column and delete its index file
+    async fn drop_index(&mut self, column: &str) -> Result<(), DataObjectError> {
+        self.index.remove(column);
+        let index_path = format!("{}/{}", self.root_path, INDEX_FOLDER);
+        remove_index(column, &index_path)
+            .await
+            .map_err(|e| DataObjectError::Alter(format!("Error deleting index file: {}", e)))
+    }
+}
+
+/// Value of the top level attribute of the record, `None` when the attribute is missing
+fn attribute<'a>(data: &'a DataObject, key: &str) -> Option<&'a DataObject> {
+    match data {
+        DataObject::Object(attributes) => attributes
+            .iter()
+            .find(|attribute| attribute.key == key)
+            .map(|attribute| &attribute.value),
+        _ => None,
+    }
+}
+
+/// Attribute a condition without `AND` or `OR` filters on
+fn condition_attribute(condition: &Condition) -> &str {
+    match condition {
+        Condition::Equal(attr, _)
+        | Condition::GreaterThan(attr, _)
+        | Condition::GreaterThanOrEqual(attr, _)
+        | Condition::LessThan(attr, _)
+        | Condition::LessThanOrEqual(attr, _)
+        | Condition::WildCard(WildCardOperations::StartsWith(attr, _))
+        | Condition::WildCard(WildCardOperations::EndsWith(attr, _))
+        | Condition::WildCard(WildCardOperations::Contains(attr, _)) => attr,
+        Condition::And(_, _) | Condition::Or(_, _) => "",
+    }
+}
+
+/// Type of the column, attributes outside the definition are indexed as strings
+fn column_type(definition: &HashMap<String, Definition>, attr: &str) -> DataType {
+    definition
+        .get(attr)
+        .map(|definition| definition.column_type())
+        .unwrap_or(DataType::String)
+}
+
+/// Kind of the index of the column, the object id index is always kept in memory
+fn index_kind(definition: &HashMap<String, Definition>, attr: &str) -> IndexKind {
+    definition
+        .get(attr)
+        .map(|definition| definition.index)
+        .unwrap_or_default()
+}
+
+/// Evaluate the condition against a record the same way the indexes do, values are compared by
+/// their index key. Attributes outside the definition are compared by the type of their value.
+/// Records without the attribute never match.
+fn matches_condition(
+    data: &DataObject,
+    condition: &Condition,
+    definition: &HashMap<String, Definition>,
+) -> bool {
+    // Key of the value of the attribute and the type it is compared by
+    let key = |attr: &str| {
+        let value = data.get_path(attr)?;
+        let data_type = match definition.get(attr) {
+            Some(definition) => definition.column_type(),
+            None => DataType::of(value),
+        };
+        Some((data_type, index_key(data_type, value)))
+    };
+    let equal = |attr: &str, expected: &str| {
+        key(attr).is_some_and(|(data_type, key)| literal_key(data_type, expected) == Some(key))
+    };
+    let compare = |attr: &str, expected: &str, op: RangeOp| {
+        let Some((data_type, key)) = key(attr) else {
+            return false;
+        };
+        let Some((expected, op)) = range_key(data_type, expected, op) else {
+            return false;
+        };
+        let ordering = key.cmp(&expected);
+        match op {
+            RangeOp::GreaterThan => ordering.is_gt(),
+            RangeOp::GreaterThanOrEqual => ordering.is_ge(),
+            RangeOp::LessThan => ordering.is_lt(),
+            RangeOp::LessThanOrEqual => ordering.is_le(),
+        }
+    };
+    let value = |attr: &str| data.get_path(attr).map(|value| value.to_string());
+    match condition {
+        Condition::And(cond1, cond2) => {
+            matches_condition(data, cond1, definition) && matches_condition(data, cond2, definition)
+        }
+        Condition::Or(cond1, cond2) => {
+            matches_condition(data, cond1, definition) || matches_condition(data, cond2, definition)
+        }
+        Condition::Equal(attr, expected) => equal(attr, expected),
+        Condition::GreaterThan(attr, expected) => compare(attr, expected, RangeOp::GreaterThan),
+        Condition::GreaterThanOrEqual(attr, expected) => {
+            compare(attr, expected, RangeOp::GreaterThanOrEqual)
+        }
+        Condition::LessThan(attr, expected) => compare(attr, expected, RangeOp::LessThan),
+        Condition::LessThanOrEqual(attr, expected) => {
+            compare(attr, expected, RangeOp::LessThanOrEqual)
+        }
+        Condition::WildCard(WildCardOperations::StartsWith(attr, prefix)) => {
+            value(attr).is_some_and(|value| value.starts_with(prefix.as_str()))
+        }
+        Condition::WildCard(WildCardOperations::EndsWith(attr, suffix)) => {
+            value(attr).is_some_and(|value| value.ends_with(suffix.as_str()))
+        }
+        Condition::WildCard(WildCardOperations::Contains(attr, substring)) => {
+            value(attr).is_some_and(|value| value.contains(substring.as_str()))
+        }
+    }
+}
+
+/// Order two records by the value at the sort field, records without the field come last
+fn compare_records(a: &InsertData, b: &InsertData, order_by: &OrderBy) -> std::cmp::Ordering {
+    compare_values(
+        a.data.get_path(&order_by.field),
+        b.data.get_path(&order_by.field),
+        order_by,
+    )
+}
+
+fn compare_values(
+    a: Option<&DataObject>,
+    b: Option<&DataObject>,
+    order_by: &OrderBy,
+) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => match order_by.direction {
+            SortDirection::Asc => a.compare(b),
+            SortDirection::Desc => b.compare(a),
+        },
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+/// Skip `offset` items and keep at most `limit` of the rest
+fn paginate<T>(items: Vec<T>, offset: Option<usize>, limit: Option<usize>) -> Vec<T> {
+    items
+        .into_iter()
+        .skip(offset.unwrap_or(0))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Check the stored records satisfy the new definition of the column
+fn validate_column(
+    records: &[(IndexId, InsertData)],
+    column: &str,
+    definition: &Definition,
+) -> Result<(), DataObjectError> {
+    let mut keys = HashSet::new();
+    for (_, record) in records {
+        if let Some(value) =
+            attribute(&record.data, column).filter(|value| **value != DataObject::Null)
+        {
+            if definition.unique && !keys.insert(index_key(definition.column_type(), value)) {
+                return Err(DataObjectError::DuplicateKey(format!(
+                    "Record {} repeats {} in the unique column {}",
+                    record.object_id, value, column
+                )));
+            }
+        }
+        match attribute(&record.data, column) {
+            None | Some(DataObject::Null) if !definition.optional => {
+                return Err(DataObjectError::Alter(format!(
+                    "Record {} has no value for the mandatory column {}",
+                    record.object_id, column
+                )));
+            }
+            Some(DataObject::Null) if definition.indexed => {
+                return Err(DataObjectError::Alter(format!(
+                    "Record {} has a null value for the indexed column {}",
+                    record.object_id, column
+                )));
+            }
+            Some(value)
+                if definition
+                    .data_type
+                    .parse::<DataType>()
+                    .is_ok_and(|data_type| !data_type.accepts(value)) =>
+            {
+                return Err(DataObjectError::Alter(format!(
+                    "Record {} has {} for the column {} of type {}",
+                    record.object_id, value, column, definition.data_type
+                )));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+impl NoSqlDataObject {
+    async fn insert_record(&self, insert_data: &InsertData) -> Result<IndexId, DataObjectError> {
+        let serialized = bincode::serialize(&insert_data);
+        match serialized {
+            Ok(data) => {
+                let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
+                let file = File::options().append(true).open(data_file_name).await; // Data file
+                                                                                    // should be available at this point
+                match file {
+                    Ok(mut file) => {
+                        let data_len = data.len();
+                        let position =
+                            write_to_end(&mut file, data, DataObjectError::Insert).await?;
                         Ok(IndexId {
                             position,
                             length: data_len,
@@ -553,21 +1937,7 @@ impl NoSqlDataObject {
                 debug!("Data file opened");
 
                 for data_object in data_objects {
-                    file.seek(SeekFrom::Start(data_object.position))
-                        .await
-                        .unwrap();
-                    let mut data_chunk = vec![0; data_object.length];
-                    file.read_exact(&mut data_chunk).await.unwrap();
-                    let data_object = bincode::deserialize::<InsertData>(&data_chunk);
-                    match data_object {
-                        Ok(data_object) => data.push(data_object),
-                        Err(e) => {
-                            error!("Error: {:?}", e);
-                            return Err(DataObjectError::Deserialize(
-                                "Error deserializing data".to_string(),
-                            ));
-                        }
-                    }
+                    data.push(read_record(&mut file, data_object).await?);
                 }
             }
             Err(e) => {
@@ -580,36 +1950,6 @@ impl NoSqlDataObject {
         Ok(data)
     }
 
-    async fn get_data_object(&self, data_object: &IndexId) -> Result<InsertData, DataObjectError> {
-        let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
-        let file = File::open(data_file_name).await;
-        match file {
-            Ok(mut file) => {
-                file.seek(SeekFrom::Start(data_object.position))
-                    .await
-                    .unwrap();
-                let mut data = vec![0; data_object.length];
-                file.read_exact(&mut data).await.unwrap();
-                let data_object = bincode::deserialize::<InsertData>(&data);
-                match data_object {
-                    Ok(data_object) => Ok(data_object),
-                    Err(e) => {
-                        error!("Error: {:?}", e);
-                        Err(DataObjectError::Deserialize(
-                            "Error deserializing data".to_string(),
-                        ))
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Error: {:?}", e);
-                Err(DataObjectError::Serialize(
-                    "Error opening data file".to_string(),
-                ))
-            }
-        }
-    }
-
     async fn get_data_objects<'a>(
         &self,
         old_index_ids: Vec<&'a IndexId>,
@@ -620,21 +1960,8 @@ impl NoSqlDataObject {
         match file {
             Ok(mut file) => {
                 for index_id in old_index_ids {
-                    file.seek(SeekFrom::Start(index_id.position)).await.unwrap();
-                    let mut data = vec![0; index_id.length];
-                    file.read_exact(&mut data).await.unwrap();
-                    let data_object = bincode::deserialize::<InsertData>(&data);
-                    match data_object {
-                        Ok(data_object) => {
-                            insert_data.push((index_id, data_object));
-                        }
-                        Err(e) => {
-                            error!("Error: {:?}", e);
-                            return Err(DataObjectError::Deserialize(
-                                "Error deserializing data".to_string(),
-                            ));
-                        }
-                    }
+                    let data_object = read_record(&mut file, index_id).await?;
+                    insert_data.push((index_id, data_object));
                 }
             }
             Err(e) => {
@@ -665,7 +1992,8 @@ impl NoSqlDataObject {
 
         let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
         let mut data_file = File::options()
-            .append(true)
+            .read(true)
+            .write(true)
             .open(data_file_name)
             .await
             .map_err(|er| {
@@ -680,8 +2008,7 @@ impl NoSqlDataObject {
                 DataObjectError::Update("Error serializing update data".to_string())
             })?;
             let length = data.len();
-            let (position, file) = self.write_to_end(data_file, data).await?; //#FIXME: We
-            data_file = file; //Handle this properly. should rollback other changes.
+            let position = write_to_end(&mut data_file, data, DataObjectError::Update).await?;
             index_ids.push((IndexId { position, length }, data_to_save.clone()));
         }
 
@@ -690,10 +2017,13 @@ impl NoSqlDataObject {
             old_data.active = false;
             let old_serialized = bincode::serialize(&old_data)
                 .map_err(|_| DataObjectError::Update("Error serializing old data".to_string()))?;
-            let (file, _) = self
-                .seek_and_write(data_file, index.position, old_serialized)
-                .await?; //#FIXME: We should rollback the data if this fails
-            data_file = file;
+            seek_and_write(
+                &mut data_file,
+                index.position,
+                old_serialized,
+                DataObjectError::Update,
+            )
+            .await?;
         }
         let old_data = old_data
             .iter()
@@ -724,15 +2054,16 @@ impl NoSqlDataObject {
                     .into_iter()
                     .for_each(|data| new_data_vec.push(data.clone()));
 
+                // The object id of a record never changes with an update
                 InsertData {
                     data: DataObject::Object(new_data_vec),
                     table: new_insert_data.table,
                     active: new_insert_data.active,
-                    object_id: new_insert_data.object_id,
+                    object_id: old_insert_data.object_id.clone(),
                 }
             }
             _ => InsertData {
-                object_id: new_insert_data.object_id,
+                object_id: old_insert_data.object_id.clone(),
                 table: new_insert_data.table,
                 data: old_insert_data.data.clone(),
                 active: old_insert_data.active,
@@ -745,38 +2076,23 @@ impl NoSqlDataObject {
         index_ids: Vec<&IndexId>,
     ) -> Result<Vec<(InsertData, IndexId)>, DataObjectError> {
         let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
-        let file = File::open(data_file_name).await;
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(data_file_name)
+            .await;
         let mut deleted_data = vec![];
         match file {
             Ok(mut file) => {
                 for index_id in index_ids {
-                    let position = index_id.position;
-                    let length = index_id.length;
-                    let mut data = vec![0; length];
-                    file.seek(SeekFrom::Start(position)).await.unwrap();
-                    file.read_exact(&mut data).await.unwrap();
-                    let data_object = bincode::deserialize::<InsertData>(&data);
-                    match data_object {
-                        Ok(mut data_object) => {
-                            data_object.active = false;
-                            let data = bincode::serialize(&data_object).map_err(|_| {
-                                DataObjectError::Delete("Error serializing data".to_string())
-                            })?;
-                            self.seek_and_write(
-                                file.try_clone().await.unwrap(),
-                                index_id.position,
-                                data,
-                            )
-                            .await?; //# FIXME: try not to clone the file
-                            deleted_data.push((data_object, index_id.clone()));
-                        }
-                        Err(e) => {
-                            error!("Error: {:?}", e);
-                            return Err(DataObjectError::Deserialize(
-                                "Error deserializing data".to_string(),
-                            ));
-                        }
-                    }
+                    let mut data_object = read_record(&mut file, index_id).await?;
+                    data_object.active = false;
+                    let data = bincode::serialize(&data_object).map_err(|_| {
+                        DataObjectError::Delete("Error serializing data".to_string())
+                    })?;
+                    seek_and_write(&mut file, index_id.position, data, DataObjectError::Delete)
+                        .await?;
+                    deleted_data.push((data_object, index_id.clone()));
                 }
                 Ok(deleted_data)
             }
@@ -789,90 +2105,185 @@ impl NoSqlDataObject {
         }
     }
 
-    async fn delete_record(&self, index_id: &IndexId) -> Result<(), DataObjectError> {
+    /// Read every active record of the data file in the order they were written
+    pub async fn scan_records(&self) -> Result<Vec<(IndexId, InsertData)>, DataObjectError> {
         let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
-        let file = File::open(data_file_name).await;
-        match file {
-            Ok(mut file) => {
-                let mut data = vec![0; index_id.length];
-                file.seek(SeekFrom::Start(index_id.position)).await.unwrap();
-                file.read_exact(&mut data).await.unwrap();
-                let data_object = bincode::deserialize::<InsertData>(&data);
-                match data_object {
-                    Ok(mut data_object) => {
-                        data_object.active = false;
-                        let data = bincode::serialize(&data_object).map_err(|_| {
-                            DataObjectError::Delete("Error serializing data".to_string())
-                        })?;
-                        self.seek_and_write(file, index_id.position, data).await?;
-                        Ok(())
-                    }
-                    Err(e) => {
-                        error!("Error: {:?}", e);
-                        Err(DataObjectError::Deserialize(
-                            "Error deserializing data".to_string(),
-                        ))
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Error: {:?}", e);
-                Err(DataObjectError::Update(
-                    "Error opening data file".to_string(),
-                ))
-            }
-        }
-    }
-
-    async fn write_to_end(
-        &self,
-        mut file: File,
-        data: Vec<u8>,
-    ) -> Result<(u64, File), DataObjectError> {
-        let position = file.seek(SeekFrom::End(0)).await.unwrap();
-        debug!("Writing data to file: {:?}", position);
-        let len = data.len().to_be_bytes();
-        let data = [len.to_vec(), data].concat();
-        file.write_all(&data).await.unwrap();
-        file.flush().await.unwrap();
-        Ok((position, file))
-    }
-
-    async fn seek_and_write(
-        &self,
-        mut file: File,
-        position: u64,
-        data: Vec<u8>,
-    ) -> Result<(File, u64), DataObjectError> {
-        let position = file.seek(SeekFrom::Start(position)).await.unwrap();
-        debug!("Writing data to file: {:?}", position);
-        let len = data.len().to_be_bytes();
-        let data = [len.to_vec(), data].concat();
-        file.write_all(&data).await.unwrap();
-        file.flush().await.unwrap();
-        Ok((file, position))
+        let records = read_data_file(Path::new(&data_file_name)).await?;
+        Ok(records
+            .into_iter()
+            .filter(|(_, record)| record.active)
+            .collect())
     }
 
+    /// Serialized record at the position, checked against its header
     pub async fn seek_and_read(
         &self,
         position: u64,
         length: usize,
     ) -> Result<Vec<u8>, DataObjectError> {
         let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
-        let mut file = File::open(data_file_name).await.unwrap();
+        let mut file = File::open(data_file_name)
+            .await
+            .map_err(|e| DataObjectError::Deserialize(format!("Error opening data file: {}", e)))?;
+        read_at(&mut file, &IndexId { position, length }).await
+    }
+}
 
-        file.seek(SeekFrom::Start(position)).await.unwrap();
-        let mut data = vec![0; length];
-        file.read_exact(&mut data).await.unwrap();
+impl NoSqlDataObject {
+    /// Bytes of the record pointed by the index id, header included
+    async fn read_raw(&self, index_id: &IndexId) -> Result<Vec<u8>, DataObjectError> {
+        let data_file_name = format!("{}/{}.dat", self.root_path, self.data_object);
+        let mut file = File::open(data_file_name)
+            .await
+            .map_err(|e| DataObjectError::Wal(format!("Error opening data file: {}", e)))?;
+        file.seek(SeekFrom::Start(index_id.position))
+            .await
+            .map_err(|e| DataObjectError::Wal(format!("Error reading record: {}", e)))?;
+        let mut data = vec![0; RECORD_HEADER_LENGTH as usize + index_id.length];
+        file.read_exact(&mut data)
+            .await
+            .map_err(|e| DataObjectError::Wal(format!("Error reading record: {}", e)))?;
         Ok(data)
     }
 }
 
+/// Read every record of the data file, active or not, in the order they were written
+async fn read_data_file(data_file: &Path) -> Result<Vec<(IndexId, InsertData)>, DataObjectError> {
+    let file = File::open(data_file).await.map_err(|e| {
+        error!("Error: {:?}", e);
+        DataObjectError::Deserialize("Error opening data file".to_string())
+    })?;
+    let file_length = file
+        .metadata()
+        .await
+        .map_err(|e| DataObjectError::Deserialize(format!("Error reading data file: {}", e)))?
+        .len();
+    let mut reader = BufReader::new(file);
+    let mut records = vec![];
+    let mut position = 0;
+    loop {
+        let max_length = file_length.saturating_sub(position + RECORD_HEADER_LENGTH);
+        let Some(data) = read_next(&mut reader, position, max_length).await? else {
+            break;
+        };
+        let record = bincode::deserialize::<InsertData>(&data).map_err(|e| {
+            error!("Error: {:?}", e);
+            DataObjectError::Corrupt(format!("Error deserializing record at {}", position))
+        })?;
+        let length = data.len();
+        records.push((IndexId { position, length }, record));
+        position += RECORD_HEADER_LENGTH + length as u64;
+    }
+    Ok(records)
+}
+
+/// Prefix the serialized record with its header, records of 4 GiB or more do not fit the length
+/// of the header
+fn frame_record(data: Vec<u8>) -> Result<Vec<u8>, DataObjectError> {
+    let length = u32::try_from(data.len()).map_err(|_| {
+        DataObjectError::Serialize(format!(
+            "Record of {} bytes exceeds the maximum record length of {} bytes",
+            data.len(),
+            u32::MAX
+        ))
+    })?;
+    let mut record = Vec::with_capacity(RECORD_HEADER_LENGTH as usize + data.len());
+    record.extend_from_slice(&RECORD_MAGIC);
+    record.push(RECORD_VERSION);
+    record.extend_from_slice(&length.to_be_bytes());
+    record.extend_from_slice(&crc32fast::hash(&data).to_be_bytes());
+    record.extend(data);
+    Ok(record)
+}
+
+/// Read the record starting at the current offset of the reader, `position`, and check it
+/// against its header. The length of the header is checked against `max_length`, the bytes the
+/// record may hold, before it is read. `None` when the reader is at the end of the file.
+async fn read_next<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    position: u64,
+    max_length: u64,
+) -> Result<Option<Vec<u8>>, DataObjectError> {
+    let truncated = |e: std::io::Error| {
+        error!("Error: {:?}", e);
+        DataObjectError::Corrupt(format!("Truncated record at {}", position))
+    };
+    let mut header = [0; RECORD_HEADER_LENGTH as usize];
+    let read = reader.read(&mut header).await.map_err(truncated)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    reader
+        .read_exact(&mut header[read..])
+        .await
+        .map_err(truncated)?;
+    if header[..4] != RECORD_MAGIC {
+        return Err(DataObjectError::Corrupt(format!(
+            "No record header at {}",
+            position
+        )));
+    }
+    if header[4] != RECORD_VERSION {
+        return Err(DataObjectError::Corrupt(format!(
+            "Record at {} has the unsupported format version {}",
+            position, header[4]
+        )));
+    }
+    let length = u32::from_be_bytes(header[5..9].try_into().unwrap()) as usize;
+    let checksum = u32::from_be_bytes(header[9..13].try_into().unwrap());
+    if length as u64 > max_length {
+        return Err(DataObjectError::Corrupt(format!(
+            "Record at {} claims {} bytes, more than the {} bytes it may hold",
+            position, length, max_length
+        )));
+    }
+    let mut data = vec![0; length];
+    reader.read_exact(&mut data).await.map_err(truncated)?;
+    if crc32fast::hash(&data) != checksum {
+        return Err(DataObjectError::Corrupt(format!(
+            "Checksum mismatch for the record at {}",
+            position
+        )));
+    }
+    Ok(Some(data))
+}
+
+/// Serialized record pointed by the index id, checked against its header and the index id
+async fn read_at(file: &mut File, index_id: &IndexId) -> Result<Vec<u8>, DataObjectError> {
+    file.seek(SeekFrom::Start(index_id.position))
+        .await
+        .map_err(|e| {
+            DataObjectError::Corrupt(format!("No record at {}: {}", index_id.position, e))
+        })?;
+    let data = read_next(file, index_id.position, index_id.length as u64)
+        .await?
+        .ok_or_else(|| DataObjectError::Corrupt(format!("No record at {}", index_id.position)))?;
+    if data.len() != index_id.length {
+        return Err(DataObjectError::Corrupt(format!(
+            "Record at {} has {} bytes but its index expects {}",
+            index_id.position,
+            data.len(),
+            index_id.length
+        )));
+    }
+    Ok(data)
+}
+
+/// Read the record pointed by the index id. The position of an index id points to the header
+/// of the record, the header is checked before the serialized record is read.
+async fn read_record(file: &mut File, index_id: &IndexId) -> Result<InsertData, DataObjectError> {
+    let data = read_at(file, index_id).await?;
+    bincode::deserialize::<InsertData>(&data).map_err(|e| {
+        error!("Error: {:?}", e);
+        DataObjectError::Deserialize("Error deserializing data".to_string())
+    })
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
-    use crate::parser::{Data, DataObject, InsertData};
+    use crate::parser::{Data, DataObject, InsertData, Number};
     use std::collections::HashMap;
     use tempfile::Builder;
 
@@ -893,12 +2304,18 @@ mod test {
             data_type: "String".to_string(),
             indexed: true,
             optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
 
         let age_definition = Definition {
             data_type: "Number".to_string(),
             indexed: false,
             optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
 
         definitions.insert("name".to_string(), name_definition);
@@ -939,6 +2356,8 @@ mod test {
             definition: HashMap::new(),
             index: HashMap::new(),
             root_path: root_dir,
+            wal: None,
+            last_id: None,
         };
 
         let data = Data {
@@ -1004,11 +2423,17 @@ mod test {
             data_type: "String".to_string(),
             indexed: true,
             optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
         let age_definition = Definition {
             data_type: "Number".to_string(),
             indexed: false,
             optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
         definitions.insert("name".to_string(), name_definition);
         definitions.insert("age".to_string(), age_definition);
@@ -1039,11 +2464,17 @@ mod test {
             data_type: "String".to_string(),
             indexed: true,
             optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
         let age_definition = Definition {
             data_type: "Number".to_string(),
             indexed: false,
             optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
         definitions.insert("name".to_string(), name_definition);
         definitions.insert("age".to_string(), age_definition);
@@ -1073,6 +2504,9 @@ mod test {
             data_type: "String".to_string(),
             indexed: false,
             optional: false,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
         definitions.insert("name".to_string(), name_definition);
         let dir = Builder::new()
@@ -1101,11 +2535,17 @@ mod test {
             data_type: "String".to_string(),
             indexed: false,
             optional: false,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
         let age_definition = Definition {
             data_type: "Number".to_string(),
             indexed: false,
             optional: false,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
         };
         definitions.insert("name".to_string(), name_definition);
         definitions.insert("age".to_string(), age_definition);
@@ -1127,4 +2567,1269 @@ mod test {
         let result = nosql_data_object.validate_insert_index_data(&attributes);
         assert!(result.is_err());
     }
+
+    fn user(object_id: &str, name: &str, age: Option<i64>) -> InsertData {
+        let mut data = vec![Data {
+            key: "name".to_string(),
+            value: DataObject::String(name.to_string()),
+        }];
+        if let Some(age) = age {
+            data.push(Data {
+                key: "age".to_string(),
+                value: DataObject::Number(Number::Int(age)),
+            });
+        }
+        InsertData {
+            object_id: object_id.to_string(),
+            table: "user".to_string(),
+            data: DataObject::Object(data),
+            active: true,
+        }
+    }
+
+    fn select(filter: Condition, projection: Option<Vec<&str>>) -> Query {
+        Query {
+            db: "xdr".to_string(),
+            table_name: "user".to_string(),
+            filter: Some(filter),
+            projection: projection
+                .map(|fields| fields.into_iter().map(|field| field.to_string()).collect()),
+            order_by: None,
+            limit: None,
+            offset: None,
+            aggregates: vec![],
+            group_by: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_alter_table() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([(
+            "name".to_string(),
+            Definition {
+                data_type: "String".to_string(),
+                indexed: false,
+                optional: false,
+                index: IndexKind::Memory,
+                unique: false,
+                generated: None,
+            },
+        )]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        data_object
+            .handle_insert(&user("1", "John", Some(30)))
+            .await
+            .unwrap();
+        data_object
+            .handle_insert(&user("2", "Jane", None))
+            .await
+            .unwrap();
+        let index_file = dir.path().join("user").join("idx").join("age.idx");
+
+        // Jane has no age so the column can not be mandatory
+        let mandatory_age = Definition {
+            data_type: "Number".to_string(),
+            indexed: true,
+            optional: false,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
+        };
+        let result = data_object
+            .handle_alter(&AlterOperation::Add("age".to_string(), mandatory_age))
+            .await;
+        assert!(matches!(result, Err(DataObjectError::Alter(_))));
+        assert!(!index_file.exists());
+
+        let age = Definition {
+            data_type: "Number".to_string(),
+            indexed: true,
+            optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
+        };
+        data_object
+            .handle_alter(&AlterOperation::Add("age".to_string(), age))
+            .await
+            .unwrap();
+        assert!(index_file.exists());
+        let found = data_object
+            .handle_query(&select(
+                Condition::Equal("age".to_string(), "30".to_string()),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].object_id, "1");
+
+        // Indexing an existing column builds its index from the stored records
+        let name = Definition {
+            data_type: "String".to_string(),
+            indexed: true,
+            optional: false,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
+        };
+        data_object
+            .handle_alter(&AlterOperation::Modify("name".to_string(), name))
+            .await
+            .unwrap();
+        let found = data_object
+            .handle_query(&select(
+                Condition::Equal("name".to_string(), "Jane".to_string()),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(found[0].object_id, "2");
+
+        data_object
+            .handle_alter(&AlterOperation::Drop("age".to_string()))
+            .await
+            .unwrap();
+        assert!(!index_file.exists());
+        let result = data_object
+            .handle_alter(&AlterOperation::Drop("age".to_string()))
+            .await;
+        assert!(matches!(result, Err(DataObjectError::Alter(_))));
+
+        // The definition file was rewritten
+        let loaded = NoSqlDataObject::load("user", root_dir).await.unwrap();
+        assert_eq!(loaded.definition.len(), 1);
+        assert!(loaded.definition.get("name").unwrap().indexed);
+        assert!(loaded.index.contains_key("name"));
+    }
+
+    #[tokio::test]
+    async fn test_query_projection() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([(
+            "name".to_string(),
+            Definition {
+                data_type: "String".to_string(),
+                indexed: true,
+                optional: false,
+                index: IndexKind::Memory,
+                unique: false,
+                generated: None,
+            },
+        )]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        let mut john = user("1", "John", Some(30));
+        if let DataObject::Object(data) = &mut john.data {
+            data.push(Data {
+                key: "address".to_string(),
+                value: DataObject::Object(vec![
+                    Data {
+                        key: "city".to_string(),
+                        value: DataObject::String("Colombo".to_string()),
+                    },
+                    Data {
+                        key: "zip".to_string(),
+                        value: DataObject::String("10".to_string()),
+                    },
+                ]),
+            });
+        }
+        data_object.handle_insert(&john).await.unwrap();
+        data_object
+            .handle_insert(&user("2", "Jane", None))
+            .await
+            .unwrap();
+
+        let query = select(
+            Condition::WildCard(WildCardOperations::StartsWith(
+                "name".to_string(),
+                "J".to_string(),
+            )),
+            Some(vec!["name", "age", "address.city"]),
+        );
+        let mut found = data_object.handle_query(&query).await.unwrap();
+        found.sort_by(|a, b| a.object_id.cmp(&b.object_id));
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].object_id, "1");
+        assert_eq!(
+            found[0].data.to_json(),
+            serde_json::json!({ "name": "John", "age": 30, "address": { "city": "Colombo" } })
+        );
+        // Missing fields are left out
+        assert_eq!(
+            found[1].data.to_json(),
+            serde_json::json!({ "name": "Jane" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_order_and_limit() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([
+            (
+                "name".to_string(),
+                Definition {
+                    data_type: "String".to_string(),
+                    indexed: true,
+                    optional: false,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+            (
+                "age".to_string(),
+                Definition {
+                    data_type: "Number".to_string(),
+                    indexed: false,
+                    optional: true,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+        ]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, name, age) in [
+            ("1", "Jane", Some(9)),
+            ("2", "John", None),
+            ("3", "Jack", Some(30)),
+            ("4", "Jill", Some(100)),
+        ] {
+            data_object
+                .handle_insert(&user(object_id, name, age))
+                .await
+                .unwrap();
+        }
+        let ids = |records: Vec<InsertData>| {
+            records
+                .into_iter()
+                .map(|record| record.object_id)
+                .collect::<Vec<_>>()
+        };
+        let starts_with_j = || {
+            Condition::WildCard(WildCardOperations::StartsWith(
+                "name".to_string(),
+                "J".to_string(),
+            ))
+        };
+
+        // Sorted with the index of name
+        let mut query = select(starts_with_j(), None);
+        query.order_by = Some(OrderBy {
+            field: "name".to_string(),
+            direction: SortDirection::Desc,
+        });
+        let found = data_object.handle_query(&query).await.unwrap();
+        assert_eq!(ids(found), vec!["2", "4", "1", "3"]);
+
+        query.offset = Some(1);
+        query.limit = Some(2);
+        let found = data_object.handle_query(&query).await.unwrap();
+        assert_eq!(ids(found), vec!["4", "1"]);
+
+        // Sorted in memory by value, records without an age come last
+        let mut query = select(starts_with_j(), None);
+        query.order_by = Some(OrderBy {
+            field: "age".to_string(),
+            direction: SortDirection::Asc,
+        });
+        let found = data_object.handle_query(&query).await.unwrap();
+        assert_eq!(ids(found), vec!["1", "3", "4", "2"]);
+
+        query.offset = Some(3);
+        let found = data_object.handle_query(&query).await.unwrap();
+        assert_eq!(ids(found), vec!["2"]);
+    }
+
+    #[tokio::test]
+    async fn test_query_without_index() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([
+            (
+                "name".to_string(),
+                Definition {
+                    data_type: "String".to_string(),
+                    indexed: true,
+                    optional: false,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+            (
+                "age".to_string(),
+                Definition {
+                    data_type: "Number".to_string(),
+                    indexed: false,
+                    optional: true,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+        ]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, name, age) in [
+            ("1", "Jane", Some(30)),
+            ("2", "John", None),
+            ("3", "Jack", Some(30)),
+        ] {
+            data_object
+                .handle_insert(&user(object_id, name, age))
+                .await
+                .unwrap();
+        }
+        let ids = |records: Vec<InsertData>| {
+            let mut ids = records
+                .into_iter()
+                .map(|record| record.object_id)
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        // No filter selects every record
+        let mut all = select(Condition::Equal("name".to_string(), "-".to_string()), None);
+        all.filter = None;
+        let found = data_object.handle_query(&all).await.unwrap();
+        assert_eq!(ids(found), vec!["1", "2", "3"]);
+
+        // age has no index so the records are scanned
+        let age = select(Condition::Equal("age".to_string(), "30".to_string()), None);
+        let found = data_object.handle_query(&age).await.unwrap();
+        assert_eq!(ids(found), vec!["1", "3"]);
+
+        let mixed = select(
+            Condition::And(
+                Box::new(Condition::Equal("age".to_string(), "30".to_string())),
+                Box::new(Condition::Equal("name".to_string(), "Jack".to_string())),
+            ),
+            None,
+        );
+        let found = data_object.handle_query(&mixed).await.unwrap();
+        assert_eq!(ids(found), vec!["3"]);
+
+        // Deleted records are not scanned
+        data_object
+            .handle_delete(&select(
+                Condition::Equal("name".to_string(), "Jane".to_string()),
+                None,
+            ))
+            .await
+            .unwrap();
+        let found = data_object.handle_query(&age).await.unwrap();
+        assert_eq!(ids(found), vec!["3"]);
+        let found = data_object.handle_query(&all).await.unwrap();
+        assert_eq!(ids(found), vec!["2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_query() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([
+            (
+                "name".to_string(),
+                Definition {
+                    data_type: "String".to_string(),
+                    indexed: true,
+                    optional: false,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+            (
+                "age".to_string(),
+                Definition {
+                    data_type: "Number".to_string(),
+                    indexed: false,
+                    optional: true,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+        ]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, name, age) in [
+            ("1", "John", Some(30)),
+            ("2", "John", Some(20)),
+            ("3", "Jack", Some(40)),
+            ("4", "Jill", None),
+        ] {
+            data_object
+                .handle_insert(&user(object_id, name, age))
+                .await
+                .unwrap();
+        }
+        let count = |filter: Option<Condition>| {
+            let mut query = select(Condition::Equal("name".to_string(), "-".to_string()), None);
+            query.filter = filter;
+            query.aggregates = vec![Aggregate {
+                function: AggregateFunction::Count,
+                field: None,
+            }];
+            query
+        };
+
+        let mut query = count(None);
+        query.aggregates.push(Aggregate {
+            function: AggregateFunction::Sum,
+            field: Some("age".to_string()),
+        });
+        query.group_by = Some("name".to_string());
+        query.order_by = Some(OrderBy {
+            field: "count".to_string(),
+            direction: SortDirection::Desc,
+        });
+        query.limit = Some(2);
+        let rows = data_object.handle_aggregate(&query).await.unwrap();
+        let rows = rows.iter().map(|row| row.to_json()).collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                serde_json::json!({ "name": "John", "count": 2, "sum(age)": 50 }),
+                serde_json::json!({ "name": "Jack", "count": 1, "sum(age)": 40 }),
+            ]
+        );
+
+        // Counting on indexed conditions does not read the data file
+        tokio::fs::remove_file(dir.path().join("user").join("user.dat"))
+            .await
+            .unwrap();
+        let john = Condition::Equal("name".to_string(), "John".to_string());
+        let rows = data_object
+            .handle_aggregate(&count(Some(john)))
+            .await
+            .unwrap();
+        assert_eq!(rows[0].to_json(), serde_json::json!({ "count": 2 }));
+        let rows = data_object.handle_aggregate(&count(None)).await.unwrap();
+        assert_eq!(rows[0].to_json(), serde_json::json!({ "count": 4 }));
+        let age = Condition::Equal("age".to_string(), "30".to_string());
+        assert!(data_object
+            .handle_aggregate(&count(Some(age)))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_numeric_range_query() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([
+            (
+                "name".to_string(),
+                Definition {
+                    data_type: "String".to_string(),
+                    indexed: false,
+                    optional: false,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+            (
+                "age".to_string(),
+                Definition {
+                    data_type: "Number".to_string(),
+                    indexed: true,
+                    optional: true,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+        ]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, age) in [("1", 4), ("2", 100), ("3", 30), ("4", -5)] {
+            data_object
+                .handle_insert(&user(object_id, "John", Some(age)))
+                .await
+                .unwrap();
+        }
+        let ids = |records: Vec<InsertData>| {
+            records
+                .into_iter()
+                .map(|record| record.object_id)
+                .collect::<Vec<_>>()
+        };
+        let at_least_30 = || Condition::GreaterThanOrEqual("age".to_string(), "30".to_string());
+
+        let mut query = select(at_least_30(), None);
+        query.order_by = Some(OrderBy {
+            field: "age".to_string(),
+            direction: SortDirection::Asc,
+        });
+        let found = data_object.handle_query(&query).await.unwrap();
+        assert_eq!(ids(found), vec!["3", "2"]);
+        query.filter = Some(Condition::LessThan("age".to_string(), "4.5".to_string()));
+        let found = data_object.handle_query(&query).await.unwrap();
+        assert_eq!(ids(found), vec!["4", "1"]);
+        drop(data_object);
+
+        // Index folders without a key format hold the text of numbers as keys, the indexes of
+        // number columns are rebuilt on load
+        let index_path = dir.path().join("user").join("idx");
+        let index_path = index_path.to_str().unwrap();
+        let entries = read_index("age", index_path, IndexKind::Memory)
+            .await
+            .unwrap();
+        remove_index("age", index_path).await.unwrap();
+        let mut index = new_or_load("age", index_path, IndexKind::Memory)
+            .await
+            .unwrap();
+        for ((_, index_id), age) in entries.iter().zip(["-5", "4", "30", "100"]) {
+            index.add_to_index(age, index_id).await.unwrap();
+        }
+        index.save().await.unwrap();
+        drop(index);
+        tokio::fs::remove_file(format!("{}/{}", index_path, KEY_FORMAT_FILE))
+            .await
+            .unwrap();
+        let mut data_object = NoSqlDataObject::load("user", root_dir).await.unwrap();
+        query.filter = Some(at_least_30());
+        let found = data_object.handle_query(&query).await.unwrap();
+        assert_eq!(ids(found), vec!["3", "2"]);
+        assert_eq!(read_key_format(index_path).await, KEY_FORMAT_VERSION);
+
+        // The scan compares the same way once the column has no index
+        let age = Definition {
+            data_type: "Number".to_string(),
+            indexed: false,
+            optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
+        };
+        data_object
+            .handle_alter(&AlterOperation::Modify("age".to_string(), age))
+            .await
+            .unwrap();
+        query.filter = Some(at_least_30());
+        let found = data_object.handle_query(&query).await.unwrap();
+        assert_eq!(ids(found), vec!["3", "2"]);
+        query.filter = Some(Condition::Equal("age".to_string(), "abc".to_string()));
+        assert!(data_object.handle_query(&query).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_int_range_query() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let age = |indexed| Definition {
+            data_type: "Int".to_string(),
+            indexed,
+            optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
+        };
+        let definition = HashMap::from([("age".to_string(), age(true))]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, age) in [("1", 2), ("2", 3), ("3", 9), ("4", 10)] {
+            data_object
+                .handle_insert(&user(object_id, "John", Some(age)))
+                .await
+                .unwrap();
+        }
+        let mut query = select(
+            Condition::GreaterThan("age".to_string(), "2.5".to_string()),
+            None,
+        );
+        query.order_by = Some(OrderBy {
+            field: "age".to_string(),
+            direction: SortDirection::Asc,
+        });
+        let conditions = || {
+            [
+                (
+                    Condition::GreaterThan("age".to_string(), "2.5".to_string()),
+                    vec!["2", "3", "4"],
+                ),
+                (
+                    Condition::GreaterThanOrEqual("age".to_string(), "2.5".to_string()),
+                    vec!["2", "3", "4"],
+                ),
+                (
+                    Condition::LessThan("age".to_string(), "9.5".to_string()),
+                    vec!["1", "2", "3"],
+                ),
+                (
+                    Condition::LessThanOrEqual("age".to_string(), "9.5".to_string()),
+                    vec!["1", "2", "3"],
+                ),
+                (
+                    Condition::GreaterThan("age".to_string(), "9.0".to_string()),
+                    vec!["4"],
+                ),
+                (
+                    Condition::LessThan("age".to_string(), "1e30".to_string()),
+                    vec!["1", "2", "3", "4"],
+                ),
+                (
+                    Condition::GreaterThan("age".to_string(), "1e30".to_string()),
+                    vec![],
+                ),
+                (
+                    Condition::Equal("age".to_string(), "2.5".to_string()),
+                    vec![],
+                ),
+                (
+                    Condition::Equal("age".to_string(), "3.0".to_string()),
+                    vec!["2"],
+                ),
+            ]
+        };
+
+        // The index and the scan round fractional bounds the same way
+        for indexed in [true, false] {
+            if !indexed {
+                data_object
+                    .handle_alter(&AlterOperation::Modify("age".to_string(), age(false)))
+                    .await
+                    .unwrap();
+            }
+            for (condition, expected) in conditions() {
+                query.filter = Some(condition);
+                let found = data_object.handle_query(&query).await.unwrap();
+                let ids = found
+                    .into_iter()
+                    .map(|record| record.object_id)
+                    .collect::<Vec<_>>();
+                assert_eq!(ids, expected, "{:?} indexed {}", query.filter, indexed);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_type_validation() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([
+            (
+                "name".to_string(),
+                Definition {
+                    data_type: "String".to_string(),
+                    indexed: true,
+                    optional: false,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+            (
+                "age".to_string(),
+                Definition {
+                    data_type: "Int".to_string(),
+                    indexed: false,
+                    optional: true,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+        ]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        data_object
+            .handle_insert(&user("1", "John", Some(30)))
+            .await
+            .unwrap();
+
+        let mut wrong_age = user("2", "Jane", None);
+        if let DataObject::Object(data) = &mut wrong_age.data {
+            data.push(Data {
+                key: "age".to_string(),
+                value: DataObject::String("thirty".to_string()),
+            });
+        }
+        let result = data_object.handle_insert(&wrong_age).await;
+        assert!(matches!(result, Err(DataObjectError::Type(_))));
+
+        let result = data_object
+            .handle_update(
+                &wrong_age,
+                select(
+                    Condition::Equal("name".to_string(), "John".to_string()),
+                    None,
+                ),
+            )
+            .await;
+        assert!(matches!(result, Err(DataObjectError::Type(_))));
+        let found = data_object
+            .handle_query(&select(
+                Condition::Equal("age".to_string(), "30".to_string()),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+
+        // Existing records must match a new type
+        let age = Definition {
+            data_type: "Bool".to_string(),
+            indexed: false,
+            optional: true,
+            index: IndexKind::Memory,
+            unique: false,
+            generated: None,
+        };
+        let result = data_object
+            .handle_alter(&AlterOperation::Modify("age".to_string(), age))
+            .await;
+        assert!(matches!(result, Err(DataObjectError::Alter(_))));
+    }
+
+    #[tokio::test]
+    async fn test_compact() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([(
+            "name".to_string(),
+            Definition {
+                data_type: "String".to_string(),
+                indexed: true,
+                optional: false,
+                index: IndexKind::Memory,
+                unique: false,
+                generated: None,
+            },
+        )]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, name) in [("1", "John"), ("2", "Jack"), ("3", "Jill")] {
+            data_object
+                .handle_insert(&user(object_id, name, None))
+                .await
+                .unwrap();
+        }
+        assert_eq!(data_object.garbage_ratio().await.unwrap(), 0.0);
+        let john = Condition::Equal("name".to_string(), "John".to_string());
+        data_object
+            .handle_update(&user("1", "John", Some(30)), select(john.clone(), None))
+            .await
+            .unwrap();
+        let jack = Condition::Equal("name".to_string(), "Jack".to_string());
+        data_object
+            .handle_delete(&select(jack, None))
+            .await
+            .unwrap();
+        assert!(data_object.garbage_ratio().await.unwrap() > 0.0);
+
+        let data_file = dir.path().join("user").join("user.dat");
+        let before = tokio::fs::metadata(&data_file).await.unwrap().len();
+        let reclaimed = data_object.compact().await.unwrap();
+        let after = tokio::fs::metadata(&data_file).await.unwrap().len();
+        assert_eq!(before - after, reclaimed);
+        assert!(reclaimed > 0);
+        assert_eq!(data_object.garbage_ratio().await.unwrap(), 0.0);
+
+        // The indexes point at the moved records
+        let records = data_object.handle_query(&select(john, None)).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].data, user("1", "John", Some(30)).data);
+        let mut all = select(Condition::Equal("name".to_string(), "-".to_string()), None);
+        all.filter = None;
+        assert_eq!(data_object.handle_query(&all).await.unwrap().len(), 2);
+
+        // A compaction stopped after the swap rebuilds the indexes on load
+        drop(data_object);
+        tokio::fs::write(dir.path().join("user").join("idx").join("name.idx"), [])
+            .await
+            .unwrap();
+        tokio::fs::write(dir.path().join("user").join("user.compacting"), [])
+            .await
+            .unwrap();
+        let data_object = NoSqlDataObject::load("user", root_dir).await.unwrap();
+        let jill = Condition::Equal("name".to_string(), "Jill".to_string());
+        let records = data_object.handle_query(&select(jill, None)).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].object_id, "3");
+        assert!(!dir.path().join("user").join("user.compacting").exists());
+    }
+
+    #[tokio::test]
+    async fn test_reindex() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([
+            (
+                "name".to_string(),
+                Definition {
+                    data_type: "String".to_string(),
+                    indexed: true,
+                    optional: false,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+            (
+                "age".to_string(),
+                Definition {
+                    data_type: "Number".to_string(),
+                    indexed: false,
+                    optional: true,
+                    index: IndexKind::Memory,
+                    unique: false,
+                    generated: None,
+                },
+            ),
+        ]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, name) in [("1", "John"), ("2", "Jack")] {
+            data_object
+                .handle_insert(&user(object_id, name, None))
+                .await
+                .unwrap();
+        }
+        let index_path = dir.path().join("user").join("idx");
+        let jack = Condition::Equal("name".to_string(), "Jack".to_string());
+
+        tokio::fs::remove_file(index_path.join("name.idx"))
+            .await
+            .unwrap();
+        data_object.reindex(Some("name")).await.unwrap();
+        let records = data_object
+            .handle_query(&select(jack.clone(), None))
+            .await
+            .unwrap();
+        assert_eq!(records[0].object_id, "2");
+        assert!(index_path.join("name.idx").exists());
+        assert!(matches!(
+            data_object.reindex(Some("age")).await,
+            Err(DataObjectError::Alter(_))
+        ));
+        drop(data_object);
+
+        // Unreadable or missing index files are rebuilt on load
+        tokio::fs::write(index_path.join("name.idx"), b"garbage")
+            .await
+            .unwrap();
+        tokio::fs::remove_file(index_path.join("object_id.idx"))
+            .await
+            .unwrap();
+        let data_object = NoSqlDataObject::load("user", root_dir).await.unwrap();
+        let records = data_object.handle_query(&select(jack, None)).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].object_id, "2");
+        let object_ids = data_object.index.get(OBJECT_ID).unwrap().entries().await;
+        assert_eq!(object_ids.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_btree_index() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([(
+            "name".to_string(),
+            Definition {
+                data_type: "String".to_string(),
+                indexed: true,
+                optional: false,
+                index: IndexKind::BTree,
+                unique: false,
+                generated: None,
+            },
+        )]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, name) in [("1", "John"), ("2", "Jack"), ("3", "Jill")] {
+            data_object
+                .handle_insert(&user(object_id, name, None))
+                .await
+                .unwrap();
+        }
+        let jack = Condition::Equal("name".to_string(), "Jack".to_string());
+        data_object
+            .handle_delete(&select(jack.clone(), None))
+            .await
+            .unwrap();
+        drop(data_object);
+
+        let data_object = NoSqlDataObject::load("user", root_dir).await.unwrap();
+        let object_ids = |records: Vec<InsertData>| {
+            records
+                .into_iter()
+                .map(|record| record.object_id)
+                .collect::<Vec<_>>()
+        };
+        let starts_with_j = Condition::WildCard(WildCardOperations::StartsWith(
+            "name".to_string(),
+            "J".to_string(),
+        ));
+        let records = data_object
+            .handle_query(&select(starts_with_j, None))
+            .await
+            .unwrap();
+        assert_eq!(object_ids(records), vec!["3", "1"]);
+        let after_jill = Condition::GreaterThan("name".to_string(), "Jill".to_string());
+        let records = data_object
+            .handle_query(&select(after_jill, None))
+            .await
+            .unwrap();
+        assert_eq!(object_ids(records), vec!["1"]);
+        assert!(data_object
+            .handle_query(&select(jack, None))
+            .await
+            .unwrap()
+            .is_empty());
+        drop(data_object);
+        assert!(NoSqlDataObject::check("user", root_dir)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Definitions written with bincode load with in-memory indexes, the B+tree file is
+        // unreadable for them so the index is rebuilt
+        let legacy = HashMap::from([("name".to_string(), ("String".to_string(), true, false))]);
+        tokio::fs::write(
+            dir.path().join("user").join("user.def"),
+            bincode::serialize(&legacy).unwrap(),
+        )
+        .await
+        .unwrap();
+        let data_object = NoSqlDataObject::load("user", root_dir).await.unwrap();
+        assert_eq!(data_object.definition()["name"].index, IndexKind::Memory);
+        let john = Condition::Equal("name".to_string(), "John".to_string());
+        let records = data_object.handle_query(&select(john, None)).await.unwrap();
+        assert_eq!(object_ids(records), vec!["1"]);
+    }
+
+    #[tokio::test]
+    async fn test_hash_index() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([(
+            "name".to_string(),
+            Definition {
+                data_type: "String".to_string(),
+                indexed: true,
+                optional: false,
+                index: IndexKind::Hash,
+                unique: false,
+                generated: None,
+            },
+        )]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        for (object_id, name) in [("1", "John"), ("2", "Jack"), ("3", "Jill")] {
+            data_object
+                .handle_insert(&user(object_id, name, None))
+                .await
+                .unwrap();
+        }
+        drop(data_object);
+
+        let data_object = NoSqlDataObject::load("user", root_dir).await.unwrap();
+        let object_ids = |records: Vec<InsertData>| {
+            records
+                .into_iter()
+                .map(|record| record.object_id)
+                .collect::<Vec<_>>()
+        };
+        let jack = Condition::Equal("name".to_string(), "Jack".to_string());
+        assert!(data_object.is_indexed(&jack));
+        let records = data_object.handle_query(&select(jack, None)).await.unwrap();
+        assert_eq!(object_ids(records), vec!["2"]);
+
+        // Range and wildcard conditions scan the data file
+        let after_jack = Condition::GreaterThan("name".to_string(), "Jack".to_string());
+        assert!(!data_object.is_indexed(&after_jack));
+        let mut records = object_ids(
+            data_object
+                .handle_query(&select(after_jack, None))
+                .await
+                .unwrap(),
+        );
+        records.sort();
+        assert_eq!(records, vec!["1", "3"]);
+        let starts_with_ji = Condition::WildCard(WildCardOperations::StartsWith(
+            "name".to_string(),
+            "Ji".to_string(),
+        ));
+        assert!(!data_object.is_indexed(&starts_with_ji));
+        let records = data_object
+            .handle_query(&select(starts_with_ji, None))
+            .await
+            .unwrap();
+        assert_eq!(object_ids(records), vec!["3"]);
+        assert!(!data_object.index_ordered("name"));
+        drop(data_object);
+        assert!(NoSqlDataObject::check("user", root_dir)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unique_column() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let name = Definition {
+            data_type: "String".to_string(),
+            indexed: true,
+            optional: false,
+            index: IndexKind::Memory,
+            unique: true,
+            generated: None,
+        };
+        let definition = HashMap::from([("name".to_string(), name.clone())]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        data_object
+            .handle_insert(&user("1", "John", None))
+            .await
+            .unwrap();
+        data_object
+            .handle_insert(&user("2", "Jack", None))
+            .await
+            .unwrap();
+        let duplicate_key = |result: Result<(), DataObjectError>| {
+            matches!(result, Err(DataObjectError::DuplicateKey(_)))
+        };
+        assert!(duplicate_key(
+            data_object.handle_insert(&user("3", "John", None)).await
+        ));
+        // The object id is always unique
+        assert!(duplicate_key(
+            data_object.handle_insert(&user("1", "Jill", None)).await
+        ));
+
+        let john = Condition::Equal("name".to_string(), "John".to_string());
+        let jack = Condition::Equal("name".to_string(), "Jack".to_string());
+        assert!(duplicate_key(
+            data_object
+                .handle_update(&user("2", "John", None), select(jack.clone(), None))
+                .await
+        ));
+        data_object
+            .handle_update(&user("1", "John", Some(30)), select(john.clone(), None))
+            .await
+            .unwrap();
+        let everyone = Condition::Or(Box::new(john.clone()), Box::new(jack));
+        assert!(duplicate_key(
+            data_object
+                .handle_update(&user("1", "Jill", None), select(everyone, None))
+                .await
+        ));
+
+        data_object
+            .handle_delete(&select(john, None))
+            .await
+            .unwrap();
+        data_object
+            .handle_insert(&user("3", "John", None))
+            .await
+            .unwrap();
+
+        // A column holding the same value twice can not be made unique
+        let not_unique = Definition {
+            unique: false,
+            generated: None,
+            ..name.clone()
+        };
+        data_object
+            .handle_alter(&AlterOperation::Modify("name".to_string(), not_unique))
+            .await
+            .unwrap();
+        data_object
+            .handle_insert(&user("4", "John", None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            data_object
+                .handle_alter(&AlterOperation::Modify("name".to_string(), name))
+                .await,
+            Err(DataObjectError::DuplicateKey(_))
+        ));
+        assert!(!data_object.definition()["name"].unique);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_records() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([(
+            "name".to_string(),
+            Definition {
+                data_type: "String".to_string(),
+                indexed: true,
+                optional: false,
+                index: IndexKind::Memory,
+                unique: false,
+                generated: None,
+            },
+        )]);
+        let mut data_object = NoSqlDataObject::new("user", root_dir, definition)
+            .await
+            .unwrap();
+        data_object
+            .handle_insert(&user("1", "John", None))
+            .await
+            .unwrap();
+        let john = select(
+            Condition::Equal("name".to_string(), "John".to_string()),
+            None,
+        );
+
+        let data_file = dir.path().join("user").join("user.dat");
+        let data = tokio::fs::read(&data_file).await.unwrap();
+        assert!(data.starts_with(b"QTRC"));
+
+        // A flipped bit in the serialized record
+        let mut flipped = data.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        tokio::fs::write(&data_file, &flipped).await.unwrap();
+        let result = data_object.handle_query(&john).await;
+        assert!(matches!(result, Err(DataObjectError::Corrupt(_))));
+        assert!(matches!(
+            data_object.scan_records().await,
+            Err(DataObjectError::Corrupt(_))
+        ));
+
+        // A damaged length in the header is rejected before the record is read
+        let mut damaged = data.clone();
+        damaged[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+        tokio::fs::write(&data_file, &damaged).await.unwrap();
+        let result = data_object.handle_query(&john).await;
+        assert!(matches!(result, Err(DataObjectError::Corrupt(_))));
+        assert!(matches!(
+            data_object.scan_records().await,
+            Err(DataObjectError::Corrupt(_))
+        ));
+
+        // A record cut short
+        tokio::fs::write(&data_file, &data[..data.len() - 1])
+            .await
+            .unwrap();
+        let result = data_object.handle_query(&john).await;
+        assert!(matches!(result, Err(DataObjectError::Corrupt(_))));
+
+        // An index pointing past the records
+        tokio::fs::write(&data_file, &data[..5]).await.unwrap();
+        let result = data_object.handle_query(&john).await;
+        assert!(matches!(result, Err(DataObjectError::Corrupt(_))));
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_legacy_data_file() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+        let definition = HashMap::from([(
+            "name".to_string(),
+            Definition {
+                data_type: "String".to_string(),
+                indexed: true,
+                optional: false,
+                index: IndexKind::Memory,
+                unique: false,
+                generated: None,
+            },
+        )]);
+        drop(
+            NoSqlDataObject::new("user", root_dir, definition)
+                .await
+                .unwrap(),
+        );
+
+        // Records prefixed only with their length, the second one was deleted
+        let mut deleted = user("2", "Jack", None);
+        deleted.active = false;
+        let mut legacy = vec![];
+        for record in [user("1", "John", Some(30)), deleted] {
+            let data = bincode::serialize(&record).unwrap();
+            legacy.extend_from_slice(&data.len().to_be_bytes());
+            legacy.extend(data);
+        }
+        let data_file = dir.path().join("user").join("user.dat");
+        tokio::fs::write(&data_file, legacy).await.unwrap();
+
+        let data_object = NoSqlDataObject::load("user", root_dir).await.unwrap();
+        assert!(tokio::fs::read(&data_file)
+            .await
+            .unwrap()
+            .starts_with(b"QTRC"));
+        let john = select(
+            Condition::Equal("name".to_string(), "John".to_string()),
+            None,
+        );
+        let records = data_object.handle_query(&john).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].data, user("1", "John", Some(30)).data);
+        assert_eq!(data_object.scan_records().await.unwrap().len(), 1);
+    }
 }
@@ -1,30 +1,124 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::Path,
+};
 
-use serde::Serialize;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
 use tokio::fs;
 use walkdir::WalkDir;
 
 use crate::{
-    data_object::NoSqlDataObject,
-    parser::{handle_message, Definition, InsertData, Query},
+    data_object::{DataObjectError, DataObjectErrorKind, NoSqlDataObject},
+    parser::{
+        handle_message, AlterOperation, Command, DataObject, Definition, DropTarget, InsertData,
+        Query, SyntaxError, SyntaxErrorCode,
+    },
+    wal::Wal,
 };
 
 pub struct NoSqlDatabase {
     data_objects: HashMap<String, NoSqlDataObject>,
     data_base: String,
     root_path: String,
+    /// Write-ahead log of the mutations of every table of the database
+    wal: Wal,
 }
 
-#[derive(Debug, Serialize)]
-struct Response {
-    data: Option<Vec<InsertData>>,
-    error: Option<String>,
-}
-
-#[derive(Debug, Serialize)]
+/// DataResponse is the envelope sent back for every request
+#[derive(Debug, Serialize, Deserialize)]
 pub enum DataResponse {
+    /// The request succeeded, carries the records produced by the request
     Data(Vec<InsertData>),
-    Error(String),
+    /// Answer to `DESCRIBE`, the definition of the requested tables
+    Definitions(Vec<TableDefinition>),
+    /// Answer to a select with aggregates, one row per group
+    Rows(Vec<DataObject>),
+    /// The request failed
+    Error(ErrorResponse),
+}
+
+/// TableDefinition is the definition of a table as sent to clients
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TableDefinition {
+    pub table: String,
+    pub columns: BTreeMap<String, Definition>,
+}
+
+/// ErrorKind tells the client which part of the server rejected the request
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ErrorKind {
+    /// The command has a syntax error
+    Syntax(SyntaxErrorCode),
+    /// The command could not be parsed
+    Parse,
+    /// The table rejected the operation
+    DataObject(DataObjectErrorKind),
+    /// The database could not be created or loaded
+    Database,
+    /// The database of the request does not exist
+    DatabaseNotFound,
+    /// The database to create already exists
+    DatabaseExists,
+    /// The table of the request does not exist
+    TableNotFound,
+    /// The request frame could not be decoded or the response does not fit in a frame
+    Protocol,
+    /// The command is not supported by the server
+    Unsupported,
+    /// The command does not fit the transaction state of the connection
+    Transaction,
+}
+
+/// ErrorResponse describes why a request failed
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ErrorResponse {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ErrorResponse {
+    pub fn new(kind: ErrorKind, message: String) -> ErrorResponse {
+        ErrorResponse { kind, message }
+    }
+
+    /// Build an error response from a table error, `context` tells which operation failed
+    pub fn data_object(error: &DataObjectError, context: &str) -> ErrorResponse {
+        ErrorResponse::new(
+            ErrorKind::DataObject(error.kind()),
+            format!("{}: {}", context, error),
+        )
+    }
+}
+
+impl From<SyntaxError> for ErrorResponse {
+    fn from(error: SyntaxError) -> Self {
+        let kind = match &error {
+            SyntaxError::SyntaxError(code, _) => ErrorKind::Syntax(code.clone()),
+            SyntaxError::ParseError(_) => ErrorKind::Parse,
+        };
+        ErrorResponse::new(kind, error.to_string())
+    }
+}
+
+impl Display for ErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+impl DataResponse {
+    pub fn error(kind: ErrorKind, message: String) -> DataResponse {
+        DataResponse::Error(ErrorResponse::new(kind, message))
+    }
+
+    fn table_not_found(table: &str) -> DataResponse {
+        DataResponse::error(
+            ErrorKind::TableNotFound,
+            format!("Table {} not found", table),
+        )
+    }
 }
 
 impl NoSqlDatabase {
@@ -34,12 +128,18 @@ impl NoSqlDatabase {
         if path.exists() {
             return Err(format!("Database {} already exists", data_base));
         }
-        fs::create_dir_all(root_path.as_str()).await.unwrap();
+        fs::create_dir_all(root_path.as_str())
+            .await
+            .map_err(|e| format!("Error creating database {}: {}", data_base, e))?;
+        let wal = Wal::open(path)
+            .await
+            .map_err(|e| format!("Error opening WAL of {}: {}", data_base, e))?;
 
         Ok(NoSqlDatabase {
             data_objects: HashMap::new(),
             data_base: data_base.to_string(),
             root_path: data_path.to_string(),
+            wal,
         })
     }
 
@@ -52,6 +152,15 @@ impl NoSqlDatabase {
             ));
         }
 
+        let wal = Wal::open(&path)
+            .await
+            .map_err(|e| format!("Error opening WAL of {}: {}", database, e))?;
+        // Mutations that did not commit before the server stopped are undone
+        let intents = wal
+            .pending()
+            .await
+            .map_err(|e| format!("Error reading WAL of {}: {}", database, e))?;
+
         let mut data_objects = HashMap::new();
         for entry in WalkDir::new(path.clone()).max_depth(1) {
             let entry = entry.unwrap();
@@ -61,17 +170,31 @@ impl NoSqlDatabase {
             }
             if entry.file_type().is_dir() {
                 let table = entry.file_name().to_str().unwrap().to_string();
-                let data_object = NoSqlDataObject::load(&table, path.to_str().unwrap())
-                    .await
-                    .unwrap();
-                data_objects.insert(table, data_object);
+                let table_intents = intents
+                    .iter()
+                    .filter(|intent| intent.table == table)
+                    .collect::<Vec<_>>();
+                let data_object = if table_intents.is_empty() {
+                    NoSqlDataObject::load(&table, path.to_str().unwrap())
+                        .await
+                        .map_err(|e| format!("Error loading table {}: {}", table, e))?
+                } else {
+                    NoSqlDataObject::recover(&table, path.to_str().unwrap(), &table_intents)
+                        .await
+                        .map_err(|e| format!("Error recovering table {}: {}", table, e))?
+                };
+                data_objects.insert(table, data_object.with_wal(wal.clone()));
             }
         }
+        wal.clear()
+            .await
+            .map_err(|e| format!("Error clearing WAL of {}: {}", database, e))?;
 
         Ok(NoSqlDatabase {
             data_objects,
             data_base: database.to_string(),
             root_path: root_dir.to_string(),
+            wal,
         })
     }
 
@@ -89,7 +212,7 @@ impl NoSqlDatabase {
             }
             if entry.file_type().is_dir() {
                 let database = entry.file_name().to_str().unwrap().to_string();
-                let database = NoSqlDatabase::load(root_dir, &database).await.unwrap();
+                let database = NoSqlDatabase::load(root_dir, &database).await?;
                 databases.insert(database.data_base.clone(), database);
             }
         }
@@ -111,16 +234,67 @@ impl NoSqlDatabase {
                 crate::parser::Command::Delete(delete_query) => {
                     self.handle_delete(delete_query).await
                 }
-                crate::parser::Command::Create(_) => DataResponse::Error(
+                crate::parser::Command::Create(_) => DataResponse::error(
+                    ErrorKind::Unsupported,
                     "Something went wrong, create should not come here ".to_string(),
                 ),
                 crate::parser::Command::Define(_, table, definition) => {
                     self.handle_definition(table, definition).await
                 }
-                crate::parser::Command::Alter => todo!(),
-                crate::parser::Command::Drop => todo!(),
+                crate::parser::Command::Alter(_, table, operation) => {
+                    self.handle_alter(table, operation).await
+                }
+                crate::parser::Command::Drop(DropTarget::Table(_, table)) => {
+                    self.handle_drop_table(table).await
+                }
+                crate::parser::Command::Drop(DropTarget::Database(_)) => DataResponse::error(
+                    ErrorKind::Unsupported,
+                    "Something went wrong, drop database should not come here ".to_string(),
+                ),
+                crate::parser::Command::Describe(_, table) => self.handle_describe(table),
+                crate::parser::Command::Compact(_, table) => self.handle_compact(table).await,
+                crate::parser::Command::Reindex(_, table, column) => {
+                    self.handle_reindex(table, column).await
+                }
+                crate::parser::Command::Format(_) => DataResponse::error(
+                    ErrorKind::Unsupported,
+                    "FORMAT is only supported on a connection".to_string(),
+                ),
+                crate::parser::Command::Begin(_)
+                | crate::parser::Command::Commit
+                | crate::parser::Command::Rollback => DataResponse::error(
+                    ErrorKind::Unsupported,
+                    "Transactions are only supported on a connection".to_string(),
+                ),
             },
-            Err(e) => DataResponse::Error(format!("Error parsing message: {}", e)),
+            Err(e) => DataResponse::Error(ErrorResponse::from(e)),
+        }
+    }
+
+    /// Remove every file of the database, the database must not be used afterwards
+    pub async fn drop_database(self) -> Result<(), String> {
+        let path = Path::new(&self.root_path).join(&self.data_base);
+        // Close the data and index files before removing them
+        drop(self.data_objects);
+        fs::remove_dir_all(&path)
+            .await
+            .map_err(|e| format!("Error deleting database {}: {}", self.data_base, e))
+    }
+
+    pub async fn handle_drop_table(&mut self, table: String) -> DataResponse {
+        // Removing the table first closes its data and index files
+        if self.data_objects.remove(&table).is_none() {
+            return DataResponse::table_not_found(&table);
+        }
+        let path = Path::new(&self.root_path)
+            .join(&self.data_base)
+            .join(&table);
+        match fs::remove_dir_all(&path).await {
+            Ok(_) => DataResponse::Data(vec![]),
+            Err(e) => DataResponse::error(
+                ErrorKind::Database,
+                format!("Error deleting table {}: {}", table, e),
+            ),
         }
     }
 
@@ -137,10 +311,26 @@ impl NoSqlDatabase {
         .await;
         match data_object {
             Ok(data_object) => {
-                self.data_objects.insert(table, data_object);
+                self.data_objects
+                    .insert(table, data_object.with_wal(self.wal.clone()));
                 DataResponse::Data(vec![])
             }
-            Err(err) => DataResponse::Error(format!("Error creating table: {}", err)),
+            Err(err) => {
+                DataResponse::Error(ErrorResponse::data_object(&err, "Error creating table"))
+            }
+        }
+    }
+
+    pub async fn handle_alter(&mut self, table: String, operation: AlterOperation) -> DataResponse {
+        if let Some(data_object) = self.data_objects.get_mut(&table) {
+            match data_object.handle_alter(&operation).await {
+                Ok(_) => DataResponse::Data(vec![]),
+                Err(e) => {
+                    DataResponse::Error(ErrorResponse::data_object(&e, "Error altering table"))
+                }
+            }
+        } else {
+            DataResponse::table_not_found(&table)
         }
     }
 
@@ -150,10 +340,12 @@ impl NoSqlDatabase {
             let result = data_object.handle_delete(&delete_query).await;
             match result {
                 Ok(_) => DataResponse::Data(vec![]),
-                Err(e) => DataResponse::Error(format!("Error deleting data: {}", e)),
+                Err(e) => {
+                    DataResponse::Error(ErrorResponse::data_object(&e, "Error deleting data"))
+                }
             }
         } else {
-            DataResponse::Error(format!("Table {} not found", table))
+            DataResponse::table_not_found(table)
         }
     }
 
@@ -163,37 +355,214 @@ impl NoSqlDatabase {
             let result = data_object.handle_update(&update_data, query).await;
             match result {
                 Ok(_) => DataResponse::Data(vec![update_data]),
-                Err(e) => DataResponse::Error(format!("Error updating data: {}", e)),
+                Err(e) => {
+                    DataResponse::Error(ErrorResponse::data_object(&e, "Error updating data"))
+                }
             }
         } else {
-            DataResponse::Error(format!("Table {} not found", table))
+            DataResponse::table_not_found(table)
         }
     }
 
-    pub async fn handle_insert(&mut self, insert_data: InsertData) -> DataResponse {
+    pub async fn handle_insert(&mut self, mut insert_data: InsertData) -> DataResponse {
         let table = insert_data.table.as_str();
         if let Some(data_object) = self.data_objects.get_mut(&insert_data.table) {
-            let result = data_object.handle_insert(&insert_data).await;
+            let mut result = data_object.assign_object_id(&mut insert_data).await;
+            if result.is_ok() {
+                result = data_object.handle_insert(&insert_data).await;
+            }
             match result {
                 Ok(_) => DataResponse::Data(vec![insert_data]),
-                Err(e) => DataResponse::Error(format!("Error inserting data: {}", e)),
+                Err(e) => {
+                    DataResponse::Error(ErrorResponse::data_object(&e, "Error inserting data"))
+                }
             }
         } else {
-            DataResponse::Error(format!("Table {} not found", table))
+            DataResponse::table_not_found(table)
+        }
+    }
+
+    /// Assign the object id of an insert buffered by a transaction, the commit writes the record
+    /// with this id
+    pub async fn assign_object_id(
+        &mut self,
+        mut insert_data: InsertData,
+    ) -> Result<InsertData, DataResponse> {
+        let Some(data_object) = self.data_objects.get_mut(&insert_data.table) else {
+            return Err(DataResponse::table_not_found(&insert_data.table));
+        };
+        match data_object.assign_object_id(&mut insert_data).await {
+            Ok(_) => Ok(insert_data),
+            Err(e) => Err(DataResponse::Error(ErrorResponse::data_object(
+                &e,
+                "Error inserting data",
+            ))),
+        }
+    }
+
+    pub async fn handle_compact(&mut self, table: String) -> DataResponse {
+        match self.data_objects.get_mut(&table) {
+            Some(data_object) => match data_object.compact().await {
+                Ok(_) => DataResponse::Data(vec![]),
+                Err(e) => {
+                    DataResponse::Error(ErrorResponse::data_object(&e, "Error compacting table"))
+                }
+            },
+            None => DataResponse::table_not_found(&table),
+        }
+    }
+
+    pub async fn handle_reindex(&mut self, table: String, column: Option<String>) -> DataResponse {
+        match self.data_objects.get_mut(&table) {
+            Some(data_object) => match data_object.reindex(column.as_deref()).await {
+                Ok(_) => DataResponse::Data(vec![]),
+                Err(e) => {
+                    DataResponse::Error(ErrorResponse::data_object(&e, "Error rebuilding index"))
+                }
+            },
+            None => DataResponse::table_not_found(&table),
+        }
+    }
+
+    /// Names of the tables of the database
+    pub fn tables(&self) -> Vec<String> {
+        self.data_objects.keys().cloned().collect()
+    }
+
+    /// Compact the table when its data file holds at least `min_garbage` of inactive records
+    pub async fn compact_table(&mut self, table: &str, min_garbage: f64) {
+        let Some(data_object) = self.data_objects.get_mut(table) else {
+            return;
+        };
+        match data_object.garbage_ratio().await {
+            Ok(ratio) if ratio > 0.0 && ratio >= min_garbage => match data_object.compact().await {
+                Ok(reclaimed) => debug!(
+                    "Compacted {}.{}, {} bytes reclaimed",
+                    self.data_base, table, reclaimed
+                ),
+                Err(e) => error!("Error compacting {}.{}: {}", self.data_base, table, e),
+            },
+            Ok(_) => {}
+            Err(e) => error!("Error reading {}.{}: {}", self.data_base, table, e),
+        }
+    }
+
+    /// Apply the writes buffered by a transaction as one change. When a write fails the writes
+    /// already applied are undone in every table they touched and the error of the write is
+    /// returned. On success the records of the inserts and updates are returned.
+    pub async fn handle_transaction(&mut self, commands: Vec<Command>) -> DataResponse {
+        if let Err(e) = self.wal.begin().await {
+            return DataResponse::error(
+                ErrorKind::Database,
+                format!("Error beginning transaction: {}", e),
+            );
+        }
+        let mut records = vec![];
+        for command in commands {
+            let response = match command {
+                Command::Insert(insert_data) => self.handle_insert(insert_data).await,
+                Command::Update(insert_data, query) => self.handle_update(insert_data, query).await,
+                Command::Delete(query) => self.handle_delete(query).await,
+                command => DataResponse::error(
+                    ErrorKind::Transaction,
+                    format!("{:?} can not be part of a transaction", command),
+                ),
+            };
+            match response {
+                DataResponse::Data(data) => records.extend(data),
+                DataResponse::Error(e) => {
+                    self.rollback().await;
+                    return DataResponse::Error(e);
+                }
+                _ => {}
+            }
+        }
+        match self.wal.commit().await {
+            Ok(_) => DataResponse::Data(records),
+            Err(e) => {
+                self.rollback().await;
+                DataResponse::error(
+                    ErrorKind::Database,
+                    format!("Error committing transaction: {}", e),
+                )
+            }
+        }
+    }
+
+    /// Undo the writes of a transaction that could not be applied. When a table can not be
+    /// restored the log keeps the writes so they are undone when the database is loaded.
+    async fn rollback(&mut self) {
+        let Some(intents) = self.wal.rollback().await else {
+            return;
+        };
+        let mut undone = true;
+        for (table, data_object) in self.data_objects.iter_mut() {
+            let table_intents = intents
+                .iter()
+                .filter(|intent| &intent.table == table)
+                .collect::<Vec<_>>();
+            if table_intents.is_empty() {
+                continue;
+            }
+            if let Err(e) = data_object.undo(&table_intents).await {
+                error!("Error undoing transaction on {}: {}", table, e);
+                undone = false;
+            }
         }
+        if undone {
+            if let Err(e) = self.wal.clear().await {
+                error!("Error clearing WAL: {}", e);
+            }
+        }
+    }
+
+    pub fn handle_describe(&self, table: Option<String>) -> DataResponse {
+        let mut tables = match table {
+            Some(table) => match self.data_objects.get_key_value(&table) {
+                Some(data_object) => vec![data_object],
+                None => return DataResponse::table_not_found(&table),
+            },
+            None => self.data_objects.iter().collect::<Vec<_>>(),
+        };
+        tables.sort_by_key(|(table, _)| *table);
+        let definitions = tables
+            .into_iter()
+            .map(|(table, data_object)| TableDefinition {
+                table: table.clone(),
+                columns: data_object
+                    .definition()
+                    .iter()
+                    .map(|(column, definition)| (column.clone(), definition.clone()))
+                    .collect(),
+            })
+            .collect();
+        DataResponse::Definitions(definitions)
     }
 
     pub async fn handle_query(&self, query: Query) -> DataResponse {
         if let Some(data_object) = self.data_objects.get(&query.table_name) {
-            let query_data = data_object.handle_query(&query.filter).await;
+            if query.is_aggregate() {
+                return match data_object.handle_aggregate(&query).await {
+                    Ok(rows) => DataResponse::Rows(rows),
+                    Err(e) => {
+                        DataResponse::Error(ErrorResponse::data_object(&e, "Error Quering data"))
+                    }
+                };
+            }
+            let query_data = data_object.handle_query(&query).await;
             match query_data {
                 Ok(data) => {
                     return DataResponse::Data(data);
                 }
-                Err(e) => return DataResponse::Error(format!("Error Quering data {}", e)),
+                Err(e) => {
+                    return DataResponse::Error(ErrorResponse::data_object(
+                        &e,
+                        "Error Quering data",
+                    ))
+                }
             };
         }
-        DataResponse::Error(format!("Table {} not found", query.table_name))
+        DataResponse::table_not_found(&query.table_name)
     }
 }
 
@@ -235,4 +604,205 @@ mod test {
         assert_eq!(database.data_base, loaded_database.data_base);
         assert_eq!(database.root_path, loaded_database.root_path);
     }
+
+    #[tokio::test]
+    async fn test_describe() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+
+        let mut database = NoSqlDatabase::new("test", root_dir).await.unwrap();
+        let response = database
+            .handle_message(
+                r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#,
+            )
+            .await;
+        assert!(matches!(response, DataResponse::Data(_)));
+
+        match database.handle_message("DESCRIBE").await {
+            DataResponse::Definitions(definitions) => {
+                assert_eq!(definitions.len(), 1);
+                assert_eq!(definitions[0].table, "user");
+                let name = definitions[0].columns.get("name").unwrap();
+                assert_eq!(name.data_type, "String");
+                assert!(name.indexed);
+            }
+            other => panic!("Expected definitions but got {:?}", other),
+        }
+        match database.handle_message("DESCRIBE car").await {
+            DataResponse::Error(error) => assert_eq!(error.kind, ErrorKind::TableNotFound),
+            other => panic!("Expected an error but got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generated_ids() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+
+        let mut database = NoSqlDatabase::new("test", root_dir).await.unwrap();
+        let commands = [
+            r#"DEFINE invoice { "id": { "type": "Int", "indexed": false, "optional": false, "generated": "increment" }}"#,
+            r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#,
+        ];
+        for command in commands {
+            assert!(matches!(
+                database.handle_message(command).await,
+                DataResponse::Data(_)
+            ));
+        }
+        let object_id = |response: DataResponse| match response {
+            DataResponse::Data(records) => records[0].object_id.clone(),
+            other => panic!("Expected data but got {:?}", other),
+        };
+        let insert = r#"INSERT INTO invoice {"item":"pen"}"#;
+        assert_eq!(object_id(database.handle_message(insert).await), "1");
+        assert_eq!(object_id(database.handle_message(insert).await), "2");
+        assert_eq!(
+            object_id(
+                database
+                    .handle_message(r#"INSERT INTO invoice {"id":10,"item":"ink"}"#)
+                    .await
+            ),
+            "10"
+        );
+        assert_eq!(object_id(database.handle_message(insert).await), "11");
+        let user_id = object_id(
+            database
+                .handle_message(r#"INSERT INTO user {"name":"John"}"#)
+                .await,
+        );
+        assert!(uuid::Uuid::parse_str(&user_id).is_ok());
+        drop(database);
+
+        // The next incremented id is read from the object id index after a restart
+        let mut database = NoSqlDatabase::load_databases(root_dir)
+            .await
+            .unwrap()
+            .remove("test")
+            .unwrap();
+        assert_eq!(object_id(database.handle_message(insert).await), "12");
+        match database
+            .handle_message("SELECT invoice WHERE item = 'pen'")
+            .await
+        {
+            DataResponse::Data(records) => {
+                let ids = records
+                    .iter()
+                    .map(|record| record.to_document()["id"].clone())
+                    .collect::<Vec<_>>();
+                assert_eq!(ids.len(), 4);
+                assert!(ids.contains(&serde_json::json!(12)));
+            }
+            other => panic!("Expected data but got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+
+        let mut database = NoSqlDatabase::new("test", root_dir).await.unwrap();
+        database
+            .handle_message(
+                r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#,
+            )
+            .await;
+        let table_path = dir.path().join("test").join("user");
+        assert!(table_path.exists());
+
+        let response = database.handle_message("DROP TABLE user").await;
+        assert!(matches!(response, DataResponse::Data(_)));
+        assert!(!table_path.exists());
+        assert!(database.data_objects.is_empty());
+        match database.handle_message("DROP TABLE user").await {
+            DataResponse::Error(error) => assert_eq!(error.kind, ErrorKind::TableNotFound),
+            other => panic!("Expected an error but got {:?}", other),
+        }
+
+        database.drop_database().await.unwrap();
+        assert!(!dir.path().join("test").exists());
+    }
+
+    #[tokio::test]
+    async fn test_recover_from_wal() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+
+        let mut database = NoSqlDatabase::new("test", root_dir).await.unwrap();
+        database
+            .handle_message(
+                r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#,
+            )
+            .await;
+        database
+            .handle_message(r#"INSERT INTO user {"name":"John"}"#)
+            .await;
+        database
+            .handle_message(r#"INSERT INTO user {"name":"Jane"}"#)
+            .await;
+
+        // Mutations inside an open transaction are written but never committed, as if the
+        // server stopped before the commit
+        database.wal.begin().await.unwrap();
+        database
+            .handle_message("UPDATE user {\"name\":\"Jack\"} WHERE name = 'John'")
+            .await;
+        database
+            .handle_message("DELETE FROM user WHERE name = 'Jane'")
+            .await;
+        database
+            .handle_message(r#"INSERT INTO user {"name":"Jill"}"#)
+            .await;
+        match database.handle_message("SELECT user").await {
+            DataResponse::Data(data) => assert_eq!(data.len(), 2),
+            other => panic!("Expected data but got {:?}", other),
+        }
+        drop(database);
+
+        let mut database = NoSqlDatabase::load(root_dir, "test").await.unwrap();
+        let names = |response: DataResponse| match response {
+            DataResponse::Data(data) => {
+                let mut names = data
+                    .iter()
+                    .map(|record| record.data.get_path("name").unwrap().to_string())
+                    .collect::<Vec<_>>();
+                names.sort();
+                names
+            }
+            other => panic!("Expected data but got {:?}", other),
+        };
+        assert_eq!(
+            names(database.handle_message("SELECT user").await),
+            vec!["Jane", "John"]
+        );
+        // The indexes are rebuilt from the restored data file
+        assert_eq!(
+            names(
+                database
+                    .handle_message("SELECT user WHERE name = 'John'")
+                    .await
+            ),
+            vec!["John"]
+        );
+        assert!(names(
+            database
+                .handle_message("SELECT user WHERE name = 'Jill'")
+                .await
+        )
+        .is_empty());
+        assert!(database.wal.pending().await.unwrap().is_empty());
+    }
 }
@@ -0,0 +1,44 @@
+use std::str::FromStr;
+
+/// ResponseFormat is the encoding used for the responses sent on a connection.
+/// A connection starts with `Bincode` and switches with the `FORMAT <name>` command.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResponseFormat {
+    /// `DataResponse` serialized with bincode
+    #[default]
+    Bincode,
+    /// A single JSON document per response
+    Json,
+    /// One JSON document per line, every record on its own line followed by a status line
+    Ndjson,
+}
+
+impl FromStr for ResponseFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_ascii_uppercase().as_str() {
+            "BINCODE" => Ok(ResponseFormat::Bincode),
+            "JSON" => Ok(ResponseFormat::Json),
+            "NDJSON" => Ok(ResponseFormat::Ndjson),
+            _ => Err(format!(
+                "Unknown response format {}, expected BINCODE, JSON or NDJSON",
+                format
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("json".parse(), Ok(ResponseFormat::Json));
+        assert_eq!("NDJSON".parse(), Ok(ResponseFormat::Ndjson));
+        assert_eq!("Bincode".parse(), Ok(ResponseFormat::Bincode));
+        assert!("xml".parse::<ResponseFormat>().is_err());
+    }
+}
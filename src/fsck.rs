@@ -0,0 +1,214 @@
+use std::{fmt::Display, path::Path};
+
+use walkdir::WalkDir;
+
+use crate::{
+    data_object::{Inconsistency, NoSqlDataObject},
+    wal::{Wal, WAL_FILE},
+};
+
+/// FsckReport lists what `fsck` found in every table of a data directory
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub tables: Vec<TableReport>,
+    /// Databases whose write-ahead log holds a mutation that did not commit, it is undone
+    /// when the server loads the database
+    pub pending_wal: Vec<String>,
+}
+
+/// TableReport is the result of checking one table
+#[derive(Debug)]
+pub struct TableReport {
+    pub database: String,
+    pub table: String,
+    /// Inconsistencies between the index files and the data file, an error when the data file
+    /// or the definition can not be read
+    pub result: Result<Vec<Inconsistency>, String>,
+    /// True when the indexes were rebuilt, `result` is then the check of the rebuilt indexes
+    pub rebuilt: bool,
+}
+
+impl FsckReport {
+    /// True when every table is consistent and no mutation waits in a write-ahead log
+    pub fn is_clean(&self) -> bool {
+        self.pending_wal.is_empty()
+            && self.tables.iter().all(
+                |table| matches!(&table.result, Ok(inconsistencies) if inconsistencies.is_empty()),
+            )
+    }
+}
+
+impl Display for FsckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for database in &self.pending_wal {
+            writeln!(
+                f,
+                "{}: the write-ahead log holds a mutation that did not commit",
+                database
+            )?;
+        }
+        for table in &self.tables {
+            let name = format!("{}.{}", table.database, table.table);
+            if table.rebuilt {
+                writeln!(f, "{}: indexes rebuilt", name)?;
+            }
+            match &table.result {
+                Ok(inconsistencies) if inconsistencies.is_empty() => writeln!(f, "{}: ok", name)?,
+                Ok(inconsistencies) => {
+                    for inconsistency in inconsistencies {
+                        writeln!(f, "{}: {}", name, inconsistency)?;
+                    }
+                }
+                Err(error) => writeln!(f, "{}: {}", name, error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check every table of every database under `data_path` the way the server loads them,
+/// without changing any file. With `rebuild` the indexes of inconsistent tables are rebuilt
+/// from their data file and checked again.
+pub async fn fsck(data_path: &str, rebuild: bool) -> Result<FsckReport, String> {
+    let mut report = FsckReport::default();
+    let path = Path::new(data_path);
+    if !path.exists() {
+        return Err(format!("Data path {} does not exist", data_path));
+    }
+    for database in sub_directories(path)? {
+        let database_path = path.join(&database);
+        if wal_pending(&database_path).await? {
+            report.pending_wal.push(database.clone());
+        }
+        let root = database_path.to_str().unwrap();
+        for table in sub_directories(&database_path)? {
+            let mut result = NoSqlDataObject::check(&table, root)
+                .await
+                .map_err(|e| e.to_string());
+            let mut rebuilt = false;
+            if rebuild && matches!(&result, Ok(inconsistencies) if !inconsistencies.is_empty()) {
+                result = match NoSqlDataObject::rebuild(&table, root).await {
+                    Ok(data_object) => {
+                        drop(data_object);
+                        rebuilt = true;
+                        NoSqlDataObject::check(&table, root)
+                            .await
+                            .map_err(|e| e.to_string())
+                    }
+                    Err(e) => Err(format!("Error rebuilding indexes: {}", e)),
+                };
+            }
+            report.tables.push(TableReport {
+                database: database.clone(),
+                table,
+                result,
+                rebuilt,
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Names of the directories directly under the path, sorted
+fn sub_directories(path: &Path) -> Result<Vec<String>, String> {
+    let mut names = vec![];
+    for entry in WalkDir::new(path).min_depth(1).max_depth(1) {
+        let entry = entry.map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        if entry.file_type().is_dir() {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+async fn wal_pending(database_path: &Path) -> Result<bool, String> {
+    if !database_path.join(WAL_FILE).exists() {
+        return Ok(false);
+    }
+    let wal = Wal::open(database_path)
+        .await
+        .map_err(|e| format!("Error opening {}: {}", database_path.display(), e))?;
+    let intents = wal
+        .pending()
+        .await
+        .map_err(|e| format!("Error reading {}: {}", database_path.display(), e))?;
+    Ok(!intents.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempfile::Builder;
+
+    use super::*;
+    use crate::database::NoSqlDatabase;
+
+    #[tokio::test]
+    async fn test_fsck() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let root_dir = dir.path().to_str().unwrap();
+
+        let mut database = NoSqlDatabase::new("xdr", root_dir).await.unwrap();
+        let commands = [
+            r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#,
+            r#"INSERT INTO user {"name":"John"}"#,
+            r#"INSERT INTO user {"name":"Jack"}"#,
+            r#"UPDATE user {"name":"Jill"} WHERE name = 'Jack'"#,
+        ];
+        for command in commands {
+            database.handle_message(command).await;
+        }
+        drop(database);
+
+        let report = fsck(root_dir, false).await.unwrap();
+        assert!(report.is_clean(), "{}", report);
+        assert_eq!(report.tables.len(), 1);
+
+        // Put back the index of the name column from before the update
+        let index_path = dir.path().join("xdr").join("user").join("idx");
+        let index_file = index_path.join("name.idx");
+        let log_file = index_path.join("name.idx.log");
+        let index = tokio::fs::read(&index_file).await.unwrap();
+        let log = tokio::fs::read(&log_file).await.unwrap();
+        let mut database = NoSqlDatabase::load_databases(root_dir)
+            .await
+            .unwrap()
+            .remove("xdr")
+            .unwrap();
+        database
+            .handle_message(r#"UPDATE user {"name":"Jane"} WHERE name = 'Jill'"#)
+            .await;
+        drop(database);
+        tokio::fs::write(&index_file, index).await.unwrap();
+        tokio::fs::write(&log_file, log).await.unwrap();
+
+        let report = fsck(root_dir, false).await.unwrap();
+        assert!(!report.is_clean());
+        let inconsistencies = report.tables[0].result.as_ref().unwrap();
+        assert!(inconsistencies.iter().any(|inconsistency| matches!(
+            inconsistency,
+            Inconsistency::Dangling { column, key, .. } if column == "name" && key == "Jill"
+        )));
+        assert!(inconsistencies.iter().any(|inconsistency| matches!(
+            inconsistency,
+            Inconsistency::Missing { column, key, .. } if column == "name" && key == "Jane"
+        )));
+
+        let report = fsck(root_dir, true).await.unwrap();
+        assert!(report.is_clean(), "{}", report);
+        assert!(report.tables[0].rebuilt);
+
+        tokio::fs::remove_file(&index_file).await.unwrap();
+        let report = fsck(root_dir, false).await.unwrap();
+        assert!(matches!(
+            &report.tables[0].result.as_ref().unwrap()[0],
+            Inconsistency::UnreadableIndex { column, .. } if column == "name"
+        ));
+        // Checking never creates files
+        assert!(!index_file.exists());
+    }
+}
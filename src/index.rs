@@ -1,13 +1,25 @@
-use std::{collections::BTreeMap, fmt::Display};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::SeekFrom,
+    ops::Bound,
+    path::{Path, PathBuf},
+};
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use tokio::{
     fs::File,
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+
+use crate::{
+    data_object::RangeOp,
+    parser::{DataObject, DataType, IndexKind, Number},
 };
 
-use crate::data_object::RangeOp;
+pub mod btree;
+pub mod hash;
 
 /// Error type for index operations
 #[derive(Debug)]
@@ -18,6 +30,8 @@ pub enum IndexError {
     Load(String),
     /// Error saving the index to the index file
     Save(String),
+    /// The index can not answer the query
+    Unsupported(String),
 }
 
 impl Display for IndexError {
@@ -26,10 +40,110 @@ impl Display for IndexError {
             IndexError::FileError(e) => write!(f, "File Error: {}", e),
             IndexError::Load(e) => write!(f, "Load Error: {}", e),
             IndexError::Save(e) => write!(f, "Save Error: {}", e),
+            IndexError::Unsupported(e) => write!(f, "Unsupported Query: {}", e),
+        }
+    }
+}
+
+/// Key of a value in the index of a column of the given type. Numbers are encoded so that the
+/// order of their keys is their numeric order, values that do not match the type keep their
+/// text.
+pub fn index_key(data_type: DataType, value: &DataObject) -> String {
+    match (data_type, value) {
+        (DataType::Int, DataObject::Number(Number::Int(number))) => encode_int(*number),
+        (DataType::Number | DataType::Float, DataObject::Number(number)) => {
+            encode_number(number.as_f64())
         }
+        _ => value.to_string(),
+    }
+}
+
+/// Key of a condition literal for the index of a column of the given type, `None` when the
+/// literal is not a value of the type, e.g. `abc` for a number or `2.5` for an integer
+pub fn literal_key(data_type: DataType, literal: &str) -> Option<String> {
+    match data_type {
+        DataType::Int => match literal.parse::<i64>() {
+            Ok(value) => Some(encode_int(value)),
+            // Whole numbers written as floats, e.g. `3.0`
+            Err(_) => literal
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.fract() == 0.0 && int_range(*value))
+                .map(|value| encode_int(value as i64)),
+        },
+        DataType::Number | DataType::Float => literal.parse::<f64>().ok().map(encode_number),
+        DataType::Bool => literal.parse::<bool>().ok().map(|value| value.to_string()),
+        DataType::String | DataType::Array | DataType::Object => Some(literal.to_string()),
     }
 }
 
+/// Key and operator of a range condition on a column of the given type, `None` when no value of
+/// the type is in the range. A fractional bound on an `Int` column is rounded to the integers in
+/// the range, `> 2.5` is `>= 3` and `<= 9.5` is `<= 9`.
+pub fn range_key(data_type: DataType, literal: &str, op: RangeOp) -> Option<(String, RangeOp)> {
+    if data_type != DataType::Int {
+        return literal_key(data_type, literal).map(|key| (key, op));
+    }
+    if let Ok(value) = literal.parse::<i64>() {
+        return Some((encode_int(value), op));
+    }
+    let value = literal
+        .parse::<f64>()
+        .ok()
+        .filter(|value| !value.is_nan())?;
+    let whole = value.fract() == 0.0;
+    let (bound, op) = match op {
+        RangeOp::GreaterThan | RangeOp::GreaterThanOrEqual if !whole => {
+            (value.ceil(), RangeOp::GreaterThanOrEqual)
+        }
+        RangeOp::LessThan | RangeOp::LessThanOrEqual if !whole => {
+            (value.floor(), RangeOp::LessThanOrEqual)
+        }
+        op => (value, op),
+    };
+    if int_range(bound) {
+        return Some((encode_int(bound as i64), op));
+    }
+    // Bounds beyond the integers hold every integer or none
+    let above = bound > 0.0;
+    match op {
+        RangeOp::GreaterThan | RangeOp::GreaterThanOrEqual if above => None,
+        RangeOp::GreaterThan | RangeOp::GreaterThanOrEqual => {
+            Some((encode_int(i64::MIN), RangeOp::GreaterThanOrEqual))
+        }
+        RangeOp::LessThan | RangeOp::LessThanOrEqual if above => {
+            Some((encode_int(i64::MAX), RangeOp::LessThanOrEqual))
+        }
+        RangeOp::LessThan | RangeOp::LessThanOrEqual => None,
+    }
+}
+
+/// Whether the whole number is an `i64`, 2^63 is the first float above every `i64`
+fn int_range(value: f64) -> bool {
+    (-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0).contains(&value)
+}
+
+/// Encode the number as 16 hex digits that sort like the numbers: the sign bit is flipped for
+/// positive numbers and every bit is flipped for negative ones. Integers of `Number` columns are
+/// indexed as floats so integers beyond 2^53 lose precision, `Int` columns use [`encode_int`].
+fn encode_number(value: f64) -> String {
+    // -0.0 and 0.0 are the same key
+    let value = if value == 0.0 { 0.0 } else { value };
+    let bits = value.to_bits();
+    let bits = if value.is_sign_negative() {
+        !bits
+    } else {
+        bits | (1 << 63)
+    };
+    format!("{:016x}", bits)
+}
+
+/// Encode the integer as 16 hex digits that sort like the integers: the sign bit is flipped so
+/// negative integers come first
+fn encode_int(value: i64) -> String {
+    format!("{:016x}", (value as u64) ^ (1 << 63))
+}
+
 /// IndexId is a struct that holds the position and length of an object in the data file.
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct IndexId {
@@ -45,15 +159,21 @@ pub trait Index: Send + Sync {
     /// # Arguments
     /// * `key` - The key to look up
     /// # Returns
-    /// * `Option<Vec<IndexId>>` - The object ids for the given key
+    /// * `Result<Option<Vec<IndexId>>, IndexError>` - The object ids for the given key
     ///
     /// # Example
-    /// ```
-    /// let index = IndexImpl::new();
+    /// ```no_run
+    /// # use qtable::data_object::RangeOp;
+    /// # use qtable::index::{new_or_load, Index, IndexError, IndexId};
+    /// # use qtable::parser::IndexKind;
+    /// # async fn example() -> Result<(), IndexError> {
+    /// let index = new_or_load("name", "idx", IndexKind::Memory).await?;
     /// let key = "test";
-    /// let result = index.get(key);
+    /// let result = index.get(key).await?;
+    /// # Ok(())
+    /// # }
     /// ```
-    fn get(&self, key: &str) -> Option<&Vec<IndexId>>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<IndexId>>, IndexError>;
 
     /// Add an object id to the index.
     /// If the key already exists, the object id is appended to the list of object ids. If the key does not exist, a new key is created with the object id.
@@ -61,36 +181,49 @@ pub trait Index: Send + Sync {
     /// * `value` - The value to add to the index
     /// * `object_id` - The object id to add to the index value
     /// # Example
-    /// ```
-    /// let index = IndexImpl::new();
+    /// ```no_run
+    /// # use qtable::data_object::RangeOp;
+    /// # use qtable::index::{new_or_load, Index, IndexError, IndexId};
+    /// # use qtable::parser::IndexKind;
+    /// # async fn example() -> Result<(), IndexError> {
+    /// let mut index = new_or_load("name", "idx", IndexKind::Memory).await?;
     /// let value = "test";
     /// let object_id = IndexId { position: 0, length: 1 };
-    /// index.add_to_index(value, object_id);
+    /// index.add_to_index(value, &object_id).await?;
+    /// # Ok(())
+    /// # }
     /// ```
-
-    fn add_to_index(&mut self, value: &str, object_id: &IndexId);
+    async fn add_to_index(&mut self, value: &str, object_id: &IndexId) -> Result<(), IndexError>;
     /// Remove an object id from the index. If the key does not exist, nothing happens.
     /// # Arguments
     /// * `value` - The index value.
     /// * `object_id` - The object id to remove from the index value.
     /// # Example
-    /// ```
-    /// let index = IndexImpl::new();
+    /// ```no_run
+    /// # use qtable::data_object::RangeOp;
+    /// # use qtable::index::{new_or_load, Index, IndexError, IndexId};
+    /// # use qtable::parser::IndexKind;
+    /// # async fn example() -> Result<(), IndexError> {
+    /// let mut index = new_or_load("name", "idx", IndexKind::Memory).await?;
     /// let value = "test";
     /// let object_id = IndexId { position: 0, length: 1 };
-    /// index.remove_from_index(value, object_id);
+    /// index.remove_from_index(value, &object_id).await?;
+    /// # Ok(())
+    /// # }
     /// ```
-    fn remove_from_index(&mut self, value: &str, object_id: &IndexId);
+    async fn remove_from_index(
+        &mut self,
+        value: &str,
+        object_id: &IndexId,
+    ) -> Result<(), IndexError>;
 
     /// Query the index for a value that is equal to the given value. This is an exact match query.
     /// If the value does not exist in the index, an empty vector is returned.
     /// # Arguments
     /// * `value` - The index value to query for
     /// # Returns
-    /// * `Vec<&IndexId>` - The object ids for the given index value
-    /// # Example
-    /// ```
-    fn query_equal(&self, value: &str) -> Vec<&IndexId>;
+    /// * `Result<Vec<IndexId>, IndexError>` - The object ids for the given index value
+    async fn query_equal(&self, value: &str) -> Result<Vec<IndexId>, IndexError>;
 
     /// Query the index for a value that is within a given range. The range is determined by the `op` parameter.
     /// If the value does not exist in the index, an empty vector is returned.
@@ -98,237 +231,491 @@ pub trait Index: Send + Sync {
     /// * `value` - The index value to query for
     /// * `op` - The range operator to use for the query. `op` can be `RangeOp::GreaterThan`, `RangeOp::GreaterThanOrEqual`, `RangeOp::LessThan`, or `RangeOp::LessThanOrEqual`
     /// # Returns
-    /// * `Vec<&IndexId>` - The object ids for the given index value.
+    /// * `Result<Vec<IndexId>, IndexError>` - The object ids for the given index value.
     /// # Example
-    /// ```
-    /// let index = IndexImpl::new();
+    /// ```no_run
+    /// # use qtable::data_object::RangeOp;
+    /// # use qtable::index::{new_or_load, Index, IndexError, IndexId};
+    /// # use qtable::parser::IndexKind;
+    /// # async fn example() -> Result<(), IndexError> {
+    /// let index = new_or_load("name", "idx", IndexKind::Memory).await?;
     /// let value = "test";
     /// let op = RangeOp::GreaterThan;
-    /// let result = index.query_range(value, op);
+    /// let result = index.query_range(value, op).await?;
+    /// # Ok(())
+    /// # }
     /// ```
-
-    fn query_range(&self, value: &str, op: RangeOp) -> Vec<&IndexId>;
+    async fn query_range(&self, value: &str, op: RangeOp) -> Result<Vec<IndexId>, IndexError>;
     /// Query the index for a value that starts with the given prefix. If the value does not exist in the index, an empty vector is returned.
     /// # Arguments
     /// * `prefix` - The prefix to query for
     /// # Returns
-    /// * `Vec<&IndexId>` - The object ids for the given index value.
+    /// * `Result<Vec<IndexId>, IndexError>` - The object ids for the given index value.
     /// # Example
-    /// ```
-    /// let index = IndexImpl::new();
+    /// ```no_run
+    /// # use qtable::data_object::RangeOp;
+    /// # use qtable::index::{new_or_load, Index, IndexError, IndexId};
+    /// # use qtable::parser::IndexKind;
+    /// # async fn example() -> Result<(), IndexError> {
+    /// let index = new_or_load("name", "idx", IndexKind::Memory).await?;
     /// let prefix = "test";
-    /// let result = index.query_prefix(prefix);
+    /// let result = index.query_prefix(prefix).await?;
+    /// # Ok(())
+    /// # }
     /// ```
-    fn query_prefix(&self, prefix: &str) -> Vec<&IndexId>;
+    async fn query_prefix(&self, prefix: &str) -> Result<Vec<IndexId>, IndexError>;
 
     /// Query the index for a value that ends with the given suffix. If the value does not exist in the index, an empty vector is returned.
     /// # Arguments
     /// * `suffix` - The suffix to query for
     /// # Returns
-    /// * `Vec<&IndexId>` - The object ids for the given index value.
+    /// * `Result<Vec<IndexId>, IndexError>` - The object ids for the given index value.
     /// # Example
-    /// ```
-    /// let index = IndexImpl::new();
+    /// ```no_run
+    /// # use qtable::data_object::RangeOp;
+    /// # use qtable::index::{new_or_load, Index, IndexError, IndexId};
+    /// # use qtable::parser::IndexKind;
+    /// # async fn example() -> Result<(), IndexError> {
+    /// let index = new_or_load("name", "idx", IndexKind::Memory).await?;
     /// let suffix = "test";
-    /// let result = index.query_suffix(suffix);
+    /// let result = index.query_suffix(suffix).await?;
+    /// # Ok(())
+    /// # }
     /// ```
-    fn query_suffix(&self, suffix: &str) -> Vec<&IndexId>;
+    async fn query_suffix(&self, suffix: &str) -> Result<Vec<IndexId>, IndexError>;
 
     /// Query the index for a value that contains the given substring. If the value does not exist in the index, an empty vector is returned.
     /// # Arguments
     /// * `substring` - The substring to query for
     /// # Returns
-    /// * `Vec<&IndexId>` - The object ids for the given index value.
+    /// * `Result<Vec<IndexId>, IndexError>` - The object ids for the given index value.
     /// # Example
-    /// ```
-    /// let index = IndexImpl::new();
+    /// ```no_run
+    /// # use qtable::data_object::RangeOp;
+    /// # use qtable::index::{new_or_load, Index, IndexError, IndexId};
+    /// # use qtable::parser::IndexKind;
+    /// # async fn example() -> Result<(), IndexError> {
+    /// let index = new_or_load("name", "idx", IndexKind::Memory).await?;
     /// let substring = "test";
-    /// let result = index.query_contains(substring);
+    /// let result = index.query_contains(substring).await?;
+    /// # Ok(())
+    /// # }
     /// ```
-    fn query_contains(&self, substring: &str) -> Vec<&IndexId>;
+    async fn query_contains(&self, substring: &str) -> Result<Vec<IndexId>, IndexError>;
+
+    /// All object ids of the index in ascending order of their index value
+    /// # Returns
+    /// * `Result<Vec<IndexId>, IndexError>` - The object ids sorted by their index value.
+    async fn query_ordered(&self) -> Result<Vec<IndexId>, IndexError>;
+
+    /// Every entry of the index with its index value, in ascending order of the index value
+    /// # Returns
+    /// * `Result<Vec<(String, IndexId)>, IndexError>` - The index values and their object ids.
+    async fn entries(&self) -> Result<Vec<(String, IndexId)>, IndexError>;
 
-    /// Save the index to the index file. If an error occurs, an IndexError is returned.
+    /// Save the changes made since the last save to the index files. If an error occurs, an IndexError is returned.
     /// # Returns
     /// * `Result<(), IndexError>` - The result of saving the index
     /// # Example
-    /// ```
-    /// let index = IndexImpl::new();
-    /// let result = index.save();
+    /// ```no_run
+    /// # use qtable::data_object::RangeOp;
+    /// # use qtable::index::{new_or_load, Index, IndexError, IndexId};
+    /// # use qtable::parser::IndexKind;
+    /// # async fn example() -> Result<(), IndexError> {
+    /// let mut index = new_or_load("name", "idx", IndexKind::Memory).await?;
+    /// let result = index.save().await;
+    /// # Ok(())
+    /// # }
     /// ```
     async fn save(&mut self) -> Result<(), IndexError>;
 }
 
+/// Changes are appended to the delta log of an index and written to the index file by a
+/// checkpoint once the log is longer than the index file and this many bytes
+const CHECKPOINT_MIN_LOG_LENGTH: u64 = 1 << 20;
+
+/// The delta log starts with the CRC32 of the index file it applies to
+const LOG_HEADER_LENGTH: u64 = std::mem::size_of::<u32>() as u64;
+
+/// Every delta of the log is prefixed with its length
+const DELTA_HEADER_LENGTH: usize = std::mem::size_of::<u32>();
+
+/// A change of the index recorded in the delta log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum Delta {
+    Add(String, IndexId),
+    Remove(String, IndexId),
+}
+
+/// IndexImpl keeps the index in memory, ordered by index value
 pub struct IndexImpl {
     index_map: BTreeMap<String, Vec<IndexId>>, // Attribute Value, Object Ids
-    index_file: File,                          // File to store the index
+    log: DeltaLog,                             // Files to store the index
+}
+
+/// DeltaLog persists an index kept in memory. The index file holds the index as of the last
+/// checkpoint and the delta log `<attribute>.idx.log` the changes made since, so saving an index
+/// only appends its latest changes.
+struct DeltaLog {
+    index_file: PathBuf, // File to store the index
+    index_length: u64,   // Length of the index file
+    log_file: File,      // Changes since the index file was written
+    log_length: u64,     // Length of the delta log
+    pending: Vec<Delta>, // Changes not yet in the delta log
+}
+
+/// What the index files of an attribute hold
+struct Contents {
+    index_map: BTreeMap<String, Vec<IndexId>>,
+    index_length: u64,
+    checksum: u32,
+    /// End of the last complete delta, `None` when the delta log is missing or belongs to an
+    /// index file a checkpoint replaced
+    log_length: Option<u64>,
+}
+
+fn log_path(index_file: &Path) -> PathBuf {
+    let mut log_file = index_file.as_os_str().to_owned();
+    log_file.push(".log");
+    PathBuf::from(log_file)
 }
 
-pub async fn new_or_load(attribute: &str, parent_path: &str) -> Result<Box<dyn Index>, IndexError> {
-    let index_file = format!("{}/{}.idx", parent_path, attribute);
-    if tokio::fs::metadata(&index_file).await.is_err() {
-        // Index file does not exist yet so we create it
-        let file = File::create(&index_file).await;
-        let _ = file.map_err(IndexError::FileError)?;
+/// Open the index of the attribute in the folder, its files are created when they do not exist
+pub async fn new_or_load(
+    attribute: &str,
+    parent_path: &str,
+    kind: IndexKind,
+) -> Result<Box<dyn Index>, IndexError> {
+    let index_file = Path::new(parent_path).join(format!("{}.idx", attribute));
+    match kind {
+        IndexKind::Memory => Ok(Box::new(IndexImpl::new_or_load(index_file).await?)),
+        IndexKind::BTree => Ok(Box::new(btree::BTreeIndex::new_or_load(&index_file).await?)),
+        IndexKind::Hash => Ok(Box::new(hash::HashIndex::new_or_load(index_file).await?)),
     }
+}
 
-    match File::options()
-        .read(true)
-        .write(true)
-        .open(&index_file)
+/// Entries of the index of the attribute, read from its index files without changing them
+pub async fn read_index(
+    attribute: &str,
+    parent_path: &str,
+    kind: IndexKind,
+) -> Result<Vec<(String, IndexId)>, IndexError> {
+    let index_file = Path::new(parent_path).join(format!("{}.idx", attribute));
+    match kind {
+        // A hash index is written in the same format as an in-memory one
+        IndexKind::Memory | IndexKind::Hash => Ok(read_contents(&index_file)
+            .await?
+            .index_map
+            .into_iter()
+            .flat_map(|(key, object_ids)| {
+                object_ids
+                    .into_iter()
+                    .map(move |object_id| (key.clone(), object_id))
+            })
+            .collect()),
+        // Opening a B+tree only reads its header
+        IndexKind::BTree => {
+            btree::BTreeIndex::new_or_load(&index_file)
+                .await?
+                .entries()
+                .await
+        }
+    }
+}
+
+/// Delete the index file and the delta log of the attribute, missing files are ignored
+pub async fn remove_index(attribute: &str, parent_path: &str) -> Result<(), IndexError> {
+    let index_file = Path::new(parent_path).join(format!("{}.idx", attribute));
+    for file in [log_path(&index_file), index_file] {
+        match tokio::fs::remove_file(&file).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(IndexError::FileError(e)),
+        }
+    }
+    Ok(())
+}
+
+/// Read the index file and replay the deltas of its delta log
+async fn read_contents(index_file: &Path) -> Result<Contents, IndexError> {
+    let buffer = tokio::fs::read(index_file)
         .await
-    {
-        Ok(mut file) => {
-            match file.metadata().await {
-                Ok(metadata) => {
-                    // If the file is empty, we can return a new IndexManager
-                    if metadata.len() == 0 {
-                        Ok(Box::new(IndexImpl {
-                            index_file: file,
-                            index_map: BTreeMap::new(),
-                        }))
-                    } else {
-                        let mut buffer = Vec::new();
-                        if let Err(e) = file.read_to_end(&mut buffer).await {
-                            return Err(IndexError::Load(format!(
-                                "Error reading index file: {}",
-                                e
-                            )));
-                        }
-
-                        let index_map =
-                            bincode::deserialize::<BTreeMap<String, Vec<IndexId>>>(&buffer);
-                        match index_map {
-                            Ok(index_map) => Ok(Box::new(IndexImpl {
-                                index_file: file,
-                                index_map,
-                            })),
-                            Err(e) => Err(IndexError::Load(format!(
-                                "Error deserializing index file: {}",
-                                e
-                            ))),
-                        }
-                    }
-                }
-                Err(e) => Err(IndexError::Load(format!(
-                    "Error reading index file metadata: {}",
-                    e
-                ))),
+        .map_err(|e| IndexError::Load(format!("Error reading index file: {}", e)))?;
+    let mut index_map = if buffer.is_empty() {
+        BTreeMap::new()
+    } else {
+        bincode::deserialize::<BTreeMap<String, Vec<IndexId>>>(&buffer)
+            .map_err(|e| IndexError::Load(format!("Error deserializing index file: {}", e)))?
+    };
+    let checksum = crc32fast::hash(&buffer);
+
+    let log = match tokio::fs::read(log_path(index_file)).await {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(IndexError::Load(format!("Error reading delta log: {}", e))),
+    };
+    let header = LOG_HEADER_LENGTH as usize;
+    let log_length = if log.len() < header || log[..header] != checksum.to_be_bytes() {
+        None
+    } else {
+        let mut offset = header;
+        while offset + DELTA_HEADER_LENGTH <= log.len() {
+            let mut length = [0; DELTA_HEADER_LENGTH];
+            length.copy_from_slice(&log[offset..offset + DELTA_HEADER_LENGTH]);
+            let start = offset + DELTA_HEADER_LENGTH;
+            let end = start + u32::from_be_bytes(length) as usize;
+            if end > log.len() {
+                break;
             }
+            let delta = bincode::deserialize::<Delta>(&log[start..end])
+                .map_err(|e| IndexError::Load(format!("Error deserializing delta log: {}", e)))?;
+            apply(&mut index_map, delta);
+            offset = end;
         }
-        Err(_) => todo!(),
-    }
+        Some(offset as u64)
+    };
+    Ok(Contents {
+        index_map,
+        index_length: buffer.len() as u64,
+        checksum,
+        log_length,
+    })
 }
 
-#[async_trait]
-impl Index for IndexImpl {
-    async fn save(&mut self) -> Result<(), IndexError> {
-        let serialized = bincode::serialize(&self.index_map.clone());
-
-        match serialized {
-            Ok(data) => {
-                if let Err(e) = self.index_file.set_len(0).await {
-                    return Err(IndexError::Save(format!(
-                        "Error truncating index file: {}",
-                        e
-                    )));
-                }
-
-                if let Err(e) = self.index_file.seek(tokio::io::SeekFrom::Start(0)).await {
-                    return Err(IndexError::Save(format!("Error seeking index file: {}", e)));
-                }
-                if let Err(e) = self.index_file.write_all(&data).await {
-                    return Err(IndexError::Save(format!("Error writing index file: {}", e)));
-                }
-                Ok(())
+/// Empty the delta log and start it for the index file with the checksum
+async fn reset_log(log_file: &mut File, checksum: u32) -> Result<u64, IndexError> {
+    log_file.set_len(0).await.map_err(IndexError::FileError)?;
+    log_file
+        .seek(SeekFrom::Start(0))
+        .await
+        .map_err(IndexError::FileError)?;
+    log_file
+        .write_all(&checksum.to_be_bytes())
+        .await
+        .map_err(IndexError::FileError)?;
+    log_file.flush().await.map_err(IndexError::FileError)?;
+    Ok(LOG_HEADER_LENGTH)
+}
+
+fn apply(index_map: &mut BTreeMap<String, Vec<IndexId>>, delta: Delta) {
+    match delta {
+        Delta::Add(value, object_id) => index_map.entry(value).or_default().push(object_id),
+        Delta::Remove(value, object_id) => {
+            if let Some(object_ids) = index_map.get_mut(&value) {
+                object_ids.retain(|id| id != &object_id);
             }
-            Err(e) => Err(IndexError::Save(format!("Error serializing index: {}", e))),
         }
     }
+}
 
-    fn get(&self, key: &str) -> Option<&Vec<IndexId>> {
-        self.index_map.get(key)
-    }
+impl DeltaLog {
+    /// Open the index file and its delta log, the index file is created when it does not
+    /// exist. Returns the index with the deltas of the log applied.
+    async fn open(
+        index_file: PathBuf,
+    ) -> Result<(BTreeMap<String, Vec<IndexId>>, DeltaLog), IndexError> {
+        if tokio::fs::metadata(&index_file).await.is_err() {
+            // Index file does not exist yet so we create it
+            let file = File::create(&index_file).await;
+            let _ = file.map_err(IndexError::FileError)?;
+        }
 
-    fn add_to_index(&mut self, value: &str, object_id: &IndexId) {
-        self.index_map
-            .entry(value.to_string())
-            .or_default()
-            .push(object_id.clone());
+        let contents = read_contents(&index_file).await?;
+        let mut log_file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(log_path(&index_file))
+            .await
+            .map_err(IndexError::FileError)?;
+        let log_length = match contents.log_length {
+            // A torn delta at the end of the log is cut off
+            Some(log_length) => {
+                log_file
+                    .set_len(log_length)
+                    .await
+                    .map_err(IndexError::FileError)?;
+                log_length
+            }
+            None => reset_log(&mut log_file, contents.checksum).await?,
+        };
+        let log = DeltaLog {
+            index_file,
+            index_length: contents.index_length,
+            log_file,
+            log_length,
+            pending: vec![],
+        };
+        Ok((contents.index_map, log))
     }
 
-    fn remove_from_index(&mut self, value: &str, object_id: &IndexId) {
-        if let Some(object_ids) = self.index_map.get_mut(value) {
-            object_ids.retain(|id| id != object_id);
-        }
+    /// Record a change applied to the index, it is written by the next save
+    fn record(&mut self, delta: Delta) {
+        self.pending.push(delta);
     }
 
-    fn query_equal(&self, value: &str) -> Vec<&IndexId> {
-        if let Some(object_ids) = self.index_map.get(value) {
-            return object_ids.iter().collect();
+    /// Append the recorded changes to the delta log, and write a checkpoint of the index once
+    /// the log is long enough
+    async fn save(&mut self, index_map: &impl Serialize) -> Result<(), IndexError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut data = vec![];
+        for delta in self.pending.drain(..) {
+            let delta = bincode::serialize(&delta)
+                .map_err(|e| IndexError::Save(format!("Error serializing delta: {}", e)))?;
+            data.extend_from_slice(&(delta.len() as u32).to_be_bytes());
+            data.extend_from_slice(&delta);
+        }
+        if let Err(e) = self.log_file.seek(SeekFrom::Start(self.log_length)).await {
+            return Err(IndexError::Save(format!("Error seeking delta log: {}", e)));
         }
-        vec![]
-    }
-
-    fn query_range(&self, value: &str, op: RangeOp) -> Vec<&IndexId> {
-        let mut range = Vec::new();
-
-        for (key, index_id) in &self.index_map {
-            match op {
-                RangeOp::GreaterThan => {
-                    if key > &value.to_string() {
-                        range.push(index_id);
-                    }
-                }
-                RangeOp::GreaterThanOrEqual => {
-                    if key >= &value.to_string() {
-                        range.push(index_id);
-                    }
-                }
-                RangeOp::LessThan => {
-                    if key < &value.to_string() {
-                        range.push(index_id);
-                    }
-                }
-                RangeOp::LessThanOrEqual => {
-                    if key <= &value.to_string() {
-                        range.push(index_id);
-                    }
-                }
-            };
+        if let Err(e) = self.log_file.write_all(&data).await {
+            return Err(IndexError::Save(format!("Error writing delta log: {}", e)));
         }
-        let mut results = Vec::new();
-        for object_ids in range {
-            results.extend(object_ids);
+        // The write-ahead log is truncated once the write commits, the deltas must be on disk
+        if let Err(e) = self.log_file.sync_data().await {
+            return Err(IndexError::Save(format!("Error syncing delta log: {}", e)));
         }
-        results
+        self.log_length += data.len() as u64;
+
+        if self.log_length > self.index_length.max(CHECKPOINT_MIN_LOG_LENGTH) {
+            self.checkpoint(index_map).await?;
+        }
+        Ok(())
+    }
+
+    /// Write the whole index to the index file and empty the delta log. The index file is
+    /// replaced atomically; until the delta log is reset its checksum no longer matches, so a
+    /// checkpoint stopped half way never replays the deltas twice.
+    async fn checkpoint(&mut self, index_map: &impl Serialize) -> Result<(), IndexError> {
+        let data = bincode::serialize(index_map)
+            .map_err(|e| IndexError::Save(format!("Error serializing index: {}", e)))?;
+        let mut temp_file = self.index_file.as_os_str().to_owned();
+        temp_file.push(".tmp");
+        let mut file = File::create(&temp_file)
+            .await
+            .map_err(|e| IndexError::Save(format!("Error creating index file: {}", e)))?;
+        file.write_all(&data)
+            .await
+            .map_err(|e| IndexError::Save(format!("Error writing index file: {}", e)))?;
+        file.sync_data()
+            .await
+            .map_err(|e| IndexError::Save(format!("Error syncing index file: {}", e)))?;
+        tokio::fs::rename(&temp_file, &self.index_file)
+            .await
+            .map_err(|e| IndexError::Save(format!("Error replacing index file: {}", e)))?;
+        self.index_length = data.len() as u64;
+        self.log_length = reset_log(&mut self.log_file, crc32fast::hash(&data)).await?;
+        Ok(())
     }
+}
 
-    fn query_prefix(&self, prefix: &str) -> Vec<&IndexId> {
-        let mut results = Vec::new();
-        for (_key, object_ids) in self
+impl IndexImpl {
+    async fn new_or_load(index_file: PathBuf) -> Result<IndexImpl, IndexError> {
+        let (index_map, log) = DeltaLog::open(index_file).await?;
+        Ok(IndexImpl { index_map, log })
+    }
+}
+
+#[async_trait]
+impl Index for IndexImpl {
+    async fn save(&mut self) -> Result<(), IndexError> {
+        self.log.save(&self.index_map).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<IndexId>>, IndexError> {
+        Ok(self.index_map.get(key).cloned())
+    }
+
+    async fn add_to_index(&mut self, value: &str, object_id: &IndexId) -> Result<(), IndexError> {
+        let delta = Delta::Add(value.to_string(), object_id.clone());
+        apply(&mut self.index_map, delta.clone());
+        self.log.record(delta);
+        Ok(())
+    }
+
+    async fn remove_from_index(
+        &mut self,
+        value: &str,
+        object_id: &IndexId,
+    ) -> Result<(), IndexError> {
+        let delta = Delta::Remove(value.to_string(), object_id.clone());
+        apply(&mut self.index_map, delta.clone());
+        self.log.record(delta);
+        Ok(())
+    }
+
+    async fn query_equal(&self, value: &str) -> Result<Vec<IndexId>, IndexError> {
+        Ok(self.index_map.get(value).cloned().unwrap_or_default())
+    }
+
+    async fn query_range(&self, value: &str, op: RangeOp) -> Result<Vec<IndexId>, IndexError> {
+        let keys = match op {
+            RangeOp::GreaterThan => self
+                .index_map
+                .range::<str, _>((Bound::Excluded(value), Bound::Unbounded)),
+            RangeOp::GreaterThanOrEqual => self
+                .index_map
+                .range::<str, _>((Bound::Included(value), Bound::Unbounded)),
+            RangeOp::LessThan => self
+                .index_map
+                .range::<str, _>((Bound::Unbounded, Bound::Excluded(value))),
+            RangeOp::LessThanOrEqual => self
+                .index_map
+                .range::<str, _>((Bound::Unbounded, Bound::Included(value))),
+        };
+        Ok(keys
+            .flat_map(|(_, object_ids)| object_ids)
+            .cloned()
+            .collect())
+    }
+
+    async fn query_prefix(&self, prefix: &str) -> Result<Vec<IndexId>, IndexError> {
+        Ok(self
             .index_map
-            .range(prefix.to_string()..)
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
             .take_while(|(k, _)| k.starts_with(prefix))
-        {
-            results.extend(object_ids);
-        }
-        results
+            .flat_map(|(_, object_ids)| object_ids)
+            .cloned()
+            .collect())
     }
 
-    fn query_suffix(&self, suffix: &str) -> Vec<&IndexId> {
-        let mut results = Vec::new();
-        for (_key, object_ids) in self.index_map.iter().filter(|(k, _)| k.ends_with(suffix)) {
-            results.extend(object_ids);
-        }
-        results
+    async fn query_ordered(&self) -> Result<Vec<IndexId>, IndexError> {
+        Ok(self.index_map.values().flatten().cloned().collect())
     }
 
-    fn query_contains(&self, substring: &str) -> Vec<&IndexId> {
-        let mut results = Vec::new();
-        for (_key, object_ids) in self.index_map.iter().filter(|(k, _)| k.contains(substring)) {
-            results.extend(object_ids);
-        }
-        results
+    async fn entries(&self) -> Result<Vec<(String, IndexId)>, IndexError> {
+        Ok(self
+            .index_map
+            .iter()
+            .flat_map(|(key, object_ids)| {
+                object_ids
+                    .iter()
+                    .map(move |object_id| (key.clone(), object_id.clone()))
+            })
+            .collect())
+    }
+
+    async fn query_suffix(&self, suffix: &str) -> Result<Vec<IndexId>, IndexError> {
+        Ok(self
+            .index_map
+            .iter()
+            .filter(|(k, _)| k.ends_with(suffix))
+            .flat_map(|(_, object_ids)| object_ids)
+            .cloned()
+            .collect())
+    }
+
+    async fn query_contains(&self, substring: &str) -> Result<Vec<IndexId>, IndexError> {
+        Ok(self
+            .index_map
+            .iter()
+            .filter(|(k, _)| k.contains(substring))
+            .flat_map(|(_, object_ids)| object_ids)
+            .cloned()
+            .collect())
     }
 }
 
@@ -342,26 +729,43 @@ mod test {
     use super::*;
     use crate::data_object::RangeOp;
 
-    #[test]
-    fn test_add_to_index() {
+    fn test_index(path: &Path) -> IndexImpl {
+        let index_file = path.join("test.idx");
+        std::fs::File::create(&index_file).unwrap();
+        let log_file = log_path(&index_file);
+        std::fs::write(&log_file, crc32fast::hash(&[]).to_be_bytes()).unwrap();
+        IndexImpl {
+            index_map: BTreeMap::new(),
+            log: DeltaLog {
+                log_file: File::from_std(
+                    std::fs::File::options()
+                        .read(true)
+                        .write(true)
+                        .open(log_file)
+                        .unwrap(),
+                ),
+                index_file,
+                index_length: 0,
+                log_length: LOG_HEADER_LENGTH,
+                pending: vec![],
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_to_index() {
         let dir = Builder::new()
             .prefix("data")
             .tempdir()
             .expect("Failed to create temp directory");
         let path = dir.path();
         fs::create_dir_all(path).unwrap();
-        let root_dir = path.parent().unwrap().to_str().unwrap().to_string();
-        let mut index = IndexImpl {
-            index_map: BTreeMap::new(),
-            index_file: File::from_std(
-                std::fs::File::create(format!("{}/test.idx", root_dir)).unwrap(),
-            ),
-        };
+        let mut index = test_index(path);
         let test_1_index_id = IndexId {
             position: 0,
             length: 1,
         };
-        index.add_to_index("test", &test_1_index_id);
+        index.add_to_index("test", &test_1_index_id).await.unwrap();
         let object_ids = index.index_map.get("test").unwrap();
         assert_eq!(object_ids.len(), 1);
         assert_eq!(object_ids[0], test_1_index_id);
@@ -369,49 +773,49 @@ mod test {
             position: 1,
             length: 1,
         };
-        index.add_to_index("test", &test_2_index_id);
+        index.add_to_index("test", &test_2_index_id).await.unwrap();
         let object_ids = index.index_map.get("test").unwrap();
         assert_eq!(object_ids.len(), 2);
         assert_eq!(object_ids[0], test_1_index_id);
         assert_eq!(object_ids[1], test_2_index_id);
     }
 
-    #[test]
-    fn test_remove_from_index() {
+    #[tokio::test]
+    async fn test_remove_from_index() {
         let dir = Builder::new()
             .prefix("data")
             .tempdir()
             .expect("Failed to create temp directory");
         let path = dir.path();
         fs::create_dir_all(path).unwrap();
-        let root_dir = path.parent().unwrap().to_str().unwrap().to_string();
-        let mut index = IndexImpl {
-            index_map: BTreeMap::new(),
-            index_file: File::from_std(
-                std::fs::File::create(format!("{}/test.idx", root_dir)).unwrap(),
-            ),
-        };
+        let mut index = test_index(path);
         let test_1_index_id = IndexId {
             position: 0,
             length: 1,
         };
-        index.add_to_index("test", &test_1_index_id);
+        index.add_to_index("test", &test_1_index_id).await.unwrap();
         let test_2_index_id = IndexId {
             position: 1,
             length: 1,
         };
-        index.add_to_index("test", &test_2_index_id);
-        index.remove_from_index("test", &test_1_index_id);
+        index.add_to_index("test", &test_2_index_id).await.unwrap();
+        index
+            .remove_from_index("test", &test_1_index_id)
+            .await
+            .unwrap();
         let object_ids = index.index_map.get("test").unwrap();
         assert_eq!(object_ids.len(), 1);
         assert_eq!(object_ids[0], test_2_index_id);
-        index.remove_from_index("test", &test_2_index_id);
+        index
+            .remove_from_index("test", &test_2_index_id)
+            .await
+            .unwrap();
         let object_ids = index.index_map.get("test");
         assert_eq!(object_ids.unwrap().len(), 0);
     }
 
-    #[test]
-    fn test_query_equal() {
+    #[tokio::test]
+    async fn test_query_equal() {
         let dir = Builder::new()
             .prefix("data")
             .tempdir()
@@ -420,40 +824,33 @@ mod test {
         let path = dir.path();
         fs::create_dir_all(path).unwrap();
 
-        let root_dir = path.parent().unwrap().to_str().unwrap().to_string();
-
-        let mut index = IndexImpl {
-            index_map: BTreeMap::new(),
-            index_file: File::from_std(
-                std::fs::File::create(format!("{}/test.idx", root_dir)).unwrap(),
-            ),
-        };
+        let mut index = test_index(path);
 
         let test_1_index_id = IndexId {
             position: 0,
             length: 1,
         };
 
-        index.add_to_index("test", &test_1_index_id);
-        index.add_to_index("test", &test_1_index_id);
+        index.add_to_index("test", &test_1_index_id).await.unwrap();
+        index.add_to_index("test", &test_1_index_id).await.unwrap();
 
         let test_2_index_id = IndexId {
             position: 1,
             length: 1,
         };
 
-        index.add_to_index("test2", &test_2_index_id);
+        index.add_to_index("test2", &test_2_index_id).await.unwrap();
 
-        let result = index.query_equal("test");
+        let result = index.query_equal("test").await.unwrap();
         assert_eq!(result.len(), 2);
-        let result = index.query_equal("test2");
+        let result = index.query_equal("test2").await.unwrap();
         assert_eq!(result.len(), 1);
-        let result = index.query_equal("test3");
+        let result = index.query_equal("test3").await.unwrap();
         assert_eq!(result.len(), 0);
     }
 
-    #[test]
-    fn test_start_with() {
+    #[tokio::test]
+    async fn test_start_with() {
         let dir = Builder::new()
             .prefix("data")
             .tempdir()
@@ -462,40 +859,39 @@ mod test {
         let path = dir.path();
         fs::create_dir_all(path).unwrap();
 
-        let root_dir = path.parent().unwrap().to_str().unwrap().to_string();
-
-        let mut index = IndexImpl {
-            index_map: BTreeMap::new(),
-            index_file: File::from_std(
-                std::fs::File::create(format!("{}/test.idx", root_dir)).unwrap(),
-            ),
-        };
+        let mut index = test_index(path);
 
         let test_1_index_id = IndexId {
             position: 0,
             length: 1,
         };
 
-        index.add_to_index("atest", &test_1_index_id);
-        index.add_to_index("atestX", &test_1_index_id);
+        index.add_to_index("atest", &test_1_index_id).await.unwrap();
+        index
+            .add_to_index("atestX", &test_1_index_id)
+            .await
+            .unwrap();
 
         let test_2_index_id = IndexId {
             position: 1,
             length: 1,
         };
 
-        index.add_to_index("another1", &test_2_index_id);
+        index
+            .add_to_index("another1", &test_2_index_id)
+            .await
+            .unwrap();
 
-        let result = index.query_prefix("atest");
+        let result = index.query_prefix("atest").await.unwrap();
         assert_eq!(result.len(), 2);
-        let result = index.query_prefix("test");
+        let result = index.query_prefix("test").await.unwrap();
         assert_eq!(result.len(), 0);
-        let result = index.query_prefix("a");
+        let result = index.query_prefix("a").await.unwrap();
         assert_eq!(result.len(), 3);
     }
 
-    #[test]
-    fn test_ends_with() {
+    #[tokio::test]
+    async fn test_ends_with() {
         let dir = Builder::new()
             .prefix("data")
             .tempdir()
@@ -504,40 +900,36 @@ mod test {
         let path = dir.path();
         fs::create_dir_all(path).unwrap();
 
-        let root_dir = path.parent().unwrap().to_str().unwrap().to_string();
-
-        let mut index = IndexImpl {
-            index_map: BTreeMap::new(),
-            index_file: File::from_std(
-                std::fs::File::create(format!("{}/test.idx", root_dir)).unwrap(),
-            ),
-        };
+        let mut index = test_index(path);
 
         let test_1_index_id = IndexId {
             position: 0,
             length: 1,
         };
 
-        index.add_to_index("test1", &test_1_index_id);
-        index.add_to_index("test2", &test_1_index_id);
+        index.add_to_index("test1", &test_1_index_id).await.unwrap();
+        index.add_to_index("test2", &test_1_index_id).await.unwrap();
 
         let test_2_index_id = IndexId {
             position: 1,
             length: 1,
         };
 
-        index.add_to_index("another1", &test_2_index_id);
+        index
+            .add_to_index("another1", &test_2_index_id)
+            .await
+            .unwrap();
 
-        let result = index.query_suffix("1");
+        let result = index.query_suffix("1").await.unwrap();
         assert_eq!(result.len(), 2);
-        let result = index.query_suffix("2");
+        let result = index.query_suffix("2").await.unwrap();
         assert_eq!(result.len(), 1);
-        let result = index.query_suffix("3");
+        let result = index.query_suffix("3").await.unwrap();
         assert_eq!(result.len(), 0);
     }
 
-    #[test]
-    fn test_contains() {
+    #[tokio::test]
+    async fn test_contains() {
         let dir = Builder::new()
             .prefix("data")
             .tempdir()
@@ -546,40 +938,36 @@ mod test {
         let path = dir.path();
         fs::create_dir_all(path).unwrap();
 
-        let root_dir = path.parent().unwrap().to_str().unwrap().to_string();
-
-        let mut index = IndexImpl {
-            index_map: BTreeMap::new(),
-            index_file: File::from_std(
-                std::fs::File::create(format!("{}/test.idx", root_dir)).unwrap(),
-            ),
-        };
+        let mut index = test_index(path);
 
         let test_1_index_id = IndexId {
             position: 0,
             length: 1,
         };
 
-        index.add_to_index("test1", &test_1_index_id);
-        index.add_to_index("test2", &test_1_index_id);
+        index.add_to_index("test1", &test_1_index_id).await.unwrap();
+        index.add_to_index("test2", &test_1_index_id).await.unwrap();
 
         let test_2_index_id = IndexId {
             position: 1,
             length: 1,
         };
 
-        index.add_to_index("another1", &test_2_index_id);
+        index
+            .add_to_index("another1", &test_2_index_id)
+            .await
+            .unwrap();
 
-        let result = index.query_contains("est");
+        let result = index.query_contains("est").await.unwrap();
         assert_eq!(result.len(), 2);
-        let result = index.query_contains("t");
+        let result = index.query_contains("t").await.unwrap();
         assert_eq!(result.len(), 3);
-        let result = index.query_contains("not");
+        let result = index.query_contains("not").await.unwrap();
         assert_eq!(result.len(), 1);
     }
 
-    #[test]
-    fn test_range() {
+    #[tokio::test]
+    async fn test_range() {
         let dir = Builder::new()
             .prefix("data")
             .tempdir()
@@ -588,37 +976,36 @@ mod test {
         let path = dir.path();
         fs::create_dir_all(path).unwrap();
 
-        let root_dir = path.parent().unwrap().to_str().unwrap().to_string();
-
-        let mut index = IndexImpl {
-            index_map: BTreeMap::new(),
-            index_file: File::from_std(
-                std::fs::File::create(format!("{}/test.idx", root_dir)).unwrap(),
-            ),
-        };
+        let mut index = test_index(path);
 
         let test_1_index_id = IndexId {
             position: 0,
             length: 1,
         };
 
-        index.add_to_index("1", &test_1_index_id);
-        index.add_to_index("2", &test_1_index_id);
+        index.add_to_index("1", &test_1_index_id).await.unwrap();
+        index.add_to_index("2", &test_1_index_id).await.unwrap();
 
         let test_2_index_id = IndexId {
             position: 1,
             length: 1,
         };
 
-        index.add_to_index("4", &test_2_index_id);
+        index.add_to_index("4", &test_2_index_id).await.unwrap();
 
-        let result = index.query_range("2", RangeOp::GreaterThan);
+        let result = index.query_range("2", RangeOp::GreaterThan).await.unwrap();
         assert_eq!(result.len(), 1);
-        let result = index.query_range("2", RangeOp::GreaterThanOrEqual);
+        let result = index
+            .query_range("2", RangeOp::GreaterThanOrEqual)
+            .await
+            .unwrap();
         assert_eq!(result.len(), 2);
-        let result = index.query_range("2", RangeOp::LessThan);
+        let result = index.query_range("2", RangeOp::LessThan).await.unwrap();
         assert_eq!(result.len(), 1);
-        let result = index.query_range("2", RangeOp::LessThanOrEqual);
+        let result = index
+            .query_range("2", RangeOp::LessThanOrEqual)
+            .await
+            .unwrap();
         assert_eq!(result.len(), 2);
     }
 
@@ -630,45 +1017,205 @@ mod test {
             .expect("Failed to create temp directory");
         let path = dir.path();
         fs::create_dir_all(path).unwrap();
-        let root_dir = path.parent().unwrap().to_str().unwrap().to_string();
-        let mut index = IndexImpl {
-            index_map: BTreeMap::new(),
-            index_file: File::from_std(
-                std::fs::File::create(format!("{}/test.idx", root_dir)).unwrap(),
-            ),
-        };
+        let mut index = test_index(path);
         let test_1_index_id = IndexId {
             position: 0,
             length: 1,
         };
-        index.add_to_index("test1", &test_1_index_id);
-        index.add_to_index("tes2", &test_1_index_id);
+        index.add_to_index("test1", &test_1_index_id).await.unwrap();
+        index.add_to_index("tes2", &test_1_index_id).await.unwrap();
         let test_2_index_id = IndexId {
             position: 1,
             length: 1,
         };
-        index.add_to_index("test1", &test_2_index_id);
+        index.add_to_index("test1", &test_2_index_id).await.unwrap();
         let result = index.save().await;
         assert!(result.is_ok());
 
-        let index = new_or_load("test", &root_dir).await;
+        let index = new_or_load("test", path.to_str().unwrap(), IndexKind::Memory).await;
         assert!(index.is_ok());
         let index = index.unwrap();
 
-        let object_id = index.get("test1");
+        let object_id = index.get("test1").await.unwrap();
         assert!(object_id.is_some());
         let object_id = object_id.unwrap();
         assert_eq!(object_id.len(), 2);
         assert_eq!(object_id[0], test_1_index_id);
         assert_eq!(object_id[1], test_2_index_id);
 
-        let object_id = index.get("tes2");
+        let object_id = index.get("tes2").await.unwrap();
         assert!(object_id.is_some());
         let object_id = object_id.unwrap();
         assert_eq!(object_id.len(), 1);
         assert_eq!(object_id[0], test_1_index_id);
 
-        let object_id = index.get("test3");
+        let object_id = index.get("test3").await.unwrap();
         assert!(object_id.is_none());
     }
+
+    #[tokio::test]
+    async fn test_delta_log() {
+        let dir = Builder::new()
+            .prefix("index")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let path = dir.path();
+        let id = |position| IndexId {
+            position,
+            length: 1,
+        };
+        let mut index = test_index(path);
+        index.add_to_index("a", &id(0)).await.unwrap();
+        index.add_to_index("b", &id(1)).await.unwrap();
+        index.remove_from_index("a", &id(0)).await.unwrap();
+        index.save().await.unwrap();
+        // Only the deltas were written
+        assert_eq!(fs::metadata(path.join("test.idx")).unwrap().len(), 0);
+
+        let loaded = new_or_load("test", path.to_str().unwrap(), IndexKind::Memory)
+            .await
+            .unwrap();
+        assert_eq!(
+            loaded.entries().await.unwrap(),
+            vec![("b".to_string(), id(1))]
+        );
+        drop(loaded);
+
+        // A checkpoint stopped before the delta log was reset does not replay it twice
+        let log_file = path.join("test.idx.log");
+        let log = fs::read(&log_file).unwrap();
+        index.log.checkpoint(&index.index_map).await.unwrap();
+        assert_eq!(fs::metadata(&log_file).unwrap().len(), LOG_HEADER_LENGTH);
+        fs::write(&log_file, log).unwrap();
+        let mut loaded = new_or_load("test", path.to_str().unwrap(), IndexKind::Memory)
+            .await
+            .unwrap();
+        assert_eq!(
+            loaded.entries().await.unwrap(),
+            vec![("b".to_string(), id(1))]
+        );
+
+        // A torn delta at the end of the log is cut off
+        loaded.add_to_index("c", &id(2)).await.unwrap();
+        loaded.save().await.unwrap();
+        drop(loaded);
+        let mut log = fs::read(&log_file).unwrap();
+        log.extend_from_slice(&[0, 0, 0, 50, 1]);
+        fs::write(&log_file, log).unwrap();
+        let mut loaded = new_or_load("test", path.to_str().unwrap(), IndexKind::Memory)
+            .await
+            .unwrap();
+        loaded.add_to_index("d", &id(3)).await.unwrap();
+        loaded.save().await.unwrap();
+        let loaded = new_or_load("test", path.to_str().unwrap(), IndexKind::Memory)
+            .await
+            .unwrap();
+        assert_eq!(
+            loaded.entries().await.unwrap(),
+            vec![
+                ("b".to_string(), id(1)),
+                ("c".to_string(), id(2)),
+                ("d".to_string(), id(3))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_numeric_keys() {
+        let dir = Builder::new()
+            .prefix("index")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let mut index = new_or_load("age", dir.path().to_str().unwrap(), IndexKind::Memory)
+            .await
+            .unwrap();
+        let ages = [
+            Number::Int(4),
+            Number::Int(100),
+            Number::Int(30),
+            Number::Float(-2.5),
+            Number::Int(-10),
+            Number::Float(30.5),
+        ];
+        for (position, age) in ages.iter().enumerate() {
+            let key = index_key(DataType::Number, &DataObject::Number(age.clone()));
+            index
+                .add_to_index(
+                    &key,
+                    &IndexId {
+                        position: position as u64,
+                        length: 1,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        let positions = |ids: Vec<IndexId>| ids.iter().map(|id| id.position).collect::<Vec<_>>();
+
+        let thirty = literal_key(DataType::Number, "30").unwrap();
+        assert_eq!(
+            positions(
+                index
+                    .query_range(&thirty, RangeOp::GreaterThanOrEqual)
+                    .await
+                    .unwrap()
+            ),
+            vec![2, 5, 1]
+        );
+        let zero = literal_key(DataType::Number, "-0").unwrap();
+        assert_eq!(
+            positions(index.query_range(&zero, RangeOp::LessThan).await.unwrap()),
+            vec![4, 3]
+        );
+        assert_eq!(
+            positions(index.query_equal(&thirty).await.unwrap()),
+            vec![2]
+        );
+        assert_eq!(
+            positions(index.query_ordered().await.unwrap()),
+            vec![4, 3, 0, 2, 5, 1]
+        );
+        assert_eq!(literal_key(DataType::Float, "abc"), None);
+        assert_eq!(
+            literal_key(DataType::Bool, "true"),
+            Some("true".to_string())
+        );
+    }
+
+    #[test]
+    fn test_int_keys() {
+        let key = |value: i64| index_key(DataType::Int, &DataObject::Number(Number::Int(value)));
+        let large = 1_i64 << 53;
+        assert_ne!(key(large), key(large + 1));
+        assert!(key(large) < key(large + 1));
+        assert!(key(i64::MIN) < key(-1));
+        assert!(key(-1) < key(0));
+        assert!(key(i64::MAX - 1) < key(i64::MAX));
+        assert_eq!(
+            literal_key(DataType::Int, "9007199254740993"),
+            Some(key(large + 1))
+        );
+        assert_eq!(literal_key(DataType::Int, "-0"), Some(key(0)));
+        assert_eq!(literal_key(DataType::Int, "2.5"), None);
+        assert_eq!(literal_key(DataType::Int, "3.0"), Some(key(3)));
+
+        let range = |literal, op| range_key(DataType::Int, literal, op);
+        assert!(matches!(
+            range("2.5", RangeOp::GreaterThan),
+            Some((bound, RangeOp::GreaterThanOrEqual)) if bound == key(3)
+        ));
+        assert!(matches!(
+            range("-2.5", RangeOp::LessThan),
+            Some((bound, RangeOp::LessThanOrEqual)) if bound == key(-3)
+        ));
+        assert!(matches!(
+            range("4", RangeOp::LessThan),
+            Some((bound, RangeOp::LessThan)) if bound == key(4)
+        ));
+        assert!(range("1e30", RangeOp::GreaterThanOrEqual).is_none());
+        assert!(matches!(
+            range("-1e30", RangeOp::GreaterThan),
+            Some((bound, RangeOp::GreaterThanOrEqual)) if bound == key(i64::MIN)
+        ));
+    }
 }
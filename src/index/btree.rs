@@ -0,0 +1,717 @@
+use std::{cmp::Ordering, collections::HashMap, io::SeekFrom, path::Path};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+
+use super::{Index, IndexError, IndexId};
+use crate::data_object::RangeOp;
+
+/// Every node of the tree is stored in a page of this many bytes
+const PAGE_SIZE: u64 = 8192;
+
+/// Pages kept in memory by an index, least recently used pages are evicted first
+const CACHE_PAGES: usize = 64;
+
+/// Longest index value a B+tree holds, so that a page always holds several entries
+pub const MAX_KEY_LENGTH: usize = 1024;
+
+const MAGIC: [u8; 4] = *b"QTBT";
+const VERSION: u8 = 1;
+
+/// Every page starts with the length of the node it holds
+const PAGE_HEADER_LENGTH: u64 = std::mem::size_of::<u32>() as u64;
+
+/// The first page of the index file holds the header
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Header {
+    version: u8,
+    /// False while pages are written, an index file left unclean is rebuilt
+    clean: bool,
+    root: u64,
+    page_count: u64,
+}
+
+/// An index value and one of the records holding it, entries are unique and sorted by value
+/// then by position
+type Entry = (String, IndexId);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum Node {
+    /// `next` is the page of the next leaf, 0 for the last leaf
+    Leaf { entries: Vec<Entry>, next: u64 },
+    /// Child `i` holds the entries from `keys[i - 1]` up to `keys[i]`
+    Internal {
+        keys: Vec<Entry>,
+        children: Vec<u64>,
+    },
+}
+
+/// What a scan does with an entry
+enum Visit {
+    Take,
+    Skip,
+    Stop,
+}
+
+struct CachedPage {
+    node: Node,
+    dirty: bool,
+    used: u64,
+}
+
+/// Pager reads and writes the pages of the index file through a small cache. Changed pages are
+/// written when they are evicted or when the index is saved.
+struct Pager {
+    file: File,
+    header: Header,
+    cache: HashMap<u64, CachedPage>,
+    tick: u64,
+}
+
+/// BTreeIndex is an index kept in a paged B+tree on disk. Only the pages a query visits are
+/// read, so the index does not have to fit in memory. Emptied pages are not merged, `REINDEX`
+/// rebuilds a compact tree.
+pub struct BTreeIndex {
+    pager: Mutex<Pager>,
+}
+
+fn compare(a: &Entry, b: &Entry) -> Ordering {
+    a.0.cmp(&b.0)
+        .then(a.1.position.cmp(&b.1.position))
+        .then(a.1.length.cmp(&b.1.length))
+}
+
+fn node_size(node: &Node) -> Result<u64, IndexError> {
+    bincode::serialized_size(node)
+        .map(|size| size + PAGE_HEADER_LENGTH)
+        .map_err(|e| IndexError::Save(format!("Error serializing page: {}", e)))
+}
+
+/// Index where the entries are split so that both halves hold about the same number of bytes
+fn split_point(entries: &[Entry]) -> Result<usize, IndexError> {
+    let sizes = entries
+        .iter()
+        .map(bincode::serialized_size)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IndexError::Save(format!("Error serializing entry: {}", e)))?;
+    let half = sizes.iter().sum::<u64>() / 2;
+    let mut total = 0;
+    let mut point = 0;
+    for size in sizes {
+        total += size;
+        point += 1;
+        if total >= half {
+            break;
+        }
+    }
+    Ok(point.clamp(1, entries.len() - 1))
+}
+
+impl BTreeIndex {
+    /// Open the B+tree in the index file, the file is created when it does not exist or is
+    /// empty
+    pub async fn new_or_load(index_file: &Path) -> Result<BTreeIndex, IndexError> {
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(index_file)
+            .await
+            .map_err(IndexError::FileError)?;
+        let length = file.metadata().await.map_err(IndexError::FileError)?.len();
+        let pager = if length == 0 {
+            let mut pager = Pager {
+                file,
+                header: Header {
+                    version: VERSION,
+                    clean: true,
+                    root: 1,
+                    page_count: 2,
+                },
+                cache: HashMap::new(),
+                tick: 0,
+            };
+            let root = Node::Leaf {
+                entries: vec![],
+                next: 0,
+            };
+            pager.write_page(1, &root).await?;
+            pager.write_header().await?;
+            pager
+                .file
+                .sync_data()
+                .await
+                .map_err(IndexError::FileError)?;
+            pager
+        } else {
+            let mut page = vec![0; PAGE_SIZE as usize];
+            file.seek(SeekFrom::Start(0))
+                .await
+                .map_err(IndexError::FileError)?;
+            file.read_exact(&mut page)
+                .await
+                .map_err(|e| IndexError::Load(format!("Error reading index header: {}", e)))?;
+            if page[..MAGIC.len()] != MAGIC {
+                return Err(IndexError::Load(
+                    "The index file is not a B+tree".to_string(),
+                ));
+            }
+            let header = bincode::deserialize::<Header>(&page[MAGIC.len()..])
+                .map_err(|e| IndexError::Load(format!("Error reading index header: {}", e)))?;
+            if header.version != VERSION {
+                return Err(IndexError::Load(format!(
+                    "Unknown B+tree version {}",
+                    header.version
+                )));
+            }
+            if !header.clean {
+                return Err(IndexError::Load(
+                    "The index file was not saved completely".to_string(),
+                ));
+            }
+            if length < header.page_count * PAGE_SIZE {
+                return Err(IndexError::Load(
+                    "The index file is shorter than its pages".to_string(),
+                ));
+            }
+            Pager {
+                file,
+                header,
+                cache: HashMap::new(),
+                tick: 0,
+            }
+        };
+        Ok(BTreeIndex {
+            pager: Mutex::new(pager),
+        })
+    }
+
+    /// Entries from the first one whose value is at least `from`, or from the first entry,
+    /// until `visit` stops the scan
+    async fn scan(
+        &self,
+        from: Option<&str>,
+        mut visit: impl FnMut(&str) -> Visit + Send,
+    ) -> Result<Vec<Entry>, IndexError> {
+        let mut pager = self.pager.lock().await;
+        let (_, mut page) = match from {
+            Some(from) => pager.descend(|key| key.0.as_str() < from).await?,
+            None => pager.descend(|_| false).await?,
+        };
+        let mut results = vec![];
+        while page != 0 {
+            let Node::Leaf { entries, next } = pager.read(page).await? else {
+                return Err(IndexError::Load(format!("Page {} is not a leaf", page)));
+            };
+            for entry in entries {
+                if from.is_some_and(|from| entry.0.as_str() < from) {
+                    continue;
+                }
+                match visit(&entry.0) {
+                    Visit::Take => results.push(entry),
+                    Visit::Skip => {}
+                    Visit::Stop => return Ok(results),
+                }
+            }
+            page = next;
+        }
+        Ok(results)
+    }
+
+    async fn scan_ids(
+        &self,
+        from: Option<&str>,
+        visit: impl FnMut(&str) -> Visit + Send,
+    ) -> Result<Vec<IndexId>, IndexError> {
+        Ok(self
+            .scan(from, visit)
+            .await?
+            .into_iter()
+            .map(|(_, index_id)| index_id)
+            .collect())
+    }
+}
+
+impl Pager {
+    /// Follow the children the keys of each internal node point at, `goes_right` tells
+    /// whether the search goes past a key. Returns the internal pages with the index of the
+    /// child taken, and the leaf reached.
+    async fn descend(
+        &mut self,
+        goes_right: impl Fn(&Entry) -> bool + Send,
+    ) -> Result<(Vec<(u64, usize)>, u64), IndexError> {
+        let mut path = vec![];
+        let mut page = self.header.root;
+        loop {
+            match self.read(page).await? {
+                Node::Leaf { .. } => return Ok((path, page)),
+                Node::Internal { keys, children } => {
+                    let child = keys.partition_point(&goes_right);
+                    path.push((page, child));
+                    page = children[child];
+                }
+            }
+        }
+    }
+
+    async fn insert(&mut self, entry: Entry) -> Result<(), IndexError> {
+        let (mut path, leaf) = self
+            .descend(|key| compare(key, &entry) != Ordering::Greater)
+            .await?;
+        let Node::Leaf { mut entries, next } = self.read(leaf).await? else {
+            return Err(IndexError::Load(format!("Page {} is not a leaf", leaf)));
+        };
+        let position = match entries.binary_search_by(|probe| compare(probe, &entry)) {
+            Ok(_) => return Ok(()),
+            Err(position) => position,
+        };
+        entries.insert(position, entry);
+
+        let mut node = Node::Leaf { entries, next };
+        let mut page = leaf;
+        loop {
+            if node_size(&node)? <= PAGE_SIZE {
+                return self.write(page, node).await;
+            }
+            let (left, separator, right) = self.split(node)?;
+            let right_page = self.allocate();
+            let left = match left {
+                Node::Leaf { entries, .. } => Node::Leaf {
+                    entries,
+                    next: right_page,
+                },
+                internal => internal,
+            };
+            self.write(page, left).await?;
+            self.write(right_page, right).await?;
+
+            match path.pop() {
+                Some((parent, child)) => {
+                    let Node::Internal {
+                        mut keys,
+                        mut children,
+                    } = self.read(parent).await?
+                    else {
+                        return Err(IndexError::Load(format!("Page {} is not internal", parent)));
+                    };
+                    keys.insert(child, separator);
+                    children.insert(child + 1, right_page);
+                    node = Node::Internal { keys, children };
+                    page = parent;
+                }
+                None => {
+                    let root = self.allocate();
+                    let node = Node::Internal {
+                        keys: vec![separator],
+                        children: vec![page, right_page],
+                    };
+                    self.write(root, node).await?;
+                    self.header.root = root;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Split a node that outgrew its page into the left node, the key separating them and the
+    /// right node. The right node of a leaf takes over the `next` of the left one.
+    fn split(&self, node: Node) -> Result<(Node, Entry, Node), IndexError> {
+        match node {
+            Node::Leaf { mut entries, next } => {
+                let right = entries.split_off(split_point(&entries)?);
+                let separator = right[0].clone();
+                Ok((
+                    Node::Leaf { entries, next: 0 },
+                    separator,
+                    Node::Leaf {
+                        entries: right,
+                        next,
+                    },
+                ))
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let point = split_point(&keys)?.min(keys.len() - 2);
+                let right_keys = keys.split_off(point + 1);
+                let separator = keys.pop().unwrap();
+                let right_children = children.split_off(point + 1);
+                Ok((
+                    Node::Internal { keys, children },
+                    separator,
+                    Node::Internal {
+                        keys: right_keys,
+                        children: right_children,
+                    },
+                ))
+            }
+        }
+    }
+
+    async fn remove(&mut self, entry: &Entry) -> Result<(), IndexError> {
+        let (_, leaf) = self
+            .descend(|key| compare(key, entry) != Ordering::Greater)
+            .await?;
+        let Node::Leaf { mut entries, next } = self.read(leaf).await? else {
+            return Err(IndexError::Load(format!("Page {} is not a leaf", leaf)));
+        };
+        if let Ok(position) = entries.binary_search_by(|probe| compare(probe, entry)) {
+            entries.remove(position);
+            self.write(leaf, Node::Leaf { entries, next }).await?;
+        }
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> u64 {
+        let page = self.header.page_count;
+        self.header.page_count += 1;
+        page
+    }
+
+    async fn read(&mut self, page: u64) -> Result<Node, IndexError> {
+        self.tick += 1;
+        if let Some(cached) = self.cache.get_mut(&page) {
+            cached.used = self.tick;
+            return Ok(cached.node.clone());
+        }
+        let node = self.read_page(page).await?;
+        self.cache(page, node.clone(), false).await?;
+        Ok(node)
+    }
+
+    /// Replace the node of the page, it is written to the index file on eviction or save
+    async fn write(&mut self, page: u64, node: Node) -> Result<(), IndexError> {
+        if self.header.clean {
+            // The index file is marked unclean before any page changes
+            self.header.clean = false;
+            self.write_header().await?;
+            self.file.sync_data().await.map_err(IndexError::FileError)?;
+        }
+        self.tick += 1;
+        self.cache(page, node, true).await
+    }
+
+    async fn cache(&mut self, page: u64, node: Node, dirty: bool) -> Result<(), IndexError> {
+        if !self.cache.contains_key(&page) && self.cache.len() >= CACHE_PAGES {
+            let evicted = self
+                .cache
+                .iter()
+                .min_by_key(|(_, cached)| cached.used)
+                .map(|(page, _)| *page);
+            if let Some(evicted) = evicted {
+                let cached = self.cache.remove(&evicted).unwrap();
+                if cached.dirty {
+                    self.write_page(evicted, &cached.node).await?;
+                }
+            }
+        }
+        let dirty = dirty || self.cache.get(&page).is_some_and(|cached| cached.dirty);
+        self.cache.insert(
+            page,
+            CachedPage {
+                node,
+                dirty,
+                used: self.tick,
+            },
+        );
+        Ok(())
+    }
+
+    /// Write the changed pages and mark the index file clean
+    async fn flush(&mut self) -> Result<(), IndexError> {
+        if self.header.clean {
+            return Ok(());
+        }
+        let mut dirty = self
+            .cache
+            .iter()
+            .filter(|(_, cached)| cached.dirty)
+            .map(|(page, _)| *page)
+            .collect::<Vec<_>>();
+        dirty.sort();
+        for page in dirty {
+            let node = self.cache[&page].node.clone();
+            self.write_page(page, &node).await?;
+            self.cache.get_mut(&page).unwrap().dirty = false;
+        }
+        self.file.sync_data().await.map_err(IndexError::FileError)?;
+        self.header.clean = true;
+        self.write_header().await
+    }
+
+    async fn read_page(&mut self, page: u64) -> Result<Node, IndexError> {
+        if page == 0 || page >= self.header.page_count {
+            return Err(IndexError::Load(format!(
+                "Page {} is out of the index",
+                page
+            )));
+        }
+        let mut buffer = vec![0; PAGE_SIZE as usize];
+        self.file
+            .seek(SeekFrom::Start(page * PAGE_SIZE))
+            .await
+            .map_err(IndexError::FileError)?;
+        self.file
+            .read_exact(&mut buffer)
+            .await
+            .map_err(|e| IndexError::Load(format!("Error reading page {}: {}", page, e)))?;
+        let mut length = [0; PAGE_HEADER_LENGTH as usize];
+        length.copy_from_slice(&buffer[..PAGE_HEADER_LENGTH as usize]);
+        let end = PAGE_HEADER_LENGTH as usize + u32::from_be_bytes(length) as usize;
+        if end > buffer.len() {
+            return Err(IndexError::Load(format!("Page {} is corrupt", page)));
+        }
+        bincode::deserialize(&buffer[PAGE_HEADER_LENGTH as usize..end])
+            .map_err(|e| IndexError::Load(format!("Error deserializing page {}: {}", page, e)))
+    }
+
+    async fn write_page(&mut self, page: u64, node: &Node) -> Result<(), IndexError> {
+        let data = bincode::serialize(node)
+            .map_err(|e| IndexError::Save(format!("Error serializing page: {}", e)))?;
+        let mut buffer = Vec::with_capacity(PAGE_SIZE as usize);
+        buffer.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(&data);
+        buffer.resize(PAGE_SIZE as usize, 0);
+        self.write_at(page * PAGE_SIZE, &buffer).await
+    }
+
+    async fn write_header(&mut self) -> Result<(), IndexError> {
+        let header = bincode::serialize(&self.header)
+            .map_err(|e| IndexError::Save(format!("Error serializing header: {}", e)))?;
+        let buffer = [MAGIC.to_vec(), header].concat();
+        self.write_at(0, &buffer).await
+    }
+
+    async fn write_at(&mut self, position: u64, data: &[u8]) -> Result<(), IndexError> {
+        self.file
+            .seek(SeekFrom::Start(position))
+            .await
+            .map_err(|e| IndexError::Save(format!("Error seeking index file: {}", e)))?;
+        self.file
+            .write_all(data)
+            .await
+            .map_err(|e| IndexError::Save(format!("Error writing index file: {}", e)))?;
+        self.file
+            .flush()
+            .await
+            .map_err(|e| IndexError::Save(format!("Error writing index file: {}", e)))
+    }
+}
+
+#[async_trait]
+impl Index for BTreeIndex {
+    async fn get(&self, key: &str) -> Result<Option<Vec<IndexId>>, IndexError> {
+        let object_ids = self.query_equal(key).await?;
+        Ok((!object_ids.is_empty()).then_some(object_ids))
+    }
+
+    async fn add_to_index(&mut self, value: &str, object_id: &IndexId) -> Result<(), IndexError> {
+        if value.len() > MAX_KEY_LENGTH {
+            return Err(IndexError::Save(format!(
+                "A B+tree index holds values of up to {} bytes but found {} bytes",
+                MAX_KEY_LENGTH,
+                value.len()
+            )));
+        }
+        self.pager
+            .get_mut()
+            .insert((value.to_string(), object_id.clone()))
+            .await
+    }
+
+    async fn remove_from_index(
+        &mut self,
+        value: &str,
+        object_id: &IndexId,
+    ) -> Result<(), IndexError> {
+        self.pager
+            .get_mut()
+            .remove(&(value.to_string(), object_id.clone()))
+            .await
+    }
+
+    async fn query_equal(&self, value: &str) -> Result<Vec<IndexId>, IndexError> {
+        self.scan_ids(Some(value), |key| match key == value {
+            true => Visit::Take,
+            false => Visit::Stop,
+        })
+        .await
+    }
+
+    async fn query_range(&self, value: &str, op: RangeOp) -> Result<Vec<IndexId>, IndexError> {
+        match op {
+            RangeOp::GreaterThan => {
+                self.scan_ids(Some(value), |key| match key == value {
+                    true => Visit::Skip,
+                    false => Visit::Take,
+                })
+                .await
+            }
+            RangeOp::GreaterThanOrEqual => self.scan_ids(Some(value), |_| Visit::Take).await,
+            RangeOp::LessThan => {
+                self.scan_ids(None, |key| match key < value {
+                    true => Visit::Take,
+                    false => Visit::Stop,
+                })
+                .await
+            }
+            RangeOp::LessThanOrEqual => {
+                self.scan_ids(None, |key| match key <= value {
+                    true => Visit::Take,
+                    false => Visit::Stop,
+                })
+                .await
+            }
+        }
+    }
+
+    async fn query_prefix(&self, prefix: &str) -> Result<Vec<IndexId>, IndexError> {
+        self.scan_ids(Some(prefix), |key| match key.starts_with(prefix) {
+            true => Visit::Take,
+            false => Visit::Stop,
+        })
+        .await
+    }
+
+    async fn query_suffix(&self, suffix: &str) -> Result<Vec<IndexId>, IndexError> {
+        self.scan_ids(None, |key| match key.ends_with(suffix) {
+            true => Visit::Take,
+            false => Visit::Skip,
+        })
+        .await
+    }
+
+    async fn query_contains(&self, substring: &str) -> Result<Vec<IndexId>, IndexError> {
+        self.scan_ids(None, |key| match key.contains(substring) {
+            true => Visit::Take,
+            false => Visit::Skip,
+        })
+        .await
+    }
+
+    async fn query_ordered(&self) -> Result<Vec<IndexId>, IndexError> {
+        self.scan_ids(None, |_| Visit::Take).await
+    }
+
+    async fn entries(&self) -> Result<Vec<(String, IndexId)>, IndexError> {
+        self.scan(None, |_| Visit::Take).await
+    }
+
+    async fn save(&mut self) -> Result<(), IndexError> {
+        self.pager.get_mut().flush().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempfile::Builder;
+
+    use super::*;
+
+    fn id(position: u64) -> IndexId {
+        IndexId {
+            position,
+            length: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_btree_index() {
+        let dir = Builder::new()
+            .prefix("btree")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let index_file = dir.path().join("name.idx");
+        let mut index = BTreeIndex::new_or_load(&index_file).await.unwrap();
+
+        // Enough entries to split leaves and internal nodes and to evict pages
+        let key = |i: u64| format!("{:05}-{}", i % 5000, "x".repeat(100));
+        for i in 0..10000 {
+            index.add_to_index(&key(i), &id(i)).await.unwrap();
+        }
+        index.save().await.unwrap();
+        for i in (0..10000).step_by(2) {
+            index.remove_from_index(&key(i), &id(i)).await.unwrap();
+        }
+        index.save().await.unwrap();
+        drop(index);
+
+        let index = BTreeIndex::new_or_load(&index_file).await.unwrap();
+        let positions = |ids: Vec<IndexId>| ids.iter().map(|id| id.position).collect::<Vec<_>>();
+        assert_eq!(
+            positions(index.query_equal(&key(3)).await.unwrap()),
+            vec![3, 5003]
+        );
+        assert!(index.get(&key(2)).await.unwrap().is_none());
+        assert_eq!(index.query_ordered().await.unwrap().len(), 5000);
+        let entries = index.entries().await.unwrap();
+        assert!(entries
+            .windows(2)
+            .all(|pair| compare(&pair[0], &pair[1]) == Ordering::Less));
+        assert_eq!(
+            positions(
+                index
+                    .query_range(&key(4995), RangeOp::GreaterThan)
+                    .await
+                    .unwrap()
+            ),
+            vec![4997, 9997, 4999, 9999]
+        );
+        assert_eq!(
+            positions(
+                index
+                    .query_range(&key(3), RangeOp::LessThanOrEqual)
+                    .await
+                    .unwrap()
+            ),
+            vec![1, 5001, 3, 5003]
+        );
+        assert_eq!(
+            index.query_prefix("0499").await.unwrap().len(),
+            index
+                .query_range("0499", RangeOp::GreaterThanOrEqual)
+                .await
+                .unwrap()
+                .len()
+        );
+        assert_eq!(
+            positions(index.query_contains("4999").await.unwrap()),
+            vec![4999, 9999]
+        );
+        assert_eq!(index.query_suffix("xx").await.unwrap().len(), 5000);
+    }
+
+    #[tokio::test]
+    async fn test_unclean_index_file() {
+        let dir = Builder::new()
+            .prefix("btree")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let index_file = dir.path().join("name.idx");
+        let mut index = BTreeIndex::new_or_load(&index_file).await.unwrap();
+        index.add_to_index("John", &id(0)).await.unwrap();
+        assert!(index
+            .add_to_index(&"x".repeat(MAX_KEY_LENGTH + 1), &id(1))
+            .await
+            .is_err());
+        // As if the server stopped before the index was saved
+        drop(index);
+        assert!(matches!(
+            BTreeIndex::new_or_load(&index_file).await,
+            Err(IndexError::Load(_))
+        ));
+
+        tokio::fs::write(&index_file, b"not a b+tree")
+            .await
+            .unwrap();
+        assert!(BTreeIndex::new_or_load(&index_file).await.is_err());
+    }
+}
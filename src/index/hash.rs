@@ -0,0 +1,158 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use async_trait::async_trait;
+
+use super::{Delta, DeltaLog, Index, IndexError, IndexId};
+use crate::data_object::RangeOp;
+
+/// HashIndex keeps the index in memory in a hash map. It answers equality queries without
+/// ordering its values, range, wildcard and ordered queries are rejected with
+/// `IndexError::Unsupported`.
+pub struct HashIndex {
+    index_map: HashMap<String, Vec<IndexId>>, // Attribute Value, Object Ids
+    log: DeltaLog,                            // Files to store the index
+}
+
+impl HashIndex {
+    pub async fn new_or_load(index_file: PathBuf) -> Result<HashIndex, IndexError> {
+        let (index_map, log) = DeltaLog::open(index_file).await?;
+        Ok(HashIndex {
+            index_map: index_map
+                .into_iter()
+                .filter(|(_, object_ids)| !object_ids.is_empty())
+                .collect(),
+            log,
+        })
+    }
+
+    fn apply(&mut self, delta: Delta) {
+        match delta {
+            Delta::Add(value, object_id) => {
+                self.index_map.entry(value).or_default().push(object_id)
+            }
+            Delta::Remove(value, object_id) => {
+                if let Some(object_ids) = self.index_map.get_mut(&value) {
+                    object_ids.retain(|id| id != &object_id);
+                    if object_ids.is_empty() {
+                        self.index_map.remove(&value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn unsupported(query: &str) -> IndexError {
+    IndexError::Unsupported(format!("A hash index does not answer {} queries", query))
+}
+
+#[async_trait]
+impl Index for HashIndex {
+    async fn get(&self, key: &str) -> Result<Option<Vec<IndexId>>, IndexError> {
+        Ok(self.index_map.get(key).cloned())
+    }
+
+    async fn add_to_index(&mut self, value: &str, object_id: &IndexId) -> Result<(), IndexError> {
+        let delta = Delta::Add(value.to_string(), object_id.clone());
+        self.apply(delta.clone());
+        self.log.record(delta);
+        Ok(())
+    }
+
+    async fn remove_from_index(
+        &mut self,
+        value: &str,
+        object_id: &IndexId,
+    ) -> Result<(), IndexError> {
+        let delta = Delta::Remove(value.to_string(), object_id.clone());
+        self.apply(delta.clone());
+        self.log.record(delta);
+        Ok(())
+    }
+
+    async fn query_equal(&self, value: &str) -> Result<Vec<IndexId>, IndexError> {
+        Ok(self.index_map.get(value).cloned().unwrap_or_default())
+    }
+
+    async fn query_range(&self, _value: &str, _op: RangeOp) -> Result<Vec<IndexId>, IndexError> {
+        Err(unsupported("range"))
+    }
+
+    async fn query_prefix(&self, _prefix: &str) -> Result<Vec<IndexId>, IndexError> {
+        Err(unsupported("prefix"))
+    }
+
+    async fn query_suffix(&self, _suffix: &str) -> Result<Vec<IndexId>, IndexError> {
+        Err(unsupported("suffix"))
+    }
+
+    async fn query_contains(&self, _substring: &str) -> Result<Vec<IndexId>, IndexError> {
+        Err(unsupported("contains"))
+    }
+
+    async fn query_ordered(&self) -> Result<Vec<IndexId>, IndexError> {
+        Err(unsupported("ordered"))
+    }
+
+    async fn entries(&self) -> Result<Vec<(String, IndexId)>, IndexError> {
+        let mut entries = self
+            .index_map
+            .iter()
+            .flat_map(|(key, object_ids)| {
+                object_ids
+                    .iter()
+                    .map(move |object_id| (key.clone(), object_id.clone()))
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+
+    async fn save(&mut self) -> Result<(), IndexError> {
+        self.log.save(&self.index_map).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempfile::Builder;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hash_index() {
+        let dir = Builder::new()
+            .prefix("hash")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let index_file = dir.path().join("email.idx");
+        let id = |position| IndexId {
+            position,
+            length: 1,
+        };
+        let mut index = HashIndex::new_or_load(index_file.clone()).await.unwrap();
+        index.add_to_index("john@x.com", &id(0)).await.unwrap();
+        index.add_to_index("jack@x.com", &id(1)).await.unwrap();
+        index.add_to_index("john@x.com", &id(2)).await.unwrap();
+        index.remove_from_index("jack@x.com", &id(1)).await.unwrap();
+        index.save().await.unwrap();
+        drop(index);
+
+        let index = HashIndex::new_or_load(index_file).await.unwrap();
+        assert_eq!(
+            index.query_equal("john@x.com").await.unwrap(),
+            vec![id(0), id(2)]
+        );
+        assert!(index.get("jack@x.com").await.unwrap().is_none());
+        assert!(matches!(
+            index.query_range("john", RangeOp::GreaterThan).await,
+            Err(IndexError::Unsupported(_))
+        ));
+        assert!(matches!(
+            index.query_prefix("jo").await,
+            Err(IndexError::Unsupported(_))
+        ));
+        assert_eq!(index.entries().await.unwrap().len(), 2);
+    }
+}
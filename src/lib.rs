@@ -0,0 +1,11 @@
+pub mod aggregate;
+pub mod compactor;
+pub mod config;
+pub mod data_object;
+pub mod database;
+pub mod format;
+pub mod fsck;
+pub mod index;
+pub mod network;
+pub mod parser;
+pub mod wal;
@@ -1,20 +1,29 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use database::NoSqlDatabase;
+use clap::Parser;
+use qtable::{compactor, config, database::NoSqlDatabase, fsck, network};
 use tokio::sync::RwLock;
 
-mod config;
-mod data_object;
-mod database;
-mod index;
-mod network;
-mod parser;
-
 lazy_static::lazy_static! {
     static ref CONFIG: config::ServerConfig = config::ServerConfig::new().unwrap();
 }
 
 fn main() {
+    if let Some(config::Command::Fsck { data_path, rebuild }) = config::Cmd::parse().command {
+        let report = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(fsck::fsck(&data_path, rebuild));
+        match report {
+            Ok(report) => {
+                print!("{}", report);
+                std::process::exit(if report.is_clean() { 0 } else { 1 });
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(2);
+            }
+        }
+    }
     log4rs::init_file("config/log4rs.yml", Default::default()).unwrap();
     tokio::runtime::Builder::new_multi_thread()
         .worker_threads(4)
@@ -28,8 +37,36 @@ async fn start() {
     let data_path = CONFIG.data_path.clone();
     let port = CONFIG.port.unwrap_or(8080);
     let server = network::server::Server::new(port);
-    let databases = NoSqlDatabase::load_databases(&data_path).await.unwrap();
+    let databases = match NoSqlDatabase::load_databases(&data_path).await {
+        Ok(databases) => databases,
+        Err(e) => {
+            log::error!("Error loading databases: {}", e);
+            std::process::exit(1);
+        }
+    };
     let databases = Arc::new(RwLock::new(databases));
 
+    if let Some(http_port) = CONFIG.http_port {
+        let http_server = network::http::HttpServer::new(http_port);
+        let data_path = data_path.clone();
+        let databases = databases.clone();
+        tokio::spawn(async move {
+            if let Err(e) = http_server.run(data_path, databases).await {
+                log::error!("Error running the HTTP server on port {}: {}", http_port, e);
+                std::process::exit(1);
+            }
+        });
+    }
+
+    if let Some(interval) = CONFIG.compact_interval {
+        let min_garbage = CONFIG
+            .compact_min_garbage
+            .unwrap_or(compactor::DEFAULT_MIN_GARBAGE);
+        let databases = databases.clone();
+        tokio::spawn(async move {
+            compactor::run(databases, Duration::from_secs(interval), min_garbage).await
+        });
+    }
+
     server.run(data_path, databases.clone()).await;
 }
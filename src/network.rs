@@ -1,2 +1,5 @@
 pub mod client;
+pub mod format;
+pub mod http;
+pub mod protocol;
 pub mod server;
@@ -1,145 +1,266 @@
 use std::{collections::HashMap, sync::Arc};
 
-use bincode::serialize;
 use log::{debug, error, info};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
-    net::TcpStream,
+    io::{AsyncRead, AsyncWrite},
     sync::RwLock,
 };
 
 use crate::{
-    database::NoSqlDatabase,
-    parser::{
-        handle_message, parse_create_command, Command, Definition, InsertData, Query, CREATE,
-    },
+    data_object::DataObjectErrorKind,
+    database::{DataResponse, ErrorKind, ErrorResponse, NoSqlDatabase},
+    format::ResponseFormat,
+    parser::{handle_message, AlterOperation, Command, Definition, DropTarget, InsertData, Query},
 };
 
-pub struct Client {
+use super::protocol::{Frame, FrameError, Request, MAX_FRAME_LENGTH, MAX_PAYLOAD_LENGTH};
+
+pub struct Client<R, W> {
     data_path: String,
-    reader: ReadHalf<TcpStream>,
-    writer: WriteHalf<TcpStream>,
+    reader: R,
+    writer: W,
     databases: Arc<RwLock<HashMap<String, NoSqlDatabase>>>,
+    format: ResponseFormat, // Encoding of the responses sent on this connection
+    transaction: Option<Transaction>,
+}
+
+/// Transaction holds the writes of a connection between `BEGIN` and `COMMIT`. The writes are
+/// buffered so no connection, this one included, sees them before they are committed.
+struct Transaction {
+    db: String,
+    commands: Vec<Command>,
 }
 
-impl Client {
+impl<R, W> Client<R, W>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
     pub fn new(
         data_path: String,
-        reader: ReadHalf<TcpStream>,
-        writer: WriteHalf<TcpStream>,
+        reader: R,
+        writer: W,
         databases: Arc<RwLock<HashMap<String, NoSqlDatabase>>>,
-    ) -> Client {
+    ) -> Client<R, W> {
         Client {
             data_path,
             reader,
             writer,
             databases,
+            format: ResponseFormat::default(),
+            transaction: None,
         }
     }
 }
 
-impl Client {
+impl<R, W> Client<R, W>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
     pub async fn listen(&mut self) {
-        let mut buffer = Vec::with_capacity(1024);
+        loop {
+            let frame = match Frame::read_from(&mut self.reader).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(FrameError::UnsupportedVersion(version)) => {
+                    // The whole frame was consumed so the stream is still in sync, the request
+                    // id can not be trusted though so the error is sent for request 0
+                    error!("Unsupported protocol version {}", version);
+                    let response = DataResponse::error(
+                        ErrorKind::Protocol,
+                        FrameError::UnsupportedVersion(version).to_string(),
+                    );
+                    self.send_response(0, &response).await;
+                    continue;
+                }
+                Err(error) => {
+                    error!("Error reading frame: {}", error);
+                    let response = DataResponse::error(ErrorKind::Protocol, error.to_string());
+                    self.send_response(0, &response).await;
+                    break;
+                }
+            };
 
-        while let Ok(n) = self.reader.read_buf(&mut buffer).await {
-            if n == 0 {
-                break;
+            let request_id = frame.request_id;
+            let request = match Request::decode(&frame.payload) {
+                Ok(request) => request,
+                Err(error) => {
+                    error!("Error decoding request {}: {}", request_id, error);
+                    let response = DataResponse::error(ErrorKind::Protocol, error.to_string());
+                    self.send_response(request_id, &response).await;
+                    continue;
+                }
+            };
+            debug!(
+                "Received request {}: database {}, command {}",
+                request_id, request.database, request.command
+            );
+            let response = self.handle_request(request).await;
+            self.send_response(request_id, &response).await;
+        }
+        info!("Connection closed")
+    }
+
+    async fn handle_request(&mut self, request: Request) -> DataResponse {
+        let db = request.database.as_str();
+        let command = handle_message(db, &request.command);
+        debug!("Command: {:?}", command);
+        match command {
+            Ok(command) => match command {
+                Command::Begin(db) => self.handle_begin(db).await,
+                Command::Commit => self.handle_commit().await,
+                Command::Rollback => self.handle_rollback(),
+                command @ (Command::Insert(_) | Command::Update(_, _) | Command::Delete(_))
+                    if self.transaction.is_some() =>
+                {
+                    self.buffer(db, command).await
+                }
+                Command::Create(_)
+                | Command::Define(_, _, _)
+                | Command::Alter(_, _, _)
+                | Command::Drop(_)
+                | Command::Compact(_, _)
+                | Command::Reindex(_, _, _)
+                    if self.transaction.is_some() =>
+                {
+                    DataResponse::error(
+                        ErrorKind::Transaction,
+                        "Only INSERT, UPDATE and DELETE can be part of a transaction".to_string(),
+                    )
+                }
+                Command::Select(query) => self.handle_select(query).await,
+                Command::Insert(insert_data) => self.handle_insert(db, insert_data).await,
+                Command::Update(insert_data, query) => {
+                    self.handle_update(db, insert_data, query).await
+                }
+                Command::Delete(query) => self.handle_delete(db, query).await,
+                Command::Create(database_to_create) => self.handle_create(database_to_create).await,
+                Command::Define(db, table, definitions) => {
+                    self.handle_definition(db, table, definitions).await
+                }
+                Command::Alter(db, table, operation) => {
+                    self.handle_alter(&db, table, operation).await
+                }
+                Command::Drop(DropTarget::Table(db, table)) => {
+                    self.handle_drop_table(&db, table).await
+                }
+                Command::Drop(DropTarget::Database(db)) => self.handle_drop_database(&db).await,
+                Command::Describe(db, table) => self.handle_describe(&db, table).await,
+                Command::Compact(db, table) => self.handle_compact(&db, table).await,
+                Command::Reindex(db, table, column) => {
+                    self.handle_reindex(&db, table, column).await
+                }
+                Command::Format(format) => {
+                    debug!("Switching response format to {:?}", format);
+                    self.format = format;
+                    DataResponse::Data(vec![])
+                }
+            },
+            Err(error) => {
+                error!("Error parsing message {}", error);
+                DataResponse::Error(ErrorResponse::from(error))
             }
+        }
+    }
+
+    async fn send_response(&mut self, request_id: u64, response: &DataResponse) {
+        let payload = encode_response(self.format, response, MAX_PAYLOAD_LENGTH);
+        let frame = Frame::new(request_id, payload);
+        if let Err(error) = frame.write_to(&mut self.writer).await {
+            error!("Error sending response {}: {}", request_id, error);
+        }
+    }
 
-            let message = String::from_utf8_lossy(&buffer);
-            debug!("Received message: {}", message);
-
-            if message.starts_with(CREATE) {
-                let create_command = parse_create_command(&message);
-                debug!("Create command: {:?}", create_command);
-                match create_command {
-                    Ok(command) => {
-                        if let Command::Create(database_to_create) = command {
-                            let databases = self.databases.read().await;
-                            let database = databases.get(&database_to_create);
-                            if database.is_some() {
-                                error!("Database {} is already exists", database_to_create);
-                                buffer.clear();
-                                //#TODO: Handle the error and send the response
-                                self.writer
-                                    .write_all(b"Database already exists")
-                                    .await
-                                    .unwrap();
-                                continue;
-                            }
-                            drop(databases);
-                            let new_database =
-                                NoSqlDatabase::new(&database_to_create, &self.data_path).await;
-                            match new_database {
-                                Ok(database) => {
-                                    let mut databases = self.databases.write().await;
-                                    debug!("Database {} created", database_to_create);
-                                    databases.insert(database_to_create, database);
-                                    buffer.clear();
-                                    self.writer.write_all(b"Database created").await.unwrap();
-                                    self.writer.flush().await.unwrap();
-                                }
-                                Err(error) => {
-                                    error!("Failed to create databse {}", error);
-                                    buffer.clear();
-                                    continue;
-                                    //#TODO: Handle the error and send the response
-                                }
-                            }
-                        }
-                    }
-                    Err(error) => {
-                        error!("Error parsing create command: {}", error);
-                    }
+    async fn handle_begin(&mut self, db: String) -> DataResponse {
+        if self.transaction.is_some() {
+            return DataResponse::error(
+                ErrorKind::Transaction,
+                "A transaction is already open".to_string(),
+            );
+        }
+        if !self.databases.read().await.contains_key(&db) {
+            return database_not_found(&db);
+        }
+        debug!("Transaction started on {}", db);
+        self.transaction = Some(Transaction {
+            db,
+            commands: vec![],
+        });
+        DataResponse::Data(vec![])
+    }
+
+    /// Keep the write for the commit, inserts and updates answer with their record as they do
+    /// outside a transaction. Inserts are assigned their object id now so they answer with the id
+    /// the commit writes. Reads do not see the buffered writes, not even on this connection.
+    async fn buffer(&mut self, db: &str, command: Command) -> DataResponse {
+        let Some(transaction) = self.transaction.as_mut() else {
+            return DataResponse::error(
+                ErrorKind::Transaction,
+                "No transaction is open".to_string(),
+            );
+        };
+        if transaction.db != db {
+            return DataResponse::error(
+                ErrorKind::Transaction,
+                format!("The transaction is open on {}", transaction.db),
+            );
+        }
+        let command = match command {
+            Command::Insert(insert_data) => {
+                let mut databases = self.databases.write().await;
+                let Some(database) = databases.get_mut(db) else {
+                    return database_not_found(db);
+                };
+                match database.assign_object_id(insert_data).await {
+                    Ok(insert_data) => Command::Insert(insert_data),
+                    Err(response) => return response,
                 }
-                continue;
             }
-            let index = message.find(':');
-            if index.is_none() {
-                info!("Invalid message format"); // should handle correctly
-                buffer.clear();
-                continue;
+            command => command,
+        };
+        let response = match &command {
+            Command::Insert(insert_data) | Command::Update(insert_data, _) => {
+                DataResponse::Data(vec![insert_data.clone()])
             }
-            let db = message[..index.unwrap()].trim();
-            let message = message[index.unwrap() + 1..].trim();
-            debug!("Parsed message: database {}, command {}", db, message);
-            let command = handle_message(db, message);
-            debug!("Command: {:?}", command);
-            match command {
-                Ok(command) => match command {
-                    Command::Select(query) => {
-                        self.handle_select(query).await;
-                    }
-                    Command::Insert(insert_data) => {
-                        self.handle_insert(db,insert_data).await;
-                    }
-                    Command::Update(insert_data, query) => {
-                        self.handle_update(db,insert_data, query).await;
-                    }
-                    Command::Delete(query) => {
-                        self.handle_delete(db,query).await;
-                    }
-                    Command::Create(_) => {
-                        error!("Unexpected create command");
-                        buffer.clear();
-                        //#TODO: send  error response
-                    }
-                    Command::Define(db, table, definitions) => {
-                        self.handle_definition(db, table, definitions).await;
-                    }
-                    Command::Alter => todo!(),
-                    Command::Drop => todo!(),
-                },
-                Err(error) => {
-                    error!("Error parsing message {}", error);
-                    buffer.clear();
-                    //#TODO: Send error response
-                }
+            _ => DataResponse::Data(vec![]),
+        };
+        transaction.commands.push(command);
+        response
+    }
+
+    async fn handle_commit(&mut self) -> DataResponse {
+        let Some(transaction) = self.transaction.take() else {
+            return DataResponse::error(
+                ErrorKind::Transaction,
+                "No transaction is open".to_string(),
+            );
+        };
+        let mut databases = self.databases.write().await;
+        match databases.get_mut(&transaction.db) {
+            Some(database) => database.handle_transaction(transaction.commands).await,
+            None => database_not_found(&transaction.db),
+        }
+    }
+
+    fn handle_rollback(&mut self) -> DataResponse {
+        match self.transaction.take() {
+            Some(transaction) => {
+                debug!(
+                    "Transaction on {} rolled back, {} writes discarded",
+                    transaction.db,
+                    transaction.commands.len()
+                );
+                DataResponse::Data(vec![])
+            }
+            None => {
+                DataResponse::error(ErrorKind::Transaction, "No transaction is open".to_string())
             }
-            buffer.clear();
         }
-        info!("Connection closed")
+    }
+
+    async fn handle_create(&mut self, database_to_create: String) -> DataResponse {
+        create_database(&self.databases, &self.data_path, database_to_create).await
     }
 
     async fn handle_definition(
@@ -147,78 +268,641 @@ impl Client {
         db: String,
         table: String,
         definitions: HashMap<String, Definition>,
-    ) {
+    ) -> DataResponse {
         let mut databases = self.databases.write().await;
-        let database = databases.get_mut(&db);
-        match database {
-            Some(database) => {
-                let response = database.handle_definition(table, definitions).await;
-                let response = serialize(&response).unwrap();
-                self.writer.write_all(&response).await.unwrap();
-            }
-            None => {
-                self.writer.write_all(b"No Records found").await.unwrap();
-            }
+        match databases.get_mut(&db) {
+            Some(database) => database.handle_definition(table, definitions).await,
+            None => database_not_found(&db),
         }
     }
 
-    async fn handle_delete(&mut self,db: &str, delete_query: Query) {
+    async fn handle_alter(
+        &mut self,
+        db: &str,
+        table: String,
+        operation: AlterOperation,
+    ) -> DataResponse {
         let mut databases = self.databases.write().await;
-        let database = databases.get_mut(db);
-        match database {
-            Some(database) => {
-                let response = database.handle_delete(delete_query).await;
-                let response = serialize(&response).unwrap();
-                self.writer.write_all(&response).await.unwrap();
-            }
-            None => {
-                self.writer.write_all(b"No Records found").await.unwrap();
-            }
+        match databases.get_mut(db) {
+            Some(database) => database.handle_alter(table, operation).await,
+            None => database_not_found(db),
         }
     }
 
-    async fn handle_update(&mut self,db: &str, insert_data: InsertData, query: Query) {
+    async fn handle_drop_table(&mut self, db: &str, table: String) -> DataResponse {
         let mut databases = self.databases.write().await;
-        let database = databases.get_mut(db);
-        match database {
-            Some(database) => {
-                let response = database.handle_update(insert_data, query).await;
-                let response = serialize(&response).unwrap();
-                self.writer.write_all(&response).await.unwrap();
-            }
-            None => {
-                self.writer.write_all(b"No Records found").await.unwrap();
-            }
+        match databases.get_mut(db) {
+            Some(database) => database.handle_drop_table(table).await,
+            None => database_not_found(db),
         }
     }
 
-    async fn handle_insert(&mut self,db: &str, insert_data: InsertData) {
+    async fn handle_drop_database(&mut self, db: &str) -> DataResponse {
+        // The write lock is held until the files are gone so no other connection can use the
+        // database or create it again in between
         let mut databases = self.databases.write().await;
-        let database = databases.get_mut(db);
-        match database {
-            Some(database) => {
-                let response = database.handle_insert(insert_data).await;
-                let response = serialize(&response).unwrap();
-                self.writer.write_all(&response).await.unwrap();
-            }
-            None => {
-                self.writer.write_all(b"No Records found").await.unwrap();
-            }
+        match databases.remove(db) {
+            Some(database) => match database.drop_database().await {
+                Ok(_) => {
+                    debug!("Database {} dropped", db);
+                    DataResponse::Data(vec![])
+                }
+                Err(error) => {
+                    error!("Failed to drop database {}", error);
+                    DataResponse::error(ErrorKind::Database, error)
+                }
+            },
+            None => database_not_found(db),
+        }
+    }
+
+    async fn handle_delete(&mut self, db: &str, delete_query: Query) -> DataResponse {
+        let mut databases = self.databases.write().await;
+        match databases.get_mut(db) {
+            Some(database) => database.handle_delete(delete_query).await,
+            None => database_not_found(db),
+        }
+    }
+
+    async fn handle_update(
+        &mut self,
+        db: &str,
+        insert_data: InsertData,
+        query: Query,
+    ) -> DataResponse {
+        let mut databases = self.databases.write().await;
+        match databases.get_mut(db) {
+            Some(database) => database.handle_update(insert_data, query).await,
+            None => database_not_found(db),
         }
     }
 
-    async fn handle_select(&mut self, query: Query) {
+    async fn handle_insert(&mut self, db: &str, insert_data: InsertData) -> DataResponse {
+        let mut databases = self.databases.write().await;
+        match databases.get_mut(db) {
+            Some(database) => database.handle_insert(insert_data).await,
+            None => database_not_found(db),
+        }
+    }
+
+    async fn handle_compact(&mut self, db: &str, table: String) -> DataResponse {
+        let mut databases = self.databases.write().await;
+        match databases.get_mut(db) {
+            Some(database) => database.handle_compact(table).await,
+            None => database_not_found(db),
+        }
+    }
+
+    async fn handle_reindex(
+        &mut self,
+        db: &str,
+        table: String,
+        column: Option<String>,
+    ) -> DataResponse {
+        let mut databases = self.databases.write().await;
+        match databases.get_mut(db) {
+            Some(database) => database.handle_reindex(table, column).await,
+            None => database_not_found(db),
+        }
+    }
+
+    async fn handle_describe(&mut self, db: &str, table: Option<String>) -> DataResponse {
         let databases = self.databases.read().await;
-        let database = databases.get(&query.db);
-        match database {
-            Some(database) => {
-                let response = database.handle_query(query).await;
-                let response = serialize(&response).unwrap();
-                self.writer.write_all(&response).await.unwrap();
-            }
-            None => {
-                self.writer.write_all(b"No Records found").await.unwrap();
-            }
+        match databases.get(db) {
+            Some(database) => database.handle_describe(table),
+            None => database_not_found(db),
+        }
+    }
+
+    async fn handle_select(&mut self, query: Query) -> DataResponse {
+        let databases = self.databases.read().await;
+        match databases.get(&query.db) {
+            Some(database) => database.handle_query(query).await,
+            None => database_not_found(&query.db),
+        }
+    }
+}
+
+/// Create a new database under `data_path` and register it, shared by the TCP and HTTP front-ends
+pub(crate) async fn create_database(
+    databases: &RwLock<HashMap<String, NoSqlDatabase>>,
+    data_path: &str,
+    database_to_create: String,
+) -> DataResponse {
+    if databases.read().await.contains_key(&database_to_create) {
+        error!("Database {} is already exists", database_to_create);
+        return DataResponse::error(
+            ErrorKind::DatabaseExists,
+            format!("Database {} already exists", database_to_create),
+        );
+    }
+    let new_database = NoSqlDatabase::new(&database_to_create, data_path).await;
+    match new_database {
+        Ok(database) => {
+            let mut databases = databases.write().await;
+            debug!("Database {} created", database_to_create);
+            databases.insert(database_to_create, database);
+            DataResponse::Data(vec![])
+        }
+        Err(error) => {
+            error!("Failed to create databse {}", error);
+            DataResponse::error(ErrorKind::Database, error)
+        }
+    }
+}
+
+pub(crate) fn database_not_found(db: &str) -> DataResponse {
+    DataResponse::error(
+        ErrorKind::DatabaseNotFound,
+        format!("Database {} not found", db),
+    )
+}
+
+/// Encode the response in the format of the connection. Responses that can not be encoded or
+/// are longer than `max_length` are replaced with an error response, so the peer always gets a
+/// frame it can read.
+fn encode_response(format: ResponseFormat, response: &DataResponse, max_length: usize) -> Vec<u8> {
+    let error = match format.encode(response) {
+        Ok(payload) if payload.len() <= max_length => return payload,
+        Ok(payload) => {
+            error!("Response of {} bytes is too large", payload.len());
+            DataResponse::error(
+                ErrorKind::Protocol,
+                format!(
+                    "Response of {} bytes exceeds the maximum frame length of {} bytes, narrow the query with WHERE or LIMIT",
+                    payload.len(),
+                    MAX_FRAME_LENGTH
+                ),
+            )
+        }
+        Err(error) => {
+            error!("Error serializing response: {}", error);
+            DataResponse::error(
+                ErrorKind::DataObject(DataObjectErrorKind::Serialize),
+                format!("Error serializing response: {}", error),
+            )
+        }
+    };
+    match format.encode(&error) {
+        Ok(payload) if payload.len() <= max_length => payload,
+        _ => format.encoding_error().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempfile::Builder;
+    use tokio::io::{split, AsyncWriteExt};
+
+    use super::*;
+    use crate::parser::{DataObject, SyntaxErrorCode};
+
+    #[tokio::test]
+    async fn test_pipelined_requests() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let data_path = dir.path().to_str().unwrap().to_string();
+        let databases = Arc::new(RwLock::new(HashMap::new()));
+
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let (reader, writer) = split(server_stream);
+        let mut client = Client::new(data_path, reader, writer, databases.clone());
+        let server = tokio::spawn(async move { client.listen().await });
+
+        let (mut client_reader, mut client_writer) = split(client_stream);
+        // Both requests are sent in a single write, the server must still see two frames
+        let create = Frame::new(7, Request::new("", "CREATE xdr").encode().unwrap());
+        let define = Frame::new(
+            8,
+            Request::new(
+                "xdr",
+                r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#,
+            )
+            .encode()
+            .unwrap(),
+        );
+        client_writer
+            .write_all(&[create.encode(), define.encode()].concat())
+            .await
+            .unwrap();
+
+        let response = Frame::read_from(&mut client_reader).await.unwrap().unwrap();
+        assert_eq!(response.request_id, 7);
+        let response: DataResponse = bincode::deserialize(&response.payload).unwrap();
+        assert!(matches!(response, DataResponse::Data(_)));
+        let response = Frame::read_from(&mut client_reader).await.unwrap().unwrap();
+        assert_eq!(response.request_id, 8);
+        let response: DataResponse = bincode::deserialize(&response.payload).unwrap();
+        assert!(matches!(response, DataResponse::Data(_)));
+
+        assert!(databases.read().await.contains_key("xdr"));
+        assert!(dir.path().join("xdr").join("user").exists());
+
+        client_writer.shutdown().await.unwrap();
+        server.await.unwrap();
+    }
+
+    async fn request(
+        reader: &mut (impl AsyncRead + Unpin),
+        writer: &mut (impl AsyncWrite + Unpin),
+        request_id: u64,
+        request: Request,
+    ) -> DataResponse {
+        Frame::new(request_id, request.encode().unwrap())
+            .write_to(writer)
+            .await
+            .unwrap();
+        let response = Frame::read_from(reader).await.unwrap().unwrap();
+        assert_eq!(response.request_id, request_id);
+        bincode::deserialize(&response.payload).unwrap()
+    }
+
+    fn error_kind(response: DataResponse) -> ErrorKind {
+        match response {
+            DataResponse::Error(error) => error.kind,
+            other => panic!("Expected an error response but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encode_large_response() {
+        let response = DataResponse::Data(vec![InsertData {
+            object_id: "1".to_string(),
+            table: "user".to_string(),
+            data: DataObject::String("x".repeat(1024)),
+            active: true,
+        }]);
+        let payload = encode_response(ResponseFormat::Bincode, &response, usize::MAX);
+        assert!(payload.len() > 1024);
+
+        // Responses that do not fit in a frame are replaced with an error
+        let payload = encode_response(ResponseFormat::Bincode, &response, 512);
+        let response = bincode::deserialize::<DataResponse>(&payload).unwrap();
+        assert_eq!(error_kind(response), ErrorKind::Protocol);
+
+        // The pre-encoded error is sent when even the error does not fit
+        let response = DataResponse::Data(vec![]);
+        assert_eq!(
+            encode_response(ResponseFormat::Json, &response, 4),
+            ResponseFormat::Json.encoding_error()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_responses() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let data_path = dir.path().to_str().unwrap().to_string();
+        let databases = Arc::new(RwLock::new(HashMap::new()));
+
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let (reader, writer) = split(server_stream);
+        let mut client = Client::new(data_path, reader, writer, databases);
+        let server = tokio::spawn(async move { client.listen().await });
+        let (mut reader, mut writer) = split(client_stream);
+
+        let response = request(
+            &mut reader,
+            &mut writer,
+            1,
+            Request::new("xdr", "SELECT user WHERE name = 'John'"),
+        )
+        .await;
+        assert_eq!(error_kind(response), ErrorKind::DatabaseNotFound);
+
+        let response = request(
+            &mut reader,
+            &mut writer,
+            2,
+            Request::new("xdr", "FETCH user"),
+        )
+        .await;
+        assert_eq!(error_kind(response), ErrorKind::Parse);
+
+        let response = request(&mut reader, &mut writer, 3, Request::new("", "CREATE xdr")).await;
+        assert!(matches!(response, DataResponse::Data(_)));
+        let response = request(&mut reader, &mut writer, 4, Request::new("", "CREATE xdr")).await;
+        assert_eq!(error_kind(response), ErrorKind::DatabaseExists);
+
+        let response = request(
+            &mut reader,
+            &mut writer,
+            5,
+            Request::new("xdr", r#"DEFINE user { "name": { "type": "String" }}"#),
+        )
+        .await;
+        assert_eq!(
+            error_kind(response),
+            ErrorKind::Syntax(SyntaxErrorCode::InvalidValue)
+        );
+
+        let response = request(
+            &mut reader,
+            &mut writer,
+            6,
+            Request::new("xdr", r#"INSERT INTO user {"name":"John"}"#),
+        )
+        .await;
+        assert_eq!(error_kind(response), ErrorKind::TableNotFound);
+
+        let response = request(
+            &mut reader,
+            &mut writer,
+            7,
+            Request::new("xdr", "DROP user"),
+        )
+        .await;
+        assert_eq!(
+            error_kind(response),
+            ErrorKind::Syntax(SyntaxErrorCode::InvalidValue)
+        );
+
+        // A malformed request still gets an answer for its request id
+        Frame::new(8, vec![0]).write_to(&mut writer).await.unwrap();
+        let response = Frame::read_from(&mut reader).await.unwrap().unwrap();
+        assert_eq!(response.request_id, 8);
+        let response: DataResponse = bincode::deserialize(&response.payload).unwrap();
+        assert_eq!(error_kind(response), ErrorKind::Protocol);
+
+        writer.shutdown().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_json_format() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let data_path = dir.path().to_str().unwrap().to_string();
+        let databases = Arc::new(RwLock::new(HashMap::new()));
+
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let (reader, writer) = split(server_stream);
+        let mut client = Client::new(data_path, reader, writer, databases);
+        let server = tokio::spawn(async move { client.listen().await });
+        let (mut reader, mut writer) = split(client_stream);
+
+        let commands = [
+            ("", "FORMAT JSON"),
+            ("", "CREATE xdr"),
+            (
+                "xdr",
+                r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#,
+            ),
+            (
+                "xdr",
+                r#"INSERT INTO user {"id":"1","name":"John","age":30}"#,
+            ),
+        ];
+        for (request_id, (db, command)) in commands.into_iter().enumerate() {
+            Frame::new(
+                request_id as u64,
+                Request::new(db, command).encode().unwrap(),
+            )
+            .write_to(&mut writer)
+            .await
+            .unwrap();
+            let response = Frame::read_from(&mut reader).await.unwrap().unwrap();
+            let response: serde_json::Value = serde_json::from_slice(&response.payload).unwrap();
+            assert_eq!(response["status"], "ok");
+        }
+
+        Frame::new(
+            9,
+            Request::new("xdr", "SELECT user WHERE name = 'John'")
+                .encode()
+                .unwrap(),
+        )
+        .write_to(&mut writer)
+        .await
+        .unwrap();
+        let response = Frame::read_from(&mut reader).await.unwrap().unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&response.payload).unwrap();
+        assert_eq!(
+            response["data"],
+            serde_json::json!([{ "object_id": "1", "id": "1", "name": "John", "age": 30 }])
+        );
+
+        writer.shutdown().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drop_database() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let data_path = dir.path().to_str().unwrap().to_string();
+        let databases = Arc::new(RwLock::new(HashMap::new()));
+
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let (reader, writer) = split(server_stream);
+        let mut client = Client::new(data_path, reader, writer, databases.clone());
+        let server = tokio::spawn(async move { client.listen().await });
+        let (mut reader, mut writer) = split(client_stream);
+
+        request(&mut reader, &mut writer, 1, Request::new("", "CREATE xdr")).await;
+        let define =
+            r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#;
+        request(&mut reader, &mut writer, 2, Request::new("xdr", define)).await;
+
+        let response = request(
+            &mut reader,
+            &mut writer,
+            3,
+            Request::new("", "DROP DATABASE xdr"),
+        )
+        .await;
+        assert!(matches!(response, DataResponse::Data(_)));
+        assert!(!databases.read().await.contains_key("xdr"));
+        assert!(!dir.path().join("xdr").exists());
+
+        let response = request(
+            &mut reader,
+            &mut writer,
+            4,
+            Request::new("", "DROP DATABASE xdr"),
+        )
+        .await;
+        assert_eq!(error_kind(response), ErrorKind::DatabaseNotFound);
+
+        // The name can be used again
+        let response = request(&mut reader, &mut writer, 5, Request::new("", "CREATE xdr")).await;
+        assert!(matches!(response, DataResponse::Data(_)));
+
+        writer.shutdown().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transactions() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let data_path = dir.path().to_str().unwrap().to_string();
+        let databases = Arc::new(RwLock::new(HashMap::new()));
+
+        let mut streams = vec![];
+        let mut servers = vec![];
+        for _ in 0..2 {
+            let (client_stream, server_stream) = tokio::io::duplex(4096);
+            let (reader, writer) = split(server_stream);
+            let mut client = Client::new(data_path.clone(), reader, writer, databases.clone());
+            servers.push(tokio::spawn(async move { client.listen().await }));
+            streams.push(split(client_stream));
+        }
+        let (mut other_reader, mut other_writer) = streams.pop().unwrap();
+        let (mut reader, mut writer) = streams.pop().unwrap();
+
+        let setup = [
+            ("", "CREATE xdr"),
+            (
+                "xdr",
+                r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false }}"#,
+            ),
+            (
+                "xdr",
+                r#"DEFINE stock { "item": { "type": "String", "indexed": true, "optional": false }, "count": { "type": "Int", "indexed": false, "optional": false }}"#,
+            ),
+            ("xdr", r#"INSERT INTO stock {"item":"pen","count":5}"#),
+        ];
+        for (request_id, (db, command)) in setup.into_iter().enumerate() {
+            let response = request(
+                &mut reader,
+                &mut writer,
+                request_id as u64,
+                Request::new(db, command),
+            )
+            .await;
+            assert!(matches!(response, DataResponse::Data(_)));
+        }
+
+        let count = |response: DataResponse| match response {
+            DataResponse::Data(data) => data.len(),
+            other => panic!("Expected data but got {:?}", other),
+        };
+        let commands = [
+            "BEGIN",
+            r#"INSERT INTO user {"name":"John"}"#,
+            r#"UPDATE stock {"item":"pen","count":4} WHERE item = 'pen'"#,
+        ];
+        for command in commands {
+            let response =
+                request(&mut reader, &mut writer, 10, Request::new("xdr", command)).await;
+            assert!(matches!(response, DataResponse::Data(_)));
+        }
+        let response = request(
+            &mut reader,
+            &mut writer,
+            11,
+            Request::new(
+                "xdr",
+                r#"DEFINE car { "name": { "type": "String", "indexed": false, "optional": true }}"#,
+            ),
+        )
+        .await;
+        assert_eq!(error_kind(response), ErrorKind::Transaction);
+
+        // Buffered inserts answer with the id the commit writes
+        let response = request(
+            &mut reader,
+            &mut writer,
+            10,
+            Request::new("xdr", r#"INSERT INTO user {"name":"Jill"}"#),
+        )
+        .await;
+        let object_id = match response {
+            DataResponse::Data(data) => data[0].object_id.clone(),
+            other => panic!("Expected data but got {:?}", other),
+        };
+        assert!(!object_id.is_empty());
+
+        // No connection sees the writes before the commit, not even the one that wrote them
+        let select = Request::new("xdr", "SELECT user");
+        let response = request(&mut other_reader, &mut other_writer, 1, select.clone()).await;
+        assert_eq!(count(response), 0);
+        let response = request(&mut reader, &mut writer, 11, select.clone()).await;
+        assert_eq!(count(response), 0);
+
+        let response = request(&mut reader, &mut writer, 12, Request::new("xdr", "COMMIT")).await;
+        assert_eq!(count(response), 3);
+        let response = request(&mut other_reader, &mut other_writer, 2, select.clone()).await;
+        assert_eq!(count(response), 2);
+        let response = request(
+            &mut other_reader,
+            &mut other_writer,
+            2,
+            Request::new("xdr", "SELECT user WHERE name = 'Jill'"),
+        )
+        .await;
+        match response {
+            DataResponse::Data(data) => assert_eq!(data[0].object_id, object_id),
+            other => panic!("Expected data but got {:?}", other),
+        }
+        let response = request(
+            &mut other_reader,
+            &mut other_writer,
+            3,
+            Request::new("xdr", "SELECT stock WHERE count = 4"),
+        )
+        .await;
+        assert_eq!(count(response), 1);
+
+        // A failing write undoes the writes before it, in every table
+        let commands = [
+            "BEGIN",
+            r#"INSERT INTO user {"name":"Jane"}"#,
+            r#"UPDATE stock {"item":"pen","count":"three"} WHERE item = 'pen'"#,
+        ];
+        for command in commands {
+            request(&mut reader, &mut writer, 20, Request::new("xdr", command)).await;
+        }
+        let response = request(&mut reader, &mut writer, 21, Request::new("xdr", "COMMIT")).await;
+        assert_eq!(
+            error_kind(response),
+            ErrorKind::DataObject(DataObjectErrorKind::Type)
+        );
+        let response = request(&mut other_reader, &mut other_writer, 4, select.clone()).await;
+        assert_eq!(count(response), 2);
+        let response = request(
+            &mut other_reader,
+            &mut other_writer,
+            5,
+            Request::new("xdr", "SELECT user WHERE name = 'Jane'"),
+        )
+        .await;
+        assert_eq!(count(response), 0);
+
+        // Rolled back writes are never applied
+        let commands = ["BEGIN", r#"INSERT INTO user {"name":"Jane"}"#, "ROLLBACK"];
+        for command in commands {
+            let response =
+                request(&mut reader, &mut writer, 30, Request::new("xdr", command)).await;
+            assert!(matches!(response, DataResponse::Data(_)));
+        }
+        let response = request(&mut other_reader, &mut other_writer, 6, select).await;
+        assert_eq!(count(response), 2);
+
+        let response = request(&mut reader, &mut writer, 31, Request::new("xdr", "COMMIT")).await;
+        assert_eq!(error_kind(response), ErrorKind::Transaction);
+        request(&mut reader, &mut writer, 32, Request::new("xdr", "BEGIN")).await;
+        let response = request(&mut reader, &mut writer, 33, Request::new("xdr", "BEGIN")).await;
+        assert_eq!(error_kind(response), ErrorKind::Transaction);
+        let response = request(&mut reader, &mut writer, 34, Request::new("", "CREATE car")).await;
+        assert_eq!(error_kind(response), ErrorKind::Transaction);
+
+        writer.shutdown().await.unwrap();
+        other_writer.shutdown().await.unwrap();
+        for server in servers {
+            server.await.unwrap();
         }
     }
 }
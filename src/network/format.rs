@@ -0,0 +1,182 @@
+use serde_json::{json, Value};
+
+use crate::{database::DataResponse, format::ResponseFormat};
+
+/// "Error encoding response" encoded with bincode as a `DataObject(Serialize)` error response
+const BINCODE_ENCODING_ERROR: &[u8] =
+    b"\x03\0\0\0\x02\0\0\0\0\0\0\0\x17\0\0\0\0\0\0\0Error encoding response";
+
+/// "Error encoding response" encoded as a JSON `DataObject(Serialize)` error response
+const JSON_ENCODING_ERROR: &[u8] = br#"{"error":{"kind":{"DataObject":"Serialize"},"message":"Error encoding response"},"status":"error"}"#;
+
+/// `JSON_ENCODING_ERROR` as a NDJSON status line
+const NDJSON_ENCODING_ERROR: &[u8] = b"{\"error\":{\"kind\":{\"DataObject\":\"Serialize\"},\"message\":\"Error encoding response\"},\"status\":\"error\"}\n";
+
+impl ResponseFormat {
+    /// Error response sent when a response, or the error describing why it could not be sent,
+    /// can not be encoded. It is encoded ahead of time so sending it never fails.
+    pub fn encoding_error(&self) -> &'static [u8] {
+        match self {
+            ResponseFormat::Bincode => BINCODE_ENCODING_ERROR,
+            ResponseFormat::Json => JSON_ENCODING_ERROR,
+            ResponseFormat::Ndjson => NDJSON_ENCODING_ERROR,
+        }
+    }
+
+    /// Encode the response in this format
+    /// # Arguments
+    /// * `response` - The response to encode
+    /// # Returns
+    /// * `Result<Vec<u8>, String>` - The encoded response
+    pub fn encode(&self, response: &DataResponse) -> Result<Vec<u8>, String> {
+        match self {
+            ResponseFormat::Bincode => bincode::serialize(response).map_err(|e| e.to_string()),
+            ResponseFormat::Json => {
+                serde_json::to_vec(&to_json(response)).map_err(|e| e.to_string())
+            }
+            ResponseFormat::Ndjson => to_ndjson(response),
+        }
+    }
+}
+
+/// Convert the response into a JSON document, records are returned as plain documents
+pub fn to_json(response: &DataResponse) -> Value {
+    match response {
+        DataResponse::Data(records) => json!({
+            "status": "ok",
+            "data": records.iter().map(|record| record.to_document()).collect::<Vec<_>>(),
+        }),
+        DataResponse::Definitions(definitions) => json!({
+            "status": "ok",
+            "data": definitions,
+        }),
+        DataResponse::Rows(rows) => json!({
+            "status": "ok",
+            "data": rows.iter().map(|row| row.to_json()).collect::<Vec<_>>(),
+        }),
+        DataResponse::Error(error) => json!({
+            "status": "error",
+            "error": error,
+        }),
+    }
+}
+
+fn to_ndjson(response: &DataResponse) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::new();
+    let status = match response {
+        DataResponse::Data(records) => {
+            for record in records {
+                serde_json::to_writer(&mut buffer, &record.to_document())
+                    .map_err(|e| e.to_string())?;
+                buffer.push(b'\n');
+            }
+            json!({ "status": "ok", "count": records.len() })
+        }
+        DataResponse::Definitions(definitions) => {
+            for definition in definitions {
+                serde_json::to_writer(&mut buffer, definition).map_err(|e| e.to_string())?;
+                buffer.push(b'\n');
+            }
+            json!({ "status": "ok", "count": definitions.len() })
+        }
+        DataResponse::Rows(rows) => {
+            for row in rows {
+                serde_json::to_writer(&mut buffer, &row.to_json()).map_err(|e| e.to_string())?;
+                buffer.push(b'\n');
+            }
+            json!({ "status": "ok", "count": rows.len() })
+        }
+        DataResponse::Error(_) => to_json(response),
+    };
+    serde_json::to_writer(&mut buffer, &status).map_err(|e| e.to_string())?;
+    buffer.push(b'\n');
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{
+        data_object::DataObjectErrorKind,
+        database::ErrorKind,
+        parser::{Data, DataObject, InsertData, Number},
+    };
+
+    fn response() -> DataResponse {
+        DataResponse::Data(vec![InsertData {
+            object_id: "1".to_string(),
+            table: "user".to_string(),
+            data: DataObject::Object(vec![
+                Data {
+                    key: "name".to_string(),
+                    value: DataObject::String("John".to_string()),
+                },
+                Data {
+                    key: "age".to_string(),
+                    value: DataObject::Number(Number::Int(30)),
+                },
+                Data {
+                    key: "tags".to_string(),
+                    value: DataObject::Array(vec![DataObject::Bool(true), DataObject::Null]),
+                },
+            ]),
+            active: true,
+        }])
+    }
+
+    #[test]
+    fn test_json_documents() {
+        let encoded = ResponseFormat::Json.encode(&response()).unwrap();
+        let value: Value = serde_json::from_slice(&encoded).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "status": "ok",
+                "data": [{ "object_id": "1", "name": "John", "age": 30, "tags": [true, null] }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_json_error() {
+        let response = DataResponse::error(ErrorKind::TableNotFound, "Table user not found".into());
+        let encoded = ResponseFormat::Json.encode(&response).unwrap();
+        let value: Value = serde_json::from_slice(&encoded).unwrap();
+        assert_eq!(value["status"], "error");
+        assert_eq!(value["error"]["kind"], "TableNotFound");
+        assert_eq!(value["error"]["message"], "Table user not found");
+    }
+
+    #[test]
+    fn test_ndjson_rows() {
+        let encoded = ResponseFormat::Ndjson.encode(&response()).unwrap();
+        let lines = String::from_utf8(encoded).unwrap();
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let row: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(row["name"], "John");
+        let status: Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(status, json!({ "status": "ok", "count": 1 }));
+    }
+
+    #[test]
+    fn test_encoding_error() {
+        let response = DataResponse::error(
+            ErrorKind::DataObject(DataObjectErrorKind::Serialize),
+            "Error encoding response".to_string(),
+        );
+        for format in [
+            ResponseFormat::Bincode,
+            ResponseFormat::Json,
+            ResponseFormat::Ndjson,
+        ] {
+            assert_eq!(
+                format.encode(&response).unwrap(),
+                format.encoding_error(),
+                "{:?}",
+                format
+            );
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{post, put},
+    Json, Router,
+};
+use log::{debug, error, info};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{net::TcpListener, sync::RwLock};
+
+use crate::{
+    data_object::DataObjectErrorKind,
+    database::{DataResponse, ErrorKind, ErrorResponse, NoSqlDatabase},
+    parser::{handle_message, Command},
+};
+
+use super::{
+    client::{create_database, database_not_found},
+    format::to_json,
+};
+
+/// HttpServer exposes the databases over a JSON REST API next to the TCP server.
+///
+/// | Method   | Path                        | Command                                     |
+/// |----------|-----------------------------|---------------------------------------------|
+/// | `POST`   | `/{db}`                     | `CREATE {db}`                               |
+/// | `PUT`    | `/{db}/{table}`             | `DEFINE {table} {body}`                     |
+/// | `POST`   | `/{db}/{table}/documents`   | `INSERT INTO {table} {body}`                |
+/// | `GET`    | `/{db}/{table}[?where=...]` | `SELECT {table} [WHERE ...]`                |
+/// | `GET`    | `...&fields=a,b.c`          | `SELECT a, b.c FROM {table} WHERE ...`      |
+/// | `PATCH`  | `/{db}/{table}?where=...`   | `UPDATE {table} {body} WHERE ...`           |
+/// | `DELETE` | `/{db}/{table}?where=...`   | `DELETE FROM {table} WHERE ...`             |
+///
+/// Every route answers with the JSON document produced by `format::to_json`.
+pub struct HttpServer {
+    pub port: u16,
+}
+
+#[derive(Clone)]
+struct HttpState {
+    data_path: String,
+    databases: Arc<RwLock<HashMap<String, NoSqlDatabase>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Filter {
+    #[serde(rename = "where")]
+    condition: Option<String>,
+    /// Comma separated fields returned by a `GET`
+    fields: Option<String>,
+}
+
+type HttpResponse = (StatusCode, Json<Value>);
+
+impl HttpServer {
+    pub fn new(port: u16) -> HttpServer {
+        HttpServer { port }
+    }
+
+    /// Serve the REST API until the listener fails, an error is returned when the port can not
+    /// be bound
+    pub async fn run(
+        &self,
+        data_path: String,
+        databases: Arc<RwLock<HashMap<String, NoSqlDatabase>>>,
+    ) -> std::io::Result<()> {
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", self.port)).await?;
+        info!("HTTP server listening on port {}", self.port);
+        axum::serve(listener, router(data_path, databases)).await
+    }
+}
+
+/// Build the routes of the REST API
+pub fn router(data_path: String, databases: Arc<RwLock<HashMap<String, NoSqlDatabase>>>) -> Router {
+    Router::new()
+        .route("/:db", post(handle_create))
+        .route(
+            "/:db/:table",
+            put(handle_definition)
+                .get(handle_select)
+                .patch(handle_update)
+                .delete(handle_delete),
+        )
+        .route("/:db/:table/documents", post(handle_insert))
+        .with_state(HttpState {
+            data_path,
+            databases,
+        })
+}
+
+async fn handle_create(State(state): State<HttpState>, Path(db): Path<String>) -> HttpResponse {
+    // Parse the command so the database name gets the same validation as on the TCP protocol
+    match handle_message("", &format!("CREATE {}", db)) {
+        Ok(Command::Create(created)) if created == db => {
+            let response = create_database(&state.databases, &state.data_path, db).await;
+            respond(StatusCode::CREATED, response)
+        }
+        other => unexpected_command(other),
+    }
+}
+
+async fn handle_definition(
+    State(state): State<HttpState>,
+    Path((db, table)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> HttpResponse {
+    match handle_message(&db, &format!("DEFINE {} {}", table, body)) {
+        Ok(Command::Define(_, defined, definitions)) if defined == table => {
+            let mut databases = state.databases.write().await;
+            let response = match databases.get_mut(&db) {
+                Some(database) => database.handle_definition(table, definitions).await,
+                None => database_not_found(&db),
+            };
+            respond(StatusCode::CREATED, response)
+        }
+        other => unexpected_command(other),
+    }
+}
+
+async fn handle_insert(
+    State(state): State<HttpState>,
+    Path((db, table)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> HttpResponse {
+    match handle_message(&db, &format!("INSERT INTO {} {}", table, body)) {
+        Ok(Command::Insert(insert_data)) if insert_data.table == table => {
+            let mut databases = state.databases.write().await;
+            let response = match databases.get_mut(&db) {
+                Some(database) => database.handle_insert(insert_data).await,
+                None => database_not_found(&db),
+            };
+            respond(StatusCode::CREATED, response)
+        }
+        other => unexpected_command(other),
+    }
+}
+
+async fn handle_select(
+    State(state): State<HttpState>,
+    Path((db, table)): Path<(String, String)>,
+    Query(filter): Query<Filter>,
+) -> HttpResponse {
+    let mut command = match &filter.fields {
+        Some(fields) => format!("SELECT {} FROM {}", fields, table),
+        None => format!("SELECT {}", table),
+    };
+    if let Some(condition) = filter.condition {
+        command.push_str(&format!(" WHERE {}", condition));
+    }
+    match handle_message(&db, &command) {
+        Ok(Command::Select(query)) if query.table_name == table => {
+            let databases = state.databases.read().await;
+            let response = match databases.get(&db) {
+                Some(database) => database.handle_query(query).await,
+                None => database_not_found(&db),
+            };
+            respond(StatusCode::OK, response)
+        }
+        other => unexpected_command(other),
+    }
+}
+
+async fn handle_update(
+    State(state): State<HttpState>,
+    Path((db, table)): Path<(String, String)>,
+    Query(filter): Query<Filter>,
+    Json(body): Json<Value>,
+) -> HttpResponse {
+    let command = match filter.condition {
+        Some(condition) => format!("UPDATE {} {} WHERE {}", table, body, condition),
+        None => return missing_filter(),
+    };
+    match handle_message(&db, &command) {
+        Ok(Command::Update(insert_data, query))
+            if insert_data.table == table && query.table_name == table =>
+        {
+            let mut databases = state.databases.write().await;
+            let response = match databases.get_mut(&db) {
+                Some(database) => database.handle_update(insert_data, query).await,
+                None => database_not_found(&db),
+            };
+            respond(StatusCode::OK, response)
+        }
+        other => unexpected_command(other),
+    }
+}
+
+async fn handle_delete(
+    State(state): State<HttpState>,
+    Path((db, table)): Path<(String, String)>,
+    Query(filter): Query<Filter>,
+) -> HttpResponse {
+    let command = match filter.condition {
+        Some(condition) => format!("DELETE FROM {} WHERE {}", table, condition),
+        None => return missing_filter(),
+    };
+    match handle_message(&db, &command) {
+        Ok(Command::Delete(query)) if query.table_name == table => {
+            let mut databases = state.databases.write().await;
+            let response = match databases.get_mut(&db) {
+                Some(database) => database.handle_delete(query).await,
+                None => database_not_found(&db),
+            };
+            respond(StatusCode::OK, response)
+        }
+        other => unexpected_command(other),
+    }
+}
+
+/// Map the response to a status code, `success` is used when the request succeeded
+fn respond(success: StatusCode, response: DataResponse) -> HttpResponse {
+    let status = match &response {
+        DataResponse::Data(_) | DataResponse::Definitions(_) | DataResponse::Rows(_) => success,
+        DataResponse::Error(error) => status_code(&error.kind),
+    };
+    debug!("HTTP response {}", status);
+    (status, Json(to_json(&response)))
+}
+
+fn status_code(kind: &ErrorKind) -> StatusCode {
+    match kind {
+        ErrorKind::Syntax(_)
+        | ErrorKind::Parse
+        | ErrorKind::Protocol
+        | ErrorKind::Transaction
+        | ErrorKind::DataObject(DataObjectErrorKind::Type) => StatusCode::BAD_REQUEST,
+        ErrorKind::DatabaseNotFound | ErrorKind::TableNotFound => StatusCode::NOT_FOUND,
+        ErrorKind::DatabaseExists | ErrorKind::DataObject(DataObjectErrorKind::DuplicateKey) => {
+            StatusCode::CONFLICT
+        }
+        ErrorKind::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        ErrorKind::DataObject(_) | ErrorKind::Database => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn missing_filter() -> HttpResponse {
+    respond(
+        StatusCode::BAD_REQUEST,
+        DataResponse::error(
+            ErrorKind::Parse,
+            "Missing where query parameter".to_string(),
+        ),
+    )
+}
+
+/// The command built from the route did not parse into the expected command or targets another
+/// table than the route, this happens when the path, the query or the body injects another
+/// command or is not valid
+fn unexpected_command<T: std::fmt::Debug>(
+    command: Result<T, crate::parser::SyntaxError>,
+) -> HttpResponse {
+    let response = match command {
+        Ok(command) => {
+            error!("Unexpected command {:?}", command);
+            DataResponse::error(
+                ErrorKind::Parse,
+                "Request does not match the route".to_string(),
+            )
+        }
+        Err(error) => {
+            error!("Error parsing request {}", error);
+            DataResponse::Error(ErrorResponse::from(error))
+        }
+    };
+    respond(StatusCode::BAD_REQUEST, response)
+}
+
+#[cfg(test)]
+mod test {
+
+    use axum::{
+        body::{to_bytes, Body},
+        http::{Method, Request},
+    };
+    use serde_json::json;
+    use tempfile::Builder;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn call(router: &Router, method: Method, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_document_routes() {
+        let dir = Builder::new()
+            .prefix("data")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let data_path = dir.path().to_str().unwrap().to_string();
+        let router = router(data_path, Arc::new(RwLock::new(HashMap::new())));
+
+        let (status, _) = call(&router, Method::POST, "/xdr", Value::Null).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, body) = call(&router, Method::POST, "/xdr", Value::Null).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["kind"], "DatabaseExists");
+
+        let definition = json!({
+            "name": { "type": "String", "indexed": true, "optional": false },
+            "age": { "type": "Number", "indexed": true, "optional": true }
+        });
+        let (status, _) = call(&router, Method::PUT, "/xdr/user", definition).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let document = json!({ "id": "1", "name": "John", "age": 30 });
+        let (status, body) = call(&router, Method::POST, "/xdr/user/documents", document).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["data"][0]["name"], "John");
+
+        let uri = "/xdr/user?where=name%20%3D%20%27John%27";
+        let (status, body) = call(&router, Method::GET, uri, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["data"],
+            json!([{ "object_id": "1", "id": "1", "name": "John", "age": 30 }])
+        );
+        let (_, body) = call(
+            &router,
+            Method::GET,
+            &format!("{}&fields=age", uri),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(body["data"], json!([{ "object_id": "1", "age": 30 }]));
+
+        let update = json!({ "name": "John", "age": 31 });
+        let (status, _) = call(&router, Method::PATCH, uri, update).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = call(&router, Method::GET, uri, Value::Null).await;
+        assert_eq!(
+            body["data"],
+            json!([{ "object_id": "1", "id": "1", "name": "John", "age": 31 }])
+        );
+
+        let (status, _) = call(&router, Method::DELETE, uri, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = call(&router, Method::GET, uri, Value::Null).await;
+        assert_eq!(body["data"], json!([]));
+
+        let (status, body) = call(&router, Method::GET, "/xdr/user", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], json!([]));
+
+        let (status, body) = call(&router, Method::DELETE, "/xdr/user", Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["kind"], "Parse");
+
+        let (status, body) = call(&router, Method::GET, "/abc/user?where=a%3D1", Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["kind"], "DatabaseNotFound");
+
+        let (status, body) = call(&router, Method::GET, "/xdr/car?where=a%3D1", Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["kind"], "TableNotFound");
+
+        // The table of the command must be the table segment of the path
+        let uri = "/xdr/car%20WHERE%20name%20%3D%20%27John%27";
+        let (status, body) = call(&router, Method::GET, uri, Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["message"], "Request does not match the route");
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt::Display;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Version of the wire protocol spoken by this server
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Largest frame body (version + request id + payload) accepted from a peer
+pub const MAX_FRAME_LENGTH: usize = 16 * 1024 * 1024;
+
+const LENGTH_FIELD: usize = 4;
+const HEADER_LENGTH: usize = 1 + 8; // version + request id
+
+/// Largest payload that fits in a frame a peer accepts
+pub const MAX_PAYLOAD_LENGTH: usize = MAX_FRAME_LENGTH - HEADER_LENGTH;
+
+/// Error type for framing operations
+#[derive(Debug)]
+pub enum FrameError {
+    /// Error reading or writing the underlying stream
+    Io(std::io::Error),
+    /// The peer announced a frame larger than `MAX_FRAME_LENGTH`
+    TooLarge(usize),
+    /// The frame was encoded with a protocol version this server does not speak
+    UnsupportedVersion(u8),
+    /// The frame or its payload could not be decoded
+    Malformed(String),
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "IO Error: {}", e),
+            FrameError::TooLarge(length) => write!(
+                f,
+                "Frame of {} bytes exceeds the maximum of {} bytes",
+                length, MAX_FRAME_LENGTH
+            ),
+            FrameError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported protocol version {}", version)
+            }
+            FrameError::Malformed(e) => write!(f, "Malformed frame: {}", e),
+        }
+    }
+}
+
+/// Frame is a single length-prefixed message on the wire.
+///
+/// Every frame is laid out as
+///
+/// ```text
+/// +----------------+-------------+------------------+-----------------+
+/// | length (u32 BE)| version (u8)| request id (u64) | payload         |
+/// +----------------+-------------+------------------+-----------------+
+/// ```
+///
+/// where `length` counts every byte after the length field itself. Responses carry the
+/// request id of the request they answer so a client can pipeline many requests on one
+/// connection and correlate the responses.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    pub version: u8,
+    pub request_id: u64,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(request_id: u64, payload: Vec<u8>) -> Frame {
+        Frame {
+            version: PROTOCOL_VERSION,
+            request_id,
+            payload,
+        }
+    }
+
+    /// Encode the frame into its wire representation
+    pub fn encode(&self) -> Vec<u8> {
+        let length = (HEADER_LENGTH + self.payload.len()) as u32;
+        let mut buffer = Vec::with_capacity(LENGTH_FIELD + length as usize);
+        buffer.extend_from_slice(&length.to_be_bytes());
+        buffer.push(self.version);
+        buffer.extend_from_slice(&self.request_id.to_be_bytes());
+        buffer.extend_from_slice(&self.payload);
+        buffer
+    }
+
+    /// Read the next frame from the reader.
+    /// Returns `Ok(None)` when the peer closed the connection between two frames.
+    /// # Arguments
+    /// * `reader` - The stream to read the frame from
+    /// # Returns
+    /// * `Result<Option<Frame>, FrameError>` - The frame read from the stream
+    pub async fn read_from<R>(reader: &mut R) -> Result<Option<Frame>, FrameError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut length = [0; LENGTH_FIELD];
+        let read = reader.read(&mut length).await.map_err(FrameError::Io)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        // A peer closing the connection inside the length field cut a frame short
+        match reader.read_exact(&mut length[read..]).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Err(FrameError::Malformed(format!(
+                    "Connection closed after {} bytes of the frame length",
+                    read
+                )))
+            }
+            Err(e) => return Err(FrameError::Io(e)),
+        }
+        let length = u32::from_be_bytes(length) as usize;
+        if length > MAX_FRAME_LENGTH {
+            return Err(FrameError::TooLarge(length));
+        }
+        if length < HEADER_LENGTH {
+            return Err(FrameError::Malformed(format!(
+                "Frame length {} is shorter than the frame header",
+                length
+            )));
+        }
+
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.map_err(FrameError::Io)?;
+
+        let version = body[0];
+        if version != PROTOCOL_VERSION {
+            return Err(FrameError::UnsupportedVersion(version));
+        }
+        let mut request_id = [0; 8];
+        request_id.copy_from_slice(&body[1..HEADER_LENGTH]);
+
+        Ok(Some(Frame {
+            version,
+            request_id: u64::from_be_bytes(request_id),
+            payload: body.split_off(HEADER_LENGTH),
+        }))
+    }
+
+    /// Write the frame to the writer and flush it
+    pub async fn write_to<W>(&self, writer: &mut W) -> Result<(), FrameError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        writer
+            .write_all(&self.encode())
+            .await
+            .map_err(FrameError::Io)?;
+        writer.flush().await.map_err(FrameError::Io)
+    }
+}
+
+/// Request is the payload of a frame sent by a client.
+///
+/// The database name is sent as a `u16` big-endian length followed by its UTF-8 bytes and the
+/// rest of the payload is the command text. Commands that are not bound to a database, such as
+/// `CREATE`, send an empty database name.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Request {
+    pub database: String,
+    pub command: String,
+}
+
+impl Request {
+    pub fn new(database: &str, command: &str) -> Request {
+        Request {
+            database: database.to_string(),
+            command: command.to_string(),
+        }
+    }
+
+    /// Encode the request into a frame payload, database names longer than `u16::MAX` bytes
+    /// do not fit their length field and are rejected
+    pub fn encode(&self) -> Result<Vec<u8>, FrameError> {
+        let database = self.database.as_bytes();
+        let length = u16::try_from(database.len()).map_err(|_| {
+            FrameError::Malformed(format!(
+                "Database name of {} bytes exceeds the maximum of {} bytes",
+                database.len(),
+                u16::MAX
+            ))
+        })?;
+        let mut buffer = Vec::with_capacity(2 + database.len() + self.command.len());
+        buffer.extend_from_slice(&length.to_be_bytes());
+        buffer.extend_from_slice(database);
+        buffer.extend_from_slice(self.command.as_bytes());
+        Ok(buffer)
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Request, FrameError> {
+        if payload.len() < 2 {
+            return Err(FrameError::Malformed(
+                "Request is missing the database length".to_string(),
+            ));
+        }
+        let database_length = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+        let payload = &payload[2..];
+        if payload.len() < database_length {
+            return Err(FrameError::Malformed(format!(
+                "Database name of {} bytes does not fit in the request",
+                database_length
+            )));
+        }
+        let (database, command) = payload.split_at(database_length);
+        let database = String::from_utf8(database.to_vec())
+            .map_err(|e| FrameError::Malformed(format!("Database name is not UTF-8: {}", e)))?;
+        let command = String::from_utf8(command.to_vec())
+            .map_err(|e| FrameError::Malformed(format!("Command is not UTF-8: {}", e)))?;
+        Ok(Request { database, command })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_round_trip() {
+        let frame = Frame::new(42, b"payload".to_vec());
+        let (mut client, mut server) = tokio::io::duplex(64);
+        frame.write_to(&mut client).await.unwrap();
+        drop(client);
+
+        let read = Frame::read_from(&mut server).await.unwrap();
+        assert_eq!(read, Some(frame));
+        let read = Frame::read_from(&mut server).await.unwrap();
+        assert_eq!(read, None);
+    }
+
+    #[tokio::test]
+    async fn test_frames_split_and_coalesced() {
+        let first = Frame::new(1, b"SELECT user WHERE name = 'John'".to_vec());
+        let second = Frame::new(2, b"SELECT user WHERE name = 'Jane'".to_vec());
+        let bytes = [first.encode(), second.encode()].concat();
+
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let writer = tokio::spawn(async move {
+            // Write byte by byte so every frame is split across many reads
+            for byte in bytes {
+                client.write_all(&[byte]).await.unwrap();
+            }
+        });
+
+        assert_eq!(Frame::read_from(&mut server).await.unwrap(), Some(first));
+        assert_eq!(Frame::read_from(&mut server).await.unwrap(), Some(second));
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_version() {
+        let mut frame = Frame::new(1, vec![]);
+        frame.version = PROTOCOL_VERSION + 1;
+        let (mut client, mut server) = tokio::io::duplex(64);
+        frame.write_to(&mut client).await.unwrap();
+
+        match Frame::read_from(&mut server).await {
+            Err(FrameError::UnsupportedVersion(version)) => {
+                assert_eq!(version, PROTOCOL_VERSION + 1)
+            }
+            other => panic!("Expected unsupported version but got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frame_too_large() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client
+            .write_all(&((MAX_FRAME_LENGTH + 1) as u32).to_be_bytes())
+            .await
+            .unwrap();
+
+        match Frame::read_from(&mut server).await {
+            Err(FrameError::TooLarge(length)) => assert_eq!(length, MAX_FRAME_LENGTH + 1),
+            other => panic!("Expected too large but got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_truncated_length() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&[0, 0]).await.unwrap();
+        drop(client);
+
+        match Frame::read_from(&mut server).await {
+            Err(FrameError::Malformed(_)) => {}
+            other => panic!("Expected malformed but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let request = Request::new("xdr", "SELECT user WHERE name = 'John'");
+        let decoded = Request::decode(&request.encode().unwrap()).unwrap();
+        assert_eq!(decoded, request);
+
+        let request = Request::new("", "CREATE xdr");
+        let decoded = Request::decode(&request.encode().unwrap()).unwrap();
+        assert_eq!(decoded.database, "");
+        assert_eq!(decoded.command, "CREATE xdr");
+    }
+
+    #[test]
+    fn test_request_malformed() {
+        assert!(Request::decode(&[0]).is_err());
+        assert!(Request::decode(&[0, 5, b'a']).is_err());
+
+        let request = Request::new(&"x".repeat(u16::MAX as usize + 1), "SELECT user");
+        assert!(matches!(request.encode(), Err(FrameError::Malformed(_))));
+    }
+}
@@ -26,6 +26,16 @@ impl Server {
         let listener = TcpListener::bind(format!("0.0.0.0:{}", self.port))
             .await
             .unwrap();
+        Server::serve(listener, data_path, database).await
+    }
+
+    /// Accept connections on an already bound listener, used to run the server on an ephemeral
+    /// port
+    pub async fn serve(
+        listener: TcpListener,
+        data_path: String,
+        database: Arc<RwLock<HashMap<String, NoSqlDatabase>>>,
+    ) {
         loop {
             let (socket, _) = listener.accept().await.unwrap();
             let database = database.clone();
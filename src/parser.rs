@@ -1,18 +1,20 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{cmp::Ordering, collections::HashMap, fmt::Display};
 
 use log::error;
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while, take_while1},
-    character::complete::{alpha1, char, multispace0, multispace1, space0},
-    combinator::{map, map_res},
-    multi::many0,
-    sequence::{delimited, preceded, tuple},
+    bytes::complete::{tag, tag_no_case, take_while, take_while1},
+    character::complete::{alpha1, char, digit1, multispace0, multispace1, space0},
+    combinator::{all_consuming, map, map_res, not, opt, recognize, verify},
+    multi::separated_list1,
+    sequence::{delimited, preceded, terminated, tuple},
     IResult,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::format::ResponseFormat;
+
 // DML
 const SELECT: &str = "SELECT";
 const INSERT: &str = "INSERT";
@@ -25,16 +27,29 @@ const DEFINE: &str = "DEFINE"; // create structure
 const ALTER: &str = "ALTER";
 const DROP: &str = "DROP";
 
+const DESCRIBE: &str = "DESCRIBE";
+const COMPACT: &str = "COMPACT";
+const REINDEX: &str = "REINDEX";
+
+// Connection
+const FORMAT: &str = "FORMAT";
+const BEGIN: &str = "BEGIN";
+const COMMIT: &str = "COMMIT";
+const ROLLBACK: &str = "ROLLBACK";
+
+/// Attribute of a document holding its object id
+pub const ID: &str = "id";
+
 /// Data type for the database
 ///
 /// # Example
 ///
 /// ```
-/// use crate::parse::{DataType, Data};
+/// use qtable::parser::{Data, DataObject};
 ///
-/// let data = DataType::Object(vec![Data {
-///    key: "test".to_string(),
-///   value: DataType::String("test".to_string()),
+/// let data = DataObject::Object(vec![Data {
+///     key: "test".to_string(),
+///     value: DataObject::String("test".to_string()),
 /// }]);
 /// ```
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -56,29 +71,161 @@ impl Display for DataObject {
                 Number::Float(v) => write!(f, "{}", v),
             },
             DataObject::Bool(value) => write!(f, "{}", value),
-            DataObject::Array(value) => todo!(),
-            DataObject::Object(value) => todo!(),
-            DataObject::Null => todo!(),
+            DataObject::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            DataObject::Object(values) => {
+                write!(f, "{{")?;
+                for (i, data) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", data.key, data.value)?;
+                }
+                write!(f, "}}")
+            }
+            DataObject::Null => write!(f, "null"),
+        }
+    }
+}
+
+impl DataObject {
+    /// Value at the dot separated path, e.g. `address.city`, `None` when a part of the path is
+    /// missing or is not an object
+    pub fn get_path(&self, path: &str) -> Option<&DataObject> {
+        path.split('.').try_fold(self, |data, key| match data {
+            DataObject::Object(attributes) => attributes
+                .iter()
+                .find(|attribute| attribute.key == key)
+                .map(|attribute| &attribute.value),
+            _ => None,
+        })
+    }
+
+    /// Order two values the way `ORDER BY` sorts them. Numbers compare by value, values of
+    /// different types are ordered null, bool, number, string, array, object
+    pub fn compare(&self, other: &DataObject) -> Ordering {
+        match (self, other) {
+            (DataObject::String(a), DataObject::String(b)) => a.cmp(b),
+            (DataObject::Number(a), DataObject::Number(b)) => a.as_f64().total_cmp(&b.as_f64()),
+            (DataObject::Bool(a), DataObject::Bool(b)) => a.cmp(b),
+            (DataObject::Array(a), DataObject::Array(b)) => a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| a.compare(b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (a, b) => a.type_rank().cmp(&b.type_rank()),
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            DataObject::Null => 0,
+            DataObject::Bool(_) => 1,
+            DataObject::Number(_) => 2,
+            DataObject::String(_) => 3,
+            DataObject::Array(_) => 4,
+            DataObject::Object(_) => 5,
+        }
+    }
+
+    /// Keep only the values at the given paths. Nested paths keep their parent objects so
+    /// `address.city` returns `{address: {city: ...}}`, missing paths are left out
+    pub fn project(&self, paths: &[String]) -> DataObject {
+        let mut projected = vec![];
+        for path in paths {
+            if let Some(value) = self.get_path(path) {
+                let keys = path.split('.').collect::<Vec<_>>();
+                insert_path(&mut projected, &keys, value.clone());
+            }
+        }
+        DataObject::Object(projected)
+    }
+
+    /// Convert the data object into a plain JSON value
+    pub fn to_json(&self) -> Value {
+        match self {
+            DataObject::String(value) => Value::String(value.clone()),
+            DataObject::Number(Number::Int(value)) => Value::from(*value),
+            DataObject::Number(Number::Float(value)) => Value::from(*value),
+            DataObject::Bool(value) => Value::Bool(*value),
+            DataObject::Array(values) => {
+                Value::Array(values.iter().map(|value| value.to_json()).collect())
+            }
+            DataObject::Object(values) => Value::Object(
+                values
+                    .iter()
+                    .map(|data| (data.key.clone(), data.value.to_json()))
+                    .collect(),
+            ),
+            DataObject::Null => Value::Null,
         }
     }
 }
 
+fn insert_path(attributes: &mut Vec<Data>, keys: &[&str], value: DataObject) {
+    let (key, rest) = match keys.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    if rest.is_empty() {
+        attributes.push(Data {
+            key: key.to_string(),
+            value,
+        });
+        return;
+    }
+    let position = match attributes
+        .iter()
+        .position(|attribute| attribute.key == *key)
+    {
+        Some(position) => position,
+        None => {
+            attributes.push(Data {
+                key: key.to_string(),
+                value: DataObject::Object(vec![]),
+            });
+            attributes.len() - 1
+        }
+    };
+    if let DataObject::Object(children) = &mut attributes[position].value {
+        insert_path(children, rest, value);
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Number {
     Int(i64),
     Float(f64),
 }
 
+impl Number {
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Number::Int(value) => *value as f64,
+            Number::Float(value) => *value,
+        }
+    }
+}
+
 /// Data is a struct that represents a key value pair
 ///
 /// # Example
 ///
 /// ```
-/// use crate::parse::{Data, DataType};
+/// use qtable::parser::{Data, DataObject};
 ///
 /// let data = Data {
 ///     key: "test".to_string(),
-///     value: DataType::String("test".to_string()),
+///     value: DataObject::String("test".to_string()),
 /// };
 /// ```
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -87,7 +234,7 @@ pub struct Data {
     pub value: DataObject,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum SyntaxErrorCode {
     UnKnownKeyWord,
     InvalidOperator,
@@ -144,18 +291,147 @@ impl Display for SyntaxError {
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Define {
-    pub column: String,
-    pub definition: Definition,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Definition {
-    #[serde(rename(serialize = "type"))]
+    #[serde(rename = "type", alias = "data_type")]
     pub data_type: String,
     pub indexed: bool,
     pub optional: bool,
+    /// Implementation of the index of an indexed column
+    #[serde(default)]
+    pub index: IndexKind,
+    /// No two records hold the same value in a unique column, only indexed columns can be unique
+    #[serde(default)]
+    pub unique: bool,
+    /// How the server assigns the object id of inserts without an `id`, only set on the `id`
+    /// column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated: Option<IdGenerator>,
+}
+
+impl Definition {
+    /// Declared type of the column, columns with an unknown type are treated as strings
+    pub fn column_type(&self) -> DataType {
+        self.data_type.parse().unwrap_or(DataType::String)
+    }
+}
+
+/// DataType is the type a column declares in its definition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataType {
+    String,
+    /// Integer or floating point number
+    Number,
+    Int,
+    Float,
+    Bool,
+    Array,
+    Object,
+}
+
+impl DataType {
+    /// True when the value is of the type. `Int` only accepts integers while `Number` and
+    /// `Float` accept any number, null is accepted by every type as optional columns may be null.
+    pub fn accepts(&self, value: &DataObject) -> bool {
+        matches!(
+            (self, value),
+            (_, DataObject::Null)
+                | (DataType::String, DataObject::String(_))
+                | (DataType::Number | DataType::Float, DataObject::Number(_))
+                | (DataType::Int, DataObject::Number(Number::Int(_)))
+                | (DataType::Bool, DataObject::Bool(_))
+                | (DataType::Array, DataObject::Array(_))
+                | (DataType::Object, DataObject::Object(_))
+        )
+    }
+
+    /// Type of a value, used for attributes that are not part of the definition
+    pub fn of(value: &DataObject) -> DataType {
+        match value {
+            DataObject::Number(_) => DataType::Number,
+            DataObject::Bool(_) => DataType::Bool,
+            DataObject::Array(_) => DataType::Array,
+            DataObject::Object(_) => DataType::Object,
+            DataObject::String(_) | DataObject::Null => DataType::String,
+        }
+    }
+}
+
+impl std::str::FromStr for DataType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "String" => Ok(DataType::String),
+            "Number" => Ok(DataType::Number),
+            "Int" => Ok(DataType::Int),
+            "Float" => Ok(DataType::Float),
+            "Bool" => Ok(DataType::Bool),
+            "Array" => Ok(DataType::Array),
+            "Object" => Ok(DataType::Object),
+            _ => Err(format!("Unknown data type {}", name)),
+        }
+    }
+}
+
+/// IndexKind selects how the index of a column is kept, with `"index": "btree"` in its definition
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexKind {
+    /// The whole index is held in memory and loaded at startup
+    #[default]
+    Memory,
+    /// The index is a B+tree paged from its index file, for indexes larger than memory
+    BTree,
+    /// The whole index is held in a hash map, it only answers equality conditions
+    Hash,
+}
+
+impl IndexKind {
+    /// True when the index answers range and wildcard conditions and orders its values
+    pub fn is_ordered(&self) -> bool {
+        !matches!(self, IndexKind::Hash)
+    }
+}
+
+impl std::str::FromStr for IndexKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "memory" => Ok(IndexKind::Memory),
+            "btree" => Ok(IndexKind::BTree),
+            "hash" => Ok(IndexKind::Hash),
+            _ => Err(format!("Unknown index {}", name)),
+        }
+    }
+}
+
+/// IdGenerator selects how the server assigns the object id of an insert without an `id`, with
+/// `"generated": "ulid"` in the definition of the `id` column
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IdGenerator {
+    /// Random UUID v4
+    #[default]
+    Uuid,
+    /// ULID, ids sort by the time they were assigned
+    Ulid,
+    /// One more than the largest id of the table, the `id` column is an `Int`
+    Increment,
+}
+
+impl std::str::FromStr for IdGenerator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "uuid" => Ok(IdGenerator::Uuid),
+            "ulid" => Ok(IdGenerator::Ulid),
+            "increment" => Ok(IdGenerator::Increment),
+            _ => Err(format!("Unknown id generator {}", name)),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -188,7 +464,76 @@ pub enum FilterValue {
 pub struct Query {
     pub db: String,
     pub table_name: String,
-    pub filter: Condition,
+    /// Condition of the WHERE clause, `None` matches every record
+    pub filter: Option<Condition>,
+    /// Field paths returned by a select, `None` returns the whole record.
+    /// Nested fields are separated with dots, e.g. `address.city`
+    pub projection: Option<Vec<String>>,
+    /// Sort order of a select, `None` keeps the index order
+    pub order_by: Option<OrderBy>,
+    /// Maximum number of records returned by a select
+    pub limit: Option<usize>,
+    /// Number of records skipped before the first one returned by a select
+    pub offset: Option<usize>,
+    /// Aggregate functions of a select, e.g. `COUNT(*)`
+    pub aggregates: Vec<Aggregate>,
+    /// Field the records are grouped by before the aggregates are computed
+    pub group_by: Option<String>,
+}
+
+impl Query {
+    /// True when the select returns aggregated rows instead of records
+    pub fn is_aggregate(&self) -> bool {
+        !self.aggregates.is_empty() || self.group_by.is_some()
+    }
+}
+
+/// Aggregate is a function computed over the records of a group, e.g. `SUM(age)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// Field the function is computed on, `None` for `COUNT(*)`
+    pub field: Option<String>,
+}
+
+impl Aggregate {
+    /// Name of the aggregate in the result rows, `count` for `COUNT(*)` and `sum(age)` for
+    /// `SUM(age)`
+    pub fn name(&self) -> String {
+        let function = match self.function {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+        };
+        match &self.field {
+            Some(field) => format!("{}({})", function, field),
+            None => function.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// OrderBy sorts the records of a select by the value at a field path
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub field: String,
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -199,6 +544,44 @@ pub struct InsertData {
     pub active: bool,
 }
 
+impl InsertData {
+    /// Convert the record into a plain JSON document holding its attributes and `object_id`
+    pub fn to_document(&self) -> Value {
+        let mut document = match self.data.to_json() {
+            Value::Object(document) => document,
+            value => {
+                let mut document = serde_json::Map::new();
+                document.insert("data".to_string(), value);
+                document
+            }
+        };
+        document
+            .entry("object_id")
+            .or_insert_with(|| Value::String(self.object_id.clone()));
+        Value::Object(document)
+    }
+}
+
+/// AlterOperation is the change made to a table definition by `ALTER`
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterOperation {
+    /// Add a new column
+    Add(String, Definition),
+    /// Remove a column from the definition, the stored values are kept in the records
+    Drop(String),
+    /// Replace the definition of an existing column
+    Modify(String, Definition),
+}
+
+/// DropTarget is what `DROP` removes
+#[derive(Debug, Clone, PartialEq)]
+pub enum DropTarget {
+    /// `DROP TABLE <table>`, the database and the table
+    Table(String, String),
+    /// `DROP DATABASE <db>`
+    Database(String),
+}
+
 /// Command is an enum that represents a command
 #[derive(Debug)]
 pub enum Command {
@@ -210,8 +593,24 @@ pub enum Command {
     Delete(Query),
     Create(String),
     Define(String, String, HashMap<String, Definition>),
-    Alter,
-    Drop,
+    /// Alter changes the definition of a column of a table
+    Alter(String, String, AlterOperation),
+    /// Drop removes a table or a whole database with its files
+    Drop(DropTarget),
+    /// Describe returns the definition of one table or of every table of the database
+    Describe(String, Option<String>),
+    /// Format switches the encoding of the responses sent on the connection
+    Format(ResponseFormat),
+    /// Compact rewrites the data file of a table without the records of updates and deletes
+    Compact(String, String),
+    /// Reindex rebuilds the indexes of a table, or of one of its columns, from its data file
+    Reindex(String, String, Option<String>),
+    /// Begin starts a transaction on the database, writes are buffered until `Commit`
+    Begin(String),
+    /// Commit applies the writes buffered since `Begin` as one atomic change
+    Commit,
+    /// Rollback discards the writes buffered since `Begin`
+    Rollback,
 }
 
 /// handle_message is a function that handles a message and returns a Command or a SyntaxError
@@ -219,17 +618,20 @@ pub enum Command {
 /// # Example
 ///
 /// ```
-/// use crate::parse::{handle_message, Command, SyntaxError};
+/// use qtable::parser::{handle_message, Command};
 ///
 /// let message = "SELECT name, age FROM user";
 /// let db = "db";
-/// let result = handle_message(db,message);
+/// let result = handle_message(db, message);
 /// match result {
-///    Ok(Command::Select(fields, table)) => {
-///       assert_eq!(fields, vec!["name", "age"]);
-///       assert_eq!(table, "user");
-///   }
-///  _ => panic!("Expected Select command"),
+///     Ok(Command::Select(query)) => {
+///         assert_eq!(query.table_name, "user");
+///         assert_eq!(
+///             query.projection,
+///             Some(vec!["name".to_string(), "age".to_string()])
+///         );
+///     }
+///     _ => panic!("Expected Select command"),
 /// }
 /// ```
 pub fn handle_message(db: &str, message: &str) -> Result<Command, SyntaxError> {
@@ -248,9 +650,23 @@ pub fn handle_message(db: &str, message: &str) -> Result<Command, SyntaxError> {
     } else if message.starts_with(DEFINE) {
         parse_define_command(db, message)
     } else if message.starts_with(ALTER) {
-        todo!("Alter command");
+        parse_alter_command(db, message)
     } else if message.starts_with(DROP) {
-        todo!("Drop command");
+        parse_drop_command(db, message)
+    } else if message.starts_with(DESCRIBE) {
+        parse_describe_command(db, message)
+    } else if message.starts_with(REINDEX) {
+        parse_reindex_command(db, message)
+    } else if message.starts_with(COMPACT) {
+        parse_compact_command(db, message)
+    } else if message.starts_with(FORMAT) {
+        parse_format_command(message)
+    } else if message.starts_with(BEGIN) {
+        parse_transaction_command(db, message, BEGIN).map(Command::Begin)
+    } else if message.starts_with(COMMIT) {
+        parse_transaction_command(db, message, COMMIT).map(|_| Command::Commit)
+    } else if message.starts_with(ROLLBACK) {
+        parse_transaction_command(db, message, ROLLBACK).map(|_| Command::Rollback)
     } else {
         Err(SyntaxError::ParseError(format!(
             "Unknown command: {}",
@@ -277,14 +693,14 @@ fn remove<'a>(input: &'a str, to_remove: &'a str) -> IResult<&'a str, &'a str> {
 /// * `input` - A string slice that contains the command
 /// # Example
 /// ```
-/// use crate::parse::{parse_create_command, Command, SyntaxError};
+/// use qtable::parser::{parse_create_command, Command};
 /// let message = "CREATE federation";
 /// let result = parse_create_command(message);
 /// match result {
-///    Ok(Command::Create(database)) => {
-///       assert_eq!(database, "federation");
-///  }
-/// _ => panic!("Expected Create command"),
+///     Ok(Command::Create(database)) => {
+///         assert_eq!(database, "federation");
+///     }
+///     _ => panic!("Expected Create command"),
 /// }
 /// ```
 pub fn parse_create_command(input: &str) -> Result<Command, SyntaxError> {
@@ -311,9 +727,166 @@ pub fn parse_create_command(input: &str) -> Result<Command, SyntaxError> {
     Ok(Command::Create(database.to_string()))
 }
 
-/// parse_define_command is a function that parses a define command and returns the document structure as a Command or a SyntaxError
+/// parse_drop_command parses `DROP TABLE <table>` and `DROP DATABASE <db>`
+/// # Example
+/// ```ignore
+/// let result = parse_drop_command("db", "DROP TABLE user");
+/// assert!(matches!(result, Ok(Command::Drop(DropTarget::Table(_, _)))));
+/// ```
+fn parse_drop_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
+    let parsed: IResult<&str, (&str, &str)> = all_consuming(tuple((
+        preceded(
+            terminated(tag(DROP), multispace1),
+            alt((tag("TABLE"), tag("DATABASE"))),
+        ),
+        preceded(multispace1, alpha1),
+    )))(input);
+    match parsed {
+        Ok((_, ("TABLE", table))) => Ok(Command::Drop(DropTarget::Table(
+            db.to_string(),
+            table.to_string(),
+        ))),
+        Ok((_, (_, database))) => Ok(Command::Drop(DropTarget::Database(database.to_string()))),
+        Err(err) => {
+            error!("Error: {:?}", err);
+            Err(SyntaxError::SyntaxError(
+                SyntaxErrorCode::InvalidValue,
+                format!(
+                    "Expected DROP TABLE <table> or DROP DATABASE <db> but found {}",
+                    input
+                ),
+            ))
+        }
+    }
+}
+
+/// parse_describe_command parses `DESCRIBE` and `DESCRIBE <table>`
+/// # Example
+/// ```ignore
+/// let result = parse_describe_command("db", "DESCRIBE user");
+/// assert!(matches!(result, Ok(Command::Describe(_, Some(_)))));
+/// ```
+fn parse_describe_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
+    let table = input[DESCRIBE.len()..].trim();
+    if table.is_empty() {
+        return Ok(Command::Describe(db.to_string(), None));
+    }
+    match all_consuming(extract_table_name)(table) {
+        Ok((_, table)) => Ok(Command::Describe(db.to_string(), Some(table.to_string()))),
+        Err(err) => {
+            error!("Error: {:?}", err);
+            Err(SyntaxError::ParseError(format!(
+                "Could not parse table name: {:?}",
+                err
+            )))
+        }
+    }
+}
+
+/// parse_compact_command parses the table to compact
 /// # Example
+/// ```ignore
+/// let result = parse_compact_command("db", "COMPACT user");
+/// assert!(matches!(result, Ok(Command::Compact(_, table)) if table == "user"));
 /// ```
+fn parse_compact_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
+    let input = match remove(input, COMPACT) {
+        Ok((input, _)) => input,
+        Err(err) => {
+            error!("Error: {:?}", err);
+            return Err(SyntaxError::ParseError(format!(
+                "Expected COMPACT <table> but found {}",
+                err
+            )));
+        }
+    };
+    match all_consuming(extract_table_name)(input.trim()) {
+        Ok((_, table)) => Ok(Command::Compact(db.to_string(), table.to_string())),
+        Err(err) => {
+            error!("Error: {:?}", err);
+            Err(SyntaxError::ParseError(format!(
+                "Could not parse table name: {:?}",
+                err
+            )))
+        }
+    }
+}
+
+/// parse_reindex_command parses the table and the optional column to reindex
+/// # Example
+/// ```ignore
+/// let result = parse_reindex_command("db", "REINDEX user name");
+/// assert!(matches!(result, Ok(Command::Reindex(_, table, Some(column))) if table == "user" && column == "name"));
+/// ```
+fn parse_reindex_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
+    let input = match remove(input, REINDEX) {
+        Ok((input, _)) => input,
+        Err(err) => {
+            error!("Error: {:?}", err);
+            return Err(SyntaxError::ParseError(format!(
+                "Expected REINDEX <table> [column] but found {}",
+                err
+            )));
+        }
+    };
+    let column = preceded(
+        multispace1,
+        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+    );
+    match all_consuming(tuple((extract_table_name, opt(column))))(input.trim()) {
+        Ok((_, (table, column))) => Ok(Command::Reindex(
+            db.to_string(),
+            table.to_string(),
+            column.map(|column| column.to_string()),
+        )),
+        Err(err) => {
+            error!("Error: {:?}", err);
+            Err(SyntaxError::ParseError(format!(
+                "Could not parse REINDEX: {:?}",
+                err
+            )))
+        }
+    }
+}
+
+/// parse_format_command parses the response format requested for the connection
+/// # Example
+/// ```ignore
+/// let result = parse_format_command("FORMAT JSON");
+/// assert!(matches!(result, Ok(Command::Format(ResponseFormat::Json))));
+/// ```
+fn parse_format_command(input: &str) -> Result<Command, SyntaxError> {
+    let input = match remove(input, FORMAT) {
+        Ok((input, _)) => input,
+        Err(err) => {
+            error!("Error: {:?}", err);
+            return Err(SyntaxError::SyntaxError(
+                SyntaxErrorCode::InvalidValue,
+                format!("Expected FORMAT but found {}", err),
+            ));
+        }
+    };
+    match input.trim().parse::<ResponseFormat>() {
+        Ok(format) => Ok(Command::Format(format)),
+        Err(err) => Err(SyntaxError::SyntaxError(SyntaxErrorCode::InvalidValue, err)),
+    }
+}
+
+/// parse_transaction_command checks `BEGIN`, `COMMIT` or `ROLLBACK` stands alone and returns
+/// the database of the transaction
+fn parse_transaction_command(db: &str, input: &str, keyword: &str) -> Result<String, SyntaxError> {
+    if input != keyword {
+        return Err(SyntaxError::ParseError(format!(
+            "Expected {} but found {}",
+            keyword, input
+        )));
+    }
+    Ok(db.to_string())
+}
+
+/// parse_define_command is a function that parses a define command and returns the document structure as a Command or a SyntaxError
+/// # Example
+/// ```ignore
 /// use crate::parse::{parse_define_command, Command, SyntaxError};
 /// let message = r#"DEFINE user { name: { type: "String", indexed: true, optional: false }, age: { type: "Number", indexed: false, optional: true}}";
 /// let result = parse_define_command(message);
@@ -369,57 +942,7 @@ fn parse_define_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
             Value::Object(obj) => {
                 let mut define = HashMap::new();
                 for (key, value) in obj.iter() {
-                    let definition = match value {
-                        Value::Object(o) => {
-                            let data_type = match o.get("type") {
-                                Some(Value::String(s)) => s.to_string(),
-                                _ => {
-                                    return Err(SyntaxError::SyntaxError(
-                                        SyntaxErrorCode::InvalidDataType,
-                                        format!(
-                                            "Invalid value for type, expected String but found {}",
-                                            key
-                                        ),
-                                    ))
-                                }
-                            };
-                            let indexed = match o.get("indexed") {
-                                Some(Value::Bool(b)) => *b,
-                                _ => {
-                                    return Err(SyntaxError::SyntaxError(
-                                        SyntaxErrorCode::InvalidValue,
-                                        format!(
-                                            "Invalid value for indexed, expected Bool but found {}",
-                                            key
-                                        ),
-                                    ))
-                                }
-                            };
-                            let optional = match o.get("optional") {
-                                Some(Value::Bool(b)) => *b,
-                                _ => {
-                                    return Err(SyntaxError::SyntaxError(
-                                        SyntaxErrorCode::InvalidValue,
-                                        format!(
-                                        "Invalid value for optional, expected Bool but found {}",
-                                        key
-                                    ),
-                                    ))
-                                }
-                            };
-                            Definition {
-                                data_type,
-                                indexed,
-                                optional,
-                            }
-                        }
-                        _ => {
-                            return Err(SyntaxError::SyntaxError(
-                                SyntaxErrorCode::UnKnownKeyWord,
-                                format!("Unknown key {}", key),
-                            ))
-                        }
-                    };
+                    let definition = parse_definition(key, value)?;
                     define.insert(key.to_string(), definition);
                 }
                 Ok(Command::Define(
@@ -443,6 +966,192 @@ fn parse_define_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
     }
 }
 
+/// parse_definition converts the JSON definition of the column `key` into a Definition
+fn parse_definition(key: &str, value: &Value) -> Result<Definition, SyntaxError> {
+    match value {
+        Value::Object(o) => {
+            let data_type = match o.get("type") {
+                Some(Value::String(s)) if s.parse::<DataType>().is_ok() => s.to_string(),
+                Some(Value::String(s)) => {
+                    return Err(SyntaxError::SyntaxError(
+                        SyntaxErrorCode::InvalidDataType,
+                        format!(
+                            "Unknown type {} for {}, expected one of String, Number, Int, Float, Bool, Array or Object",
+                            s, key
+                        ),
+                    ))
+                }
+                _ => {
+                    return Err(SyntaxError::SyntaxError(
+                        SyntaxErrorCode::InvalidDataType,
+                        format!("Invalid value for type, expected String but found {}", key),
+                    ))
+                }
+            };
+            let indexed = match o.get("indexed") {
+                Some(Value::Bool(b)) => *b,
+                _ => {
+                    return Err(SyntaxError::SyntaxError(
+                        SyntaxErrorCode::InvalidValue,
+                        format!("Invalid value for indexed, expected Bool but found {}", key),
+                    ))
+                }
+            };
+            let optional = match o.get("optional") {
+                Some(Value::Bool(b)) => *b,
+                _ => {
+                    return Err(SyntaxError::SyntaxError(
+                        SyntaxErrorCode::InvalidValue,
+                        format!(
+                            "Invalid value for optional, expected Bool but found {}",
+                            key
+                        ),
+                    ))
+                }
+            };
+            let index = match o.get("index") {
+                None => IndexKind::default(),
+                Some(Value::String(s)) if s.parse::<IndexKind>().is_ok() => s.parse().unwrap(),
+                Some(_) => {
+                    return Err(SyntaxError::SyntaxError(
+                        SyntaxErrorCode::InvalidValue,
+                        format!(
+                            "Invalid value for index, expected memory, btree or hash but found {}",
+                            key
+                        ),
+                    ))
+                }
+            };
+            let unique = match o.get("unique") {
+                None => false,
+                Some(Value::Bool(b)) if !*b || indexed => *b,
+                Some(Value::Bool(_)) => {
+                    return Err(SyntaxError::SyntaxError(
+                        SyntaxErrorCode::InvalidValue,
+                        format!("Unique column {} must be indexed", key),
+                    ))
+                }
+                _ => {
+                    return Err(SyntaxError::SyntaxError(
+                        SyntaxErrorCode::InvalidValue,
+                        format!("Invalid value for unique, expected Bool but found {}", key),
+                    ))
+                }
+            };
+            let generated = match o.get("generated") {
+                None => None,
+                Some(Value::String(s)) if key == ID => match s.parse::<IdGenerator>() {
+                    Ok(IdGenerator::Increment) if data_type != "Int" => {
+                        return Err(SyntaxError::SyntaxError(
+                            SyntaxErrorCode::InvalidValue,
+                            "Incremented ids need an Int id column".to_string(),
+                        ))
+                    }
+                    Ok(generator) if generator != IdGenerator::Increment && data_type != "String" => {
+                        return Err(SyntaxError::SyntaxError(
+                            SyntaxErrorCode::InvalidValue,
+                            format!("Generated {} ids need a String id column", s),
+                        ))
+                    }
+                    Ok(generator) => Some(generator),
+                    Err(_) => {
+                        return Err(SyntaxError::SyntaxError(
+                            SyntaxErrorCode::InvalidValue,
+                            format!(
+                                "Invalid value for generated, expected uuid, ulid or increment but found {}",
+                                s
+                            ),
+                        ))
+                    }
+                },
+                Some(_) => {
+                    return Err(SyntaxError::SyntaxError(
+                        SyntaxErrorCode::InvalidValue,
+                        format!("Only the {} column can be generated, found {}", ID, key),
+                    ))
+                }
+            };
+            Ok(Definition {
+                data_type,
+                indexed,
+                optional,
+                index,
+                unique,
+                generated,
+            })
+        }
+        _ => Err(SyntaxError::SyntaxError(
+            SyntaxErrorCode::UnKnownKeyWord,
+            format!("Unknown key {}", key),
+        )),
+    }
+}
+
+/// parse_alter_command parses the change of a table definition
+/// ALTER user ADD age {"type": "Number", "indexed": true, "optional": true}
+/// ALTER user MODIFY age {"type": "Number", "indexed": false, "optional": true}
+/// ALTER user DROP age
+/// # Example
+/// ```ignore
+/// let result = parse_alter_command("db", "ALTER user DROP age");
+/// match result {
+///     Ok(Command::Alter(_, table, AlterOperation::Drop(column))) => {
+///         assert_eq!(table, "user");
+///         assert_eq!(column, "age");
+///     }
+///     _ => panic!("Expected Alter command"),
+/// }
+/// ```
+fn parse_alter_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
+    let parsed: IResult<&str, (&str, &str, &str)> = tuple((
+        preceded(terminated(tag(ALTER), multispace1), alpha1),
+        preceded(multispace1, alt((tag("ADD"), tag("DROP"), tag("MODIFY")))),
+        preceded(
+            multispace1,
+            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+        ),
+    ))(input);
+    let (input, (table, operation, column)) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            error!("Error: {:?}", err);
+            return Err(SyntaxError::SyntaxError(
+                SyntaxErrorCode::InvalidValue,
+                format!(
+                    "Expected ALTER <table> ADD|DROP|MODIFY <column> but found {}",
+                    input
+                ),
+            ));
+        }
+    };
+    let input = input.trim();
+
+    let operation = if operation == "DROP" {
+        if !input.is_empty() {
+            return Err(SyntaxError::ParseError(format!(
+                "Unexpected input after DROP {}: {}",
+                column, input
+            )));
+        }
+        AlterOperation::Drop(column.to_string())
+    } else {
+        let json = serde_json::from_str::<Value>(input).map_err(|e| {
+            error!("Wrong JSON format for alter command {:?}", e);
+            SyntaxError::SyntaxError(
+                SyntaxErrorCode::InvalidDefinition,
+                format!("Wrong JSON format for alter command {:?}", e),
+            )
+        })?;
+        let definition = parse_definition(column, &json)?;
+        if operation == "ADD" {
+            AlterOperation::Add(column.to_string(), definition)
+        } else {
+            AlterOperation::Modify(column.to_string(), definition)
+        }
+    };
+    Ok(Command::Alter(db.to_string(), table.to_string(), operation))
+}
+
 ///
 /// parse_update_command is a function that parses an update command and returns a Command or a SyntaxError
 /// UPDATE user {"name":"John","age":30} WHERE id = '123' and name = 'John' and age >= 30
@@ -513,17 +1222,6 @@ fn parse_update_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
         }
     };
 
-    let (_, _) = match extract_json(input) {
-        Ok((input, json_str)) => (input, json_str),
-        Err(err) => {
-            error!("Error: {:?}", err);
-            return Err(SyntaxError::ParseError(format!(
-                "Could not parse JSON: {:?}",
-                err
-            )));
-        }
-    };
-
     let (_input, filter) = match parse_condition(input) {
         Ok((input, filter)) => (input, filter),
         Err(x) => {
@@ -538,7 +1236,13 @@ fn parse_update_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
     let query = Query {
         db: db.to_string(),
         table_name: table_name.to_string(),
-        filter,
+        filter: Some(filter),
+        projection: None,
+        order_by: None,
+        limit: None,
+        offset: None,
+        aggregates: vec![],
+        group_by: None,
     };
 
     let update_data = InsertData {
@@ -567,7 +1271,7 @@ fn parse_update_json(input: &str) -> IResult<&str, Value> {
 
 /// parse_delete_command is a function that parses a delete command and returns a Command or a SyntaxError
 /// # Example
-/// ```
+/// ```ignore
 /// use crate::parse::{parse_delete_command, Command, SyntaxError};
 /// let message = r#"DELETE FROM user WHERE id = '123' and name = 'John' and age >= 30";
 /// let result = parse_delete_command(message);
@@ -638,37 +1342,18 @@ fn parse_delete_command(db: &str, input: &str) -> Result<Command, SyntaxError> {
     let query = Query {
         db: db.to_string(),
         table_name: table_name.to_string(),
-        filter,
+        filter: Some(filter),
+        projection: None,
+        order_by: None,
+        limit: None,
+        offset: None,
+        aggregates: vec![],
+        group_by: None,
     };
 
     Ok(Command::Delete(query))
 }
 
-fn parse_delete_json<'a>(
-    json_str: &'a str,
-    table_name: &'a str,
-) -> Result<(String, DataObject), SyntaxError> {
-    match serde_json::from_str(json_str) {
-        Ok(json) => match json {
-            Value::Object(obj) => {
-                let data = handle_object(obj.to_owned());
-                Ok((table_name.to_owned(), data))
-            }
-            _ => Err(SyntaxError::ParseError(format!(
-                "Expected Object but found {}",
-                json_str
-            ))),
-        },
-        Err(e) => {
-            error!("Error parsing JSON: {}", e);
-            Err(SyntaxError::ParseError(format!(
-                "Could not parse JSON: {:?}",
-                e
-            )))
-        }
-    }
-}
-
 fn parse_insert_command(_: &str, input: &str) -> Result<Command, SyntaxError> {
     let input = match remove(input, "INSERT INTO") {
         Ok((input, _)) => input,
@@ -739,7 +1424,7 @@ fn parse_json<'a>(
     }
 }
 
-fn parse_json_value<'a>(json: Value) -> Result<DataObject, SyntaxError> {
+pub(crate) fn parse_json_value(json: Value) -> Result<DataObject, SyntaxError> {
     match json {
         Value::Object(obj) => {
             let data = handle_object(obj.to_owned());
@@ -752,18 +1437,21 @@ fn parse_json_value<'a>(json: Value) -> Result<DataObject, SyntaxError> {
     }
 }
 
+/// Object id of an insert, empty when the document has no `id` and the server assigns it
 fn get_id(obj: &serde_json::Map<String, Value>) -> Result<String, SyntaxError> {
-    let id = match obj.get("id") {
+    let id = match obj.get(ID) {
         Some(some) => match some {
-            Value::String(s) => s.to_owned(),
+            Value::String(s) if !s.is_empty() => s.to_owned(),
+            Value::Number(n) if n.is_i64() => n.to_string(),
             _ => {
                 return Err(SyntaxError::SyntaxError(
                     SyntaxErrorCode::InvalidValue,
-                    "Expected String for ID but found something else".to_string(),
+                    "Expected a non empty String or an Int for ID but found something else"
+                        .to_string(),
                 ))
             }
         },
-        None => uuid::Uuid::new_v4().to_string(),
+        None => String::new(),
     };
     Ok(id)
 }
@@ -836,7 +1524,7 @@ fn handle_object(object: serde_json::Map<String, Value>) -> DataObject {
 ///
 /// parse_select is a function that parses a select command and returns a Command or a SyntaxError
 /// # Example
-/// ```
+/// ```ignore
 /// use crate::parse::{parse_select, Command, SyntaxError};
 /// let message = "SELECT user WHERE id = '123' and name = 'John' and age >= 30";
 /// let result = parse_select(message);
@@ -860,8 +1548,20 @@ fn parse_select(db: &str, input: &str) -> Result<Command, SyntaxError> {
         }
     };
 
-    let (input, table_name) = match extract_select_table(input) {
-        Ok((input, table_name)) => (input, table_name),
+    // SELECT <fields> FROM <table> or the short form SELECT <table>
+    let (input, (items, table_name)) = match alt((
+        map(
+            tuple((
+                parse_select_items,
+                delimited(multispace1, tag("FROM"), multispace1),
+                parse_select_table,
+            )),
+            |(items, _, table_name)| (items, table_name),
+        ),
+        map(parse_select_table, |table_name| (None, table_name)),
+    ))(input)
+    {
+        Ok((input, parsed)) => (input, parsed),
         Err(err) => {
             error!("Error: {:?}", err);
             return Err(SyntaxError::ParseError(format!(
@@ -871,82 +1571,112 @@ fn parse_select(db: &str, input: &str) -> Result<Command, SyntaxError> {
         }
     };
 
-    let input = match remove(input, "WHERE") {
-        Ok((input, _)) => input,
+    // Without a WHERE clause every record of the table is selected
+    let (input, filter) = match preceded(multispace1, |input| remove(input, "WHERE"))(input) {
+        Ok((input, _)) => match parse_condition(input) {
+            Ok((input, filter)) => (input, Some(filter)),
+            Err(x) => {
+                error!("Error: {:?}", x);
+                return Err(SyntaxError::ParseError(format!(
+                    "Could not parse condition: {:?}",
+                    x
+                )));
+            }
+        },
+        Err(_) => (input, None),
+    };
+
+    let options = match parse_select_options(input) {
+        Ok((_, options)) => options,
         Err(err) => {
             error!("Error: {:?}", err);
             return Err(SyntaxError::SyntaxError(
                 SyntaxErrorCode::InvalidValue,
-                format!("Expected WHERE but found {}", err),
+                format!(
+                    "Expected GROUP BY, ORDER BY, LIMIT or OFFSET but found {}",
+                    input.trim()
+                ),
             ));
         }
     };
 
-    let (_input, filter) = match parse_condition(input) {
-        Ok((input, filter)) => (input, filter),
-        Err(x) => {
-            error!("Error: {:?}", x);
-            return Err(SyntaxError::ParseError(format!(
-                "Could not parse condition: {:?}",
-                x
-            )));
+    let mut projection = None;
+    let mut aggregates = vec![];
+    if let Some(items) = items {
+        let mut fields = vec![];
+        for item in items {
+            match item {
+                SelectItem::Field(field) => fields.push(field),
+                SelectItem::Aggregate(aggregate) => aggregates.push(aggregate),
+            }
         }
-    };
+        projection = Some(fields);
+    }
+    if !aggregates.is_empty() || options.group_by.is_some() {
+        // Aggregated rows only carry the group field next to the aggregates
+        let fields = projection.take().unwrap_or_default();
+        if let Some(field) = fields
+            .iter()
+            .find(|field| Some(*field) != options.group_by.as_ref())
+        {
+            return Err(SyntaxError::SyntaxError(
+                SyntaxErrorCode::InvalidValue,
+                format!("Field {} must be aggregated or used in GROUP BY", field),
+            ));
+        }
+    }
 
     let query = Query {
         db: db.to_string(),
         table_name: table_name.to_string(),
         filter,
+        projection,
+        order_by: options.order_by,
+        limit: options.limit,
+        offset: options.offset,
+        aggregates,
+        group_by: options.group_by,
     };
 
     Ok(Command::Select(query))
 }
 
+/// Parses a condition where `AND` binds tighter than `OR` and parentheses group conditions.
+/// Chains of the same operator nest to the right, `a AND b AND c` is `a AND (b AND c)`.
 fn parse_condition(input: &str) -> IResult<&str, Condition> {
     let (input, _) = multispace0(input)?;
-    let (input, first_condition) = parse_complex_condition(input)?;
-    let (input, conditions) = many0(tuple((
-        preceded(multispace0, alt((tag("AND"), tag("OR")))),
-        preceded(multispace0, parse_complex_condition),
-    )))(input)?;
-
-    let condition = conditions
-        .into_iter()
-        .fold(first_condition, |acc, (op, next)| {
-            if op == "AND" {
-                Condition::And(Box::new(acc), Box::new(next))
-            } else {
-                Condition::Or(Box::new(acc), Box::new(next))
-            }
-        });
-
-    Ok((input, condition))
+    let (input, left) = parse_and_condition(input)?;
+    let (input, right) = opt(preceded(
+        delimited(multispace0, tag_no_case("OR"), multispace1),
+        parse_condition,
+    ))(input)?;
+    match right {
+        Some(right) => Ok((input, Condition::Or(Box::new(left), Box::new(right)))),
+        None => Ok((input, left)),
+    }
 }
 
-fn parse_complex_condition(input: &str) -> IResult<&str, Condition> {
-    if input.starts_with('(') && input.ends_with(')') {
-        //#FIXME A hack to remove the brackets, should use nom to do this
-        let input = input
-            .strip_prefix('(')
-            .unwrap_or(input)
-            .strip_suffix(')')
-            .unwrap_or(input);
-        return parse_condition(input);
+fn parse_and_condition(input: &str) -> IResult<&str, Condition> {
+    let (input, left) = parse_complex_condition(input)?;
+    let (input, right) = opt(preceded(
+        delimited(multispace0, tag_no_case("AND"), multispace1),
+        parse_and_condition,
+    ))(input)?;
+    match right {
+        Some(right) => Ok((input, Condition::And(Box::new(left), Box::new(right)))),
+        None => Ok((input, left)),
     }
+}
 
-    parse_simple_condition(input)
-    // alt((
-    //     map(
-    //         delimited(
-    //             char('('),
-    //             cut(parse_condition),
-    //             char(')'),
-    //         ),
-    //         |condition| condition,
-    //     ),
-    //     parse_simple_condition,
-    // ))(input)
-    // Ok((" AND (name = 'John' OR age >= 30)", Condition::Equal("id".to_string(), "123".to_string())))
+fn parse_complex_condition(input: &str) -> IResult<&str, Condition> {
+    alt((
+        delimited(
+            terminated(char('('), multispace0),
+            parse_condition,
+            preceded(multispace0, char(')')),
+        ),
+        parse_simple_condition,
+    ))(input)
 }
 
 fn parse_simple_condition(input: &str) -> IResult<&str, Condition> {
@@ -1024,18 +1754,159 @@ fn parse_simple_condition(input: &str) -> IResult<&str, Condition> {
     ))(input)
 }
 
+/// Parses a literal of a condition: a quoted string that may hold any character but the quote,
+/// a number with an optional sign and decimals, or a bare word
 fn parse_value(input: &str) -> IResult<&str, String> {
     alt((
-        delimited(
-            char('\''),
-            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
-            char('\''),
+        delimited(char('\''), take_while(|c: char| c != '\''), char('\'')),
+        terminated(
+            recognize(tuple((
+                opt(char('-')),
+                digit1,
+                opt(preceded(char('.'), digit1)),
+            ))),
+            not(take_while1(|c: char| {
+                c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+            })),
         ),
         take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
     ))(input)
     .map(|(next_input, res)| (next_input, res.to_string()))
 }
 
+/// A field path or an aggregate function listed after `SELECT`
+enum SelectItem {
+    Field(String),
+    Aggregate(Aggregate),
+}
+
+/// Parses `*` or a comma separated list of field paths and aggregate functions
+fn parse_select_items(input: &str) -> IResult<&str, Option<Vec<SelectItem>>> {
+    alt((
+        map(char('*'), |_| None),
+        map(
+            separated_list1(
+                delimited(multispace0, char(','), multispace0),
+                alt((
+                    map(parse_aggregate, SelectItem::Aggregate),
+                    map(parse_field_path, |field| {
+                        SelectItem::Field(field.to_string())
+                    }),
+                )),
+            ),
+            Some,
+        ),
+    ))(input)
+}
+
+/// Parses `COUNT(*)` or `<function>(<field>)`, only `COUNT` accepts `*`
+fn parse_aggregate(input: &str) -> IResult<&str, Aggregate> {
+    map(
+        verify(
+            tuple((
+                alt((
+                    map(tag_no_case("COUNT"), |_| AggregateFunction::Count),
+                    map(tag_no_case("SUM"), |_| AggregateFunction::Sum),
+                    map(tag_no_case("AVG"), |_| AggregateFunction::Avg),
+                    map(tag_no_case("MIN"), |_| AggregateFunction::Min),
+                    map(tag_no_case("MAX"), |_| AggregateFunction::Max),
+                )),
+                delimited(
+                    terminated(char('('), multispace0),
+                    alt((
+                        map(char('*'), |_| None),
+                        map(parse_field_path, |field| Some(field.to_string())),
+                    )),
+                    preceded(multispace0, char(')')),
+                ),
+            )),
+            |(function, field)| field.is_some() || *function == AggregateFunction::Count,
+        ),
+        |(function, field)| Aggregate { function, field },
+    )(input)
+}
+
+fn parse_field_path(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '.')(input)
+}
+
+/// GROUP BY, ORDER BY, LIMIT and OFFSET of a select
+struct SelectOptions {
+    group_by: Option<String>,
+    order_by: Option<OrderBy>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// Parses `[GROUP BY <field>] [ORDER BY <field> [ASC|DESC]] [LIMIT n] [OFFSET m]` at the end of
+/// a select
+fn parse_select_options(input: &str) -> IResult<&str, SelectOptions> {
+    map(
+        all_consuming(terminated(
+            tuple((
+                opt(preceded(keywords("GROUP", "BY"), parse_field_path)),
+                opt(preceded(keywords("ORDER", "BY"), parse_order_by)),
+                opt(preceded(
+                    delimited(multispace1, tag_no_case("LIMIT"), multispace1),
+                    parse_usize,
+                )),
+                opt(preceded(
+                    delimited(multispace1, tag_no_case("OFFSET"), multispace1),
+                    parse_usize,
+                )),
+            )),
+            multispace0,
+        )),
+        |(group_by, order_by, limit, offset)| SelectOptions {
+            group_by: group_by.map(|field| field.to_string()),
+            order_by,
+            limit,
+            offset,
+        },
+    )(input)
+}
+
+/// Parses two keywords separated and surrounded by white space, e.g. ` ORDER BY `
+fn keywords<'a>(first: &'a str, second: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, ()> {
+    map(
+        tuple((
+            multispace1,
+            tag_no_case(first),
+            multispace1,
+            tag_no_case(second),
+            multispace1,
+        )),
+        |_| (),
+    )
+}
+
+fn parse_order_by(input: &str) -> IResult<&str, OrderBy> {
+    map(
+        tuple((
+            parse_field_path,
+            opt(preceded(
+                multispace1,
+                alt((
+                    map(tag_no_case("ASC"), |_| SortDirection::Asc),
+                    map(tag_no_case("DESC"), |_| SortDirection::Desc),
+                )),
+            )),
+        )),
+        |(field, direction): (&str, Option<SortDirection>)| OrderBy {
+            field: field.to_string(),
+            direction: direction.unwrap_or(SortDirection::Asc),
+        },
+    )(input)
+}
+
+fn parse_usize(input: &str) -> IResult<&str, usize> {
+    map_res(digit1, |digits: &str| digits.parse::<usize>())(input)
+}
+
+fn parse_select_table(input: &str) -> IResult<&str, &str> {
+    preceded(space0, alpha1)(input)
+}
+
 fn extract_select_table(input: &str) -> IResult<&str, &str> {
     delimited(space0, alpha1, tag(" "))(input)
 }
@@ -1079,13 +1950,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_insert_id() {
+        let object_id = |message| match parse_insert_command("db", message) {
+            Ok(Command::Insert(insert_data)) => insert_data.object_id,
+            result => panic!("Expected Insert command but got {:?}", result),
+        };
+        // The server assigns the id of documents without one
+        assert_eq!(object_id(r#"INSERT INTO user {"name":"John"}"#), "");
+        assert_eq!(
+            object_id(r#"INSERT INTO user {"id":"a1","name":"John"}"#),
+            "a1"
+        );
+        assert_eq!(object_id(r#"INSERT INTO user {"id":7,"name":"John"}"#), "7");
+        for message in [
+            r#"INSERT INTO user {"id":"","name":"John"}"#,
+            r#"INSERT INTO user {"id":1.5,"name":"John"}"#,
+        ] {
+            assert!(matches!(
+                parse_insert_command("db", message),
+                Err(SyntaxError::SyntaxError(SyntaxErrorCode::InvalidValue, _))
+            ));
+        }
+
+        let message = r#"DEFINE user { "id": { "type": "String", "indexed": false, "optional": false, "generated": "ulid" }}"#;
+        match parse_define_command("user", message) {
+            Ok(Command::Define(_, _, define)) => {
+                assert_eq!(define["id"].generated, Some(IdGenerator::Ulid))
+            }
+            result => panic!("Expected Define command but got {:?}", result),
+        }
+        for message in [
+            r#"DEFINE user { "id": { "type": "String", "indexed": false, "optional": false, "generated": "increment" }}"#,
+            r#"DEFINE user { "id": { "type": "Int", "indexed": false, "optional": false, "generated": "uuid" }}"#,
+            r#"DEFINE user { "id": { "type": "String", "indexed": false, "optional": false, "generated": "serial" }}"#,
+            r#"DEFINE user { "key": { "type": "String", "indexed": false, "optional": false, "generated": "uuid" }}"#,
+        ] {
+            assert!(matches!(
+                parse_define_command("user", message),
+                Err(SyntaxError::SyntaxError(SyntaxErrorCode::InvalidValue, _))
+            ));
+        }
+    }
+
     #[test]
     fn test_parse_delete_command() {
         let db = "db";
         //SELECT       user WHERE id = '123' and name = 'John' and age >= 30
         let message = r#"DELETE FROM user WHERE id = '123' AND (name = 'John' OR age >= 30)"#;
         if let Command::Delete(query) = parse_delete_command(db, message).unwrap() {
-            match query.filter {
+            match query.filter.unwrap() {
                 Condition::And(left, right) => {
                     match *left {
                         Condition::Equal(field, value) => {
@@ -1159,6 +2073,63 @@ mod tests {
                 panic!("Expected Define command but got {:?}", e);
             }
         }
+
+        let message =
+            r#"DEFINE user { "name": { "type": "Text", "indexed": true, "optional": false }}"#;
+        assert!(matches!(
+            parse_define_command("user", message),
+            Err(SyntaxError::SyntaxError(
+                SyntaxErrorCode::InvalidDataType,
+                _
+            ))
+        ));
+
+        let message = r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false, "index": "btree" }, "age": { "type": "Number", "indexed": true, "optional": true }}"#;
+        match parse_define_command("user", message) {
+            Ok(Command::Define(_, _, define)) => {
+                assert_eq!(define["name"].index, IndexKind::BTree);
+                assert_eq!(define["age"].index, IndexKind::Memory);
+            }
+            result => panic!("Expected Define command but got {:?}", result),
+        }
+        let message = r#"DEFINE user { "email": { "type": "String", "indexed": true, "optional": false, "index": "hash" }}"#;
+        match parse_define_command("user", message) {
+            Ok(Command::Define(_, _, define)) => {
+                assert_eq!(define["email"].index, IndexKind::Hash);
+                assert!(!define["email"].unique);
+            }
+            result => panic!("Expected Define command but got {:?}", result),
+        }
+        let message = r#"DEFINE user { "name": { "type": "String", "indexed": true, "optional": false, "index": "skiplist" }}"#;
+        assert!(matches!(
+            parse_define_command("user", message),
+            Err(SyntaxError::SyntaxError(SyntaxErrorCode::InvalidValue, _))
+        ));
+        let message = r#"DEFINE user { "email": { "type": "String", "indexed": true, "optional": false, "unique": true }}"#;
+        match parse_define_command("user", message) {
+            Ok(Command::Define(_, _, define)) => assert!(define["email"].unique),
+            result => panic!("Expected Define command but got {:?}", result),
+        }
+        let message = r#"DEFINE user { "email": { "type": "String", "indexed": false, "optional": false, "unique": true }}"#;
+        assert!(matches!(
+            parse_define_command("user", message),
+            Err(SyntaxError::SyntaxError(SyntaxErrorCode::InvalidValue, _))
+        ));
+    }
+
+    #[test]
+    fn test_data_type_accepts() {
+        let int = DataObject::Number(Number::Int(1));
+        let float = DataObject::Number(Number::Float(1.5));
+        assert!(DataType::Int.accepts(&int));
+        assert!(!DataType::Int.accepts(&float));
+        assert!(DataType::Float.accepts(&int));
+        assert!(DataType::Number.accepts(&float));
+        assert!(!DataType::String.accepts(&int));
+        assert!(DataType::Bool.accepts(&DataObject::Bool(true)));
+        assert!(DataType::Array.accepts(&DataObject::Array(vec![])));
+        assert!(!DataType::Object.accepts(&DataObject::Array(vec![])));
+        assert!(DataType::String.accepts(&DataObject::Null));
     }
 
     #[test]
@@ -1168,7 +2139,7 @@ mod tests {
             Ok(command) => match command {
                 Command::Select(query) => {
                     assert_eq!(query.table_name, "user");
-                    match query.filter {
+                    match query.filter.unwrap() {
                         Condition::And(left, right) => {
                             match *left {
                                 Condition::Equal(field, value) => {
@@ -1314,7 +2285,7 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_update(){
+    fn test_parse_update() {
         let db = "db";
         let message = r#"UPDATE user {"name":"John","age":30} WHERE id = '123' and name = 'John' and age >= 30"#;
         if let Command::Update(update_data, query) = parse_update_command(db, message).unwrap() {
@@ -1340,7 +2311,7 @@ mod tests {
                     panic!("Expected object");
                 }
             }
-            match query.filter {
+            match query.filter.unwrap() {
                 Condition::And(left, right) => {
                     match *left {
                         Condition::Equal(field, value) => {
@@ -1385,4 +2356,314 @@ mod tests {
             panic!("Expected Update command");
         }
     }
+
+    #[test]
+    fn test_parse_format_command() {
+        match handle_message("db", "FORMAT json") {
+            Ok(Command::Format(format)) => assert_eq!(format, ResponseFormat::Json),
+            other => panic!("Expected Format command but got {:?}", other),
+        }
+        assert!(handle_message("db", "FORMAT xml").is_err());
+    }
+
+    #[test]
+    fn test_parse_compact_command() {
+        match handle_message("db", "COMPACT user") {
+            Ok(Command::Compact(db, table)) => {
+                assert_eq!(db, "db");
+                assert_eq!(table, "user");
+            }
+            other => panic!("Expected Compact command but got {:?}", other),
+        }
+        assert!(handle_message("db", "COMPACT").is_err());
+        assert!(handle_message("db", "COMPACT user car").is_err());
+    }
+
+    #[test]
+    fn test_parse_reindex_command() {
+        match handle_message("db", "REINDEX user") {
+            Ok(Command::Reindex(db, table, column)) => {
+                assert_eq!(db, "db");
+                assert_eq!(table, "user");
+                assert_eq!(column, None);
+            }
+            other => panic!("Expected Reindex command but got {:?}", other),
+        }
+        match handle_message("db", "REINDEX user object_id") {
+            Ok(Command::Reindex(_, _, column)) => assert_eq!(column, Some("object_id".to_string())),
+            other => panic!("Expected Reindex command but got {:?}", other),
+        }
+        assert!(handle_message("db", "REINDEX").is_err());
+        assert!(handle_message("db", "REINDEX user name age").is_err());
+    }
+
+    #[test]
+    fn test_parse_transaction_commands() {
+        assert!(matches!(handle_message("db", "BEGIN"), Ok(Command::Begin(db)) if db == "db"));
+        assert!(matches!(
+            handle_message("db", " COMMIT "),
+            Ok(Command::Commit)
+        ));
+        assert!(matches!(
+            handle_message("db", "ROLLBACK"),
+            Ok(Command::Rollback)
+        ));
+        assert!(handle_message("db", "BEGIN user").is_err());
+    }
+
+    #[test]
+    fn test_parse_describe_command() {
+        match handle_message("db", "DESCRIBE") {
+            Ok(Command::Describe(db, table)) => {
+                assert_eq!(db, "db");
+                assert_eq!(table, None);
+            }
+            other => panic!("Expected Describe command but got {:?}", other),
+        }
+        match handle_message("db", "DESCRIBE user") {
+            Ok(Command::Describe(_, table)) => assert_eq!(table, Some("user".to_string())),
+            other => panic!("Expected Describe command but got {:?}", other),
+        }
+        assert!(handle_message("db", "DESCRIBE user car").is_err());
+    }
+
+    #[test]
+    fn test_parse_alter_command() {
+        let message = r#"ALTER user ADD age {"type": "Number", "indexed": true, "optional": true}"#;
+        match handle_message("db", message) {
+            Ok(Command::Alter(db, table, AlterOperation::Add(column, definition))) => {
+                assert_eq!(db, "db");
+                assert_eq!(table, "user");
+                assert_eq!(column, "age");
+                assert_eq!(definition.data_type, "Number");
+                assert!(definition.indexed);
+                assert!(definition.optional);
+            }
+            other => panic!("Expected Alter command but got {:?}", other),
+        }
+
+        let message = r#"ALTER user MODIFY last_name {"type": "String", "indexed": false, "optional": false}"#;
+        match handle_message("db", message) {
+            Ok(Command::Alter(_, _, AlterOperation::Modify(column, definition))) => {
+                assert_eq!(column, "last_name");
+                assert!(!definition.indexed);
+            }
+            other => panic!("Expected Alter command but got {:?}", other),
+        }
+
+        match handle_message("db", "ALTER user DROP age") {
+            Ok(Command::Alter(_, _, AlterOperation::Drop(column))) => assert_eq!(column, "age"),
+            other => panic!("Expected Alter command but got {:?}", other),
+        }
+
+        assert!(handle_message("db", "ALTER user RENAME age").is_err());
+        assert!(handle_message("db", "ALTER user ADD age").is_err());
+        assert!(handle_message("db", r#"ALTER user ADD age {"type": "Number"}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_drop_command() {
+        match handle_message("db", "DROP TABLE user") {
+            Ok(Command::Drop(DropTarget::Table(db, table))) => {
+                assert_eq!(db, "db");
+                assert_eq!(table, "user");
+            }
+            other => panic!("Expected Drop command but got {:?}", other),
+        }
+        match handle_message("", "DROP DATABASE xdr") {
+            Ok(Command::Drop(DropTarget::Database(db))) => assert_eq!(db, "xdr"),
+            other => panic!("Expected Drop command but got {:?}", other),
+        }
+        assert!(handle_message("db", "DROP user").is_err());
+        assert!(handle_message("db", "DROP TABLE user car").is_err());
+    }
+
+    #[test]
+    fn test_parse_select_projection() {
+        let message = "SELECT name, address.city FROM user WHERE age >= 30";
+        match parse_select("db", message) {
+            Ok(Command::Select(query)) => {
+                assert_eq!(query.table_name, "user");
+                assert_eq!(
+                    query.projection,
+                    Some(vec!["name".to_string(), "address.city".to_string()])
+                );
+                assert!(matches!(
+                    query.filter,
+                    Some(Condition::GreaterThanOrEqual(_, _))
+                ));
+            }
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+
+        match parse_select("db", "SELECT * FROM user WHERE age >= 30") {
+            Ok(Command::Select(query)) => {
+                assert_eq!(query.table_name, "user");
+                assert_eq!(query.projection, None);
+            }
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+
+        match parse_select("db", "SELECT user WHERE age >= 30") {
+            Ok(Command::Select(query)) => assert_eq!(query.projection, None),
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_select_order_limit() {
+        let message = "SELECT user WHERE age >= 30 ORDER BY address.city DESC LIMIT 10 OFFSET 20";
+        match parse_select("db", message) {
+            Ok(Command::Select(query)) => {
+                assert!(matches!(
+                    query.filter,
+                    Some(Condition::GreaterThanOrEqual(_, _))
+                ));
+                assert_eq!(
+                    query.order_by,
+                    Some(OrderBy {
+                        field: "address.city".to_string(),
+                        direction: SortDirection::Desc,
+                    })
+                );
+                assert_eq!(query.limit, Some(10));
+                assert_eq!(query.offset, Some(20));
+            }
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+
+        match parse_select("db", "SELECT user WHERE name = 'John' order by age limit 5") {
+            Ok(Command::Select(query)) => {
+                assert_eq!(query.order_by.unwrap().direction, SortDirection::Asc);
+                assert_eq!(query.limit, Some(5));
+                assert_eq!(query.offset, None);
+            }
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+
+        assert!(parse_select("db", "SELECT user WHERE age >= 30 LIMIT ten").is_err());
+        assert!(parse_select("db", "SELECT user WHERE age >= 30 OFFSET 1 LIMIT 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_select_without_where() {
+        match parse_select("db", "SELECT user") {
+            Ok(Command::Select(query)) => {
+                assert_eq!(query.table_name, "user");
+                assert!(query.filter.is_none());
+            }
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+
+        match parse_select("db", "SELECT name FROM user ORDER BY name LIMIT 2") {
+            Ok(Command::Select(query)) => {
+                assert_eq!(query.table_name, "user");
+                assert!(query.filter.is_none());
+                assert_eq!(query.projection, Some(vec!["name".to_string()]));
+                assert_eq!(query.limit, Some(2));
+            }
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+
+        assert!(parse_select("db", "SELECT user WHERE").is_err());
+        assert!(parse_select("db", "SELECT user name = 'John'").is_err());
+    }
+
+    #[test]
+    fn test_parse_select_aggregates() {
+        let message = "SELECT address.city, COUNT(*), sum(age), MAX( age ) FROM user WHERE age >= 30 GROUP BY address.city ORDER BY count DESC LIMIT 5";
+        match parse_select("db", message) {
+            Ok(Command::Select(query)) => {
+                assert!(query.is_aggregate());
+                assert_eq!(query.projection, None);
+                assert_eq!(query.group_by, Some("address.city".to_string()));
+                assert_eq!(
+                    query.aggregates,
+                    vec![
+                        Aggregate {
+                            function: AggregateFunction::Count,
+                            field: None,
+                        },
+                        Aggregate {
+                            function: AggregateFunction::Sum,
+                            field: Some("age".to_string()),
+                        },
+                        Aggregate {
+                            function: AggregateFunction::Max,
+                            field: Some("age".to_string()),
+                        },
+                    ]
+                );
+                assert_eq!(query.aggregates[1].name(), "sum(age)");
+                assert_eq!(query.order_by.unwrap().field, "count");
+                assert_eq!(query.limit, Some(5));
+            }
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+
+        match parse_select("db", "SELECT COUNT(*) FROM user") {
+            Ok(Command::Select(query)) => {
+                assert!(query.filter.is_none());
+                assert_eq!(query.aggregates[0].name(), "count");
+                assert_eq!(query.group_by, None);
+            }
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+
+        assert!(parse_select("db", "SELECT SUM(*) FROM user").is_err());
+        // Plain fields must be the group field
+        assert!(parse_select("db", "SELECT name, COUNT(*) FROM user").is_err());
+        assert!(parse_select("db", "SELECT name, COUNT(*) FROM user GROUP BY age").is_err());
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("-12 AND"), Ok((" AND", "-12".to_string())));
+        assert_eq!(parse_value("4.25)"), Ok((")", "4.25".to_string())));
+        assert_eq!(
+            parse_value("'John Smith, Jr.' OR"),
+            Ok((" OR", "John Smith, Jr.".to_string()))
+        );
+        assert_eq!(
+            parse_value("cf0aad38-3ea2"),
+            Ok(("", "cf0aad38-3ea2".to_string()))
+        );
+        // A number may not run into other characters
+        assert_eq!(parse_value("1.2.3"), Ok((".2.3", "1".to_string())));
+
+        match parse_select("db", "SELECT user WHERE balance > -10.5 AND name = 'a b'") {
+            Ok(Command::Select(query)) => match query.filter.unwrap() {
+                Condition::And(left, right) => {
+                    assert!(matches!(*left, Condition::GreaterThan(_, value) if value == "-10.5"));
+                    assert!(matches!(*right, Condition::Equal(_, value) if value == "a b"));
+                }
+                other => panic!("Expected And condition but got {:?}", other),
+            },
+            other => panic!("Expected Select command but got {:?}", other),
+        }
+        assert!(parse_select("db", "SELECT user WHERE balance > 1.2.3").is_err());
+    }
+
+    #[test]
+    fn test_project() {
+        let (_, _, data) = parse_json(
+            r#"{"id":"1","name":"John","address":{"city":"Colombo","zip":"10"},"age":30}"#,
+            "user",
+        )
+        .unwrap();
+        let projected = data.project(&[
+            "name".to_string(),
+            "address.city".to_string(),
+            "missing.field".to_string(),
+        ]);
+        assert_eq!(
+            projected.to_json(),
+            serde_json::json!({ "name": "John", "address": { "city": "Colombo" } })
+        );
+        assert_eq!(
+            data.get_path("address.zip"),
+            Some(&DataObject::String("10".to_string()))
+        );
+        assert_eq!(data.get_path("name.first"), None);
+    }
 }
@@ -0,0 +1,287 @@
+use std::{fmt::Display, io::SeekFrom, path::Path, sync::Arc};
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+
+/// Name of the write-ahead log file in the folder of a database
+pub const WAL_FILE: &str = "qtable.wal";
+
+/// Every entry of the log is prefixed with its length
+const ENTRY_HEADER_LENGTH: usize = std::mem::size_of::<u32>();
+
+#[derive(Debug)]
+pub enum WalError {
+    /// Error reading or writing the log file
+    File(std::io::Error),
+    /// Error serializing an entry
+    Serialize(String),
+}
+
+impl Display for WalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalError::File(e) => write!(f, "WAL File Error: {}", e),
+            WalError::Serialize(e) => write!(f, "WAL Serialize Error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for WalError {
+    fn from(e: std::io::Error) -> Self {
+        WalError::File(e)
+    }
+}
+
+/// Intent records how to undo a change to the data file of a table: records appended after
+/// `data_length` are cut off and the overwritten records get their previous bytes back.
+/// Index files are rebuilt from the data file once it is restored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Intent {
+    pub table: String,
+    /// Length of the data file before the change
+    pub data_length: u64,
+    /// Position and previous bytes of every record the change overwrites
+    pub overwrites: Vec<(u64, Vec<u8>)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum WalEntry {
+    Begin,
+    Intent(Intent),
+    Commit,
+}
+
+/// Wal is the write-ahead log of a database, shared by its tables.
+///
+/// A mutation begins a transaction and logs its intent before touching a data file, and commits
+/// once the data and index files are written. Transactions nest, only the outermost commit is
+/// written, so several mutations can be committed together. The log is emptied on commit, what
+/// it holds when the database is loaded belongs to a transaction that did not commit and is
+/// undone.
+#[derive(Clone)]
+pub struct Wal {
+    inner: Arc<Mutex<WalFile>>,
+}
+
+struct WalFile {
+    file: File,
+    /// Number of transactions begun and not yet committed or rolled back
+    depth: usize,
+    /// Intents of the open transaction
+    intents: Vec<Intent>,
+}
+
+impl Wal {
+    /// Open the log of the database in the folder, the file is created when it does not exist
+    pub async fn open(database_path: &Path) -> Result<Wal, WalError> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(database_path.join(WAL_FILE))
+            .await?;
+        Ok(Wal {
+            inner: Arc::new(Mutex::new(WalFile {
+                file,
+                depth: 0,
+                intents: vec![],
+            })),
+        })
+    }
+
+    /// Intents of the transaction left open in the log, in the order they were logged.
+    /// A torn entry at the end of the log was never followed by a change so it is ignored.
+    pub async fn pending(&self) -> Result<Vec<Intent>, WalError> {
+        let mut inner = self.inner.lock().await;
+        inner.file.seek(SeekFrom::Start(0)).await?;
+        let mut buffer = vec![];
+        inner.file.read_to_end(&mut buffer).await?;
+
+        let mut intents = vec![];
+        let mut offset = 0;
+        while offset + ENTRY_HEADER_LENGTH <= buffer.len() {
+            let mut header = [0; ENTRY_HEADER_LENGTH];
+            header.copy_from_slice(&buffer[offset..offset + ENTRY_HEADER_LENGTH]);
+            let start = offset + ENTRY_HEADER_LENGTH;
+            let end = start + u32::from_be_bytes(header) as usize;
+            if end > buffer.len() {
+                break;
+            }
+            match bincode::deserialize::<WalEntry>(&buffer[start..end]) {
+                Ok(WalEntry::Begin) => {}
+                // Intents of a transaction that failed to roll back stay before the next Begin
+                Ok(WalEntry::Commit) => intents.clear(),
+                Ok(WalEntry::Intent(intent)) => intents.push(intent),
+                Err(e) => {
+                    error!("Error deserializing WAL entry: {:?}", e);
+                    break;
+                }
+            }
+            offset = end;
+        }
+        Ok(intents)
+    }
+
+    /// Begin a transaction, or join the one already open
+    pub async fn begin(&self) -> Result<(), WalError> {
+        let mut inner = self.inner.lock().await;
+        if inner.depth == 0 {
+            inner.intents.clear();
+            inner.append(&WalEntry::Begin).await?;
+        }
+        inner.depth += 1;
+        Ok(())
+    }
+
+    /// Log the intent of a change, the change may only start once this returns
+    pub async fn log(&self, intent: Intent) -> Result<(), WalError> {
+        let mut inner = self.inner.lock().await;
+        inner.append(&WalEntry::Intent(intent.clone())).await?;
+        inner.file.sync_data().await?;
+        inner.intents.push(intent);
+        Ok(())
+    }
+
+    /// Commit the transaction, the log is emptied when the outermost transaction commits
+    pub async fn commit(&self) -> Result<(), WalError> {
+        let mut inner = self.inner.lock().await;
+        inner.depth = inner.depth.saturating_sub(1);
+        if inner.depth == 0 {
+            inner.append(&WalEntry::Commit).await?;
+            inner.clear().await?;
+            debug!("WAL committed");
+        }
+        Ok(())
+    }
+
+    /// End the transaction without committing it. When it is the outermost transaction the
+    /// intents to undo are returned, in the order they were logged, and the caller must undo
+    /// them before calling `clear`.
+    pub async fn rollback(&self) -> Option<Vec<Intent>> {
+        let mut inner = self.inner.lock().await;
+        inner.depth = inner.depth.saturating_sub(1);
+        if inner.depth > 0 {
+            return None;
+        }
+        Some(std::mem::take(&mut inner.intents))
+    }
+
+    /// Empty the log once its intents are undone
+    pub async fn clear(&self) -> Result<(), WalError> {
+        let mut inner = self.inner.lock().await;
+        inner.intents.clear();
+        inner.clear().await
+    }
+}
+
+impl WalFile {
+    async fn append(&mut self, entry: &WalEntry) -> Result<(), WalError> {
+        let data = bincode::serialize(entry).map_err(|e| WalError::Serialize(e.to_string()))?;
+        let length = (data.len() as u32).to_be_bytes();
+        self.file.seek(SeekFrom::End(0)).await?;
+        self.file
+            .write_all(&[length.to_vec(), data].concat())
+            .await?;
+        self.file.flush().await?;
+        Ok(())
+    }
+
+    async fn clear(&mut self) -> Result<(), WalError> {
+        self.file.set_len(0).await?;
+        self.file.seek(SeekFrom::Start(0)).await?;
+        self.file.sync_data().await?;
+        Ok(())
+    }
+}
+
+/// Undo the intent on the data file: restore the overwritten records and cut off what was
+/// appended
+pub async fn undo(data_file: &Path, intent: &Intent) -> Result<(), WalError> {
+    let mut file = File::options().write(true).open(data_file).await?;
+    for (position, data) in intent.overwrites.iter().rev() {
+        file.seek(SeekFrom::Start(*position)).await?;
+        file.write_all(data).await?;
+    }
+    file.set_len(intent.data_length).await?;
+    file.sync_data().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use tempfile::Builder;
+
+    use super::*;
+
+    fn intent(table: &str, data_length: u64) -> Intent {
+        Intent {
+            table: table.to_string(),
+            data_length,
+            overwrites: vec![(2, vec![9, 9])],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_intents() {
+        let dir = Builder::new()
+            .prefix("wal")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let wal = Wal::open(dir.path()).await.unwrap();
+
+        // A committed transaction leaves nothing to undo
+        wal.begin().await.unwrap();
+        wal.log(intent("user", 10)).await.unwrap();
+        wal.commit().await.unwrap();
+        assert!(wal.pending().await.unwrap().is_empty());
+
+        // Nested transactions commit with the outermost one
+        wal.begin().await.unwrap();
+        wal.log(intent("user", 10)).await.unwrap();
+        wal.begin().await.unwrap();
+        wal.log(intent("order", 20)).await.unwrap();
+        wal.commit().await.unwrap();
+        drop(wal);
+
+        // As if the server stopped before the commit
+        let wal = Wal::open(dir.path()).await.unwrap();
+        assert_eq!(
+            wal.pending().await.unwrap(),
+            vec![intent("user", 10), intent("order", 20)]
+        );
+
+        // A torn entry at the end is ignored
+        let mut file = File::options()
+            .append(true)
+            .open(dir.path().join(WAL_FILE))
+            .await
+            .unwrap();
+        file.write_all(&[0, 0, 0, 50, 1]).await.unwrap();
+        assert_eq!(wal.pending().await.unwrap().len(), 2);
+
+        wal.clear().await.unwrap();
+        assert!(wal.pending().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_undo() {
+        let dir = Builder::new()
+            .prefix("wal")
+            .tempdir()
+            .expect("Failed to create temp directory");
+        let data_file = dir.path().join("user.dat");
+        tokio::fs::write(&data_file, [1, 2, 3, 4, 5, 6])
+            .await
+            .unwrap();
+
+        undo(&data_file, &intent("user", 4)).await.unwrap();
+        assert_eq!(tokio::fs::read(&data_file).await.unwrap(), vec![1, 2, 9, 9]);
+    }
+}